@@ -0,0 +1,186 @@
+//!Client for the go-iod daemon that owns the modules of a controller,
+//!see [protocol] for the messages on the socket.
+pub mod protocol;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+use protocol::{
+    CycleStats, Inputs, ModuleInfo, OutputWrite, Reply, Request, RequestFrame, ServerMessage,
+    Value, PROTOCOL_VERSION,
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    ///The daemon closed the connection
+    Closed,
+    ///The daemon does not speak the version of this client
+    Version(u32),
+    ///The daemon could not handle the request
+    Daemon(String),
+    ///The daemon answered with a reply that does not belong to the request
+    UnexpectedReply(Reply),
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(err: serde_json::Error) -> Self {
+        ClientError::Json(err)
+    }
+}
+
+///Signals of a subscription that changed in an I/O cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub cycle: u64,
+    pub changes: BTreeMap<String, Option<Value>>,
+}
+
+///A connection to the daemon, requests are answered in order,
+///events that arrive while waiting for a reply are kept for [Client::next_event]
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    events: VecDeque<Event>,
+    ///A message that was only partly received when a read timed out
+    partial: Vec<u8>,
+}
+
+impl Client {
+    ///Connect to the daemon, usually on [DEFAULT_SOCKET](protocol::DEFAULT_SOCKET)
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let writer = UnixStream::connect(path)?;
+        let mut client = Client {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            next_id: 1,
+            events: VecDeque::new(),
+            partial: Vec::new(),
+        };
+        match client.request(Request::Hello {
+            version: PROTOCOL_VERSION,
+        })? {
+            Reply::Hello { version } if version == PROTOCOL_VERSION => Ok(client),
+            Reply::Hello { version } => Err(ClientError::Version(version)),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    pub fn read_inputs(&mut self) -> Result<Inputs, ClientError> {
+        match self.request(Request::ReadInputs)? {
+            Reply::Inputs(inputs) => Ok(inputs),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    ///Write outputs, they are all applied in the same I/O cycle or not at all
+    pub fn write_outputs(&mut self, outputs: Vec<OutputWrite>) -> Result<(), ClientError> {
+        match self.request(Request::WriteOutputs { outputs })? {
+            Reply::Written => Ok(()),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    pub fn modules(&mut self) -> Result<Vec<ModuleInfo>, ClientError> {
+        match self.request(Request::Modules)? {
+            Reply::Modules { modules } => Ok(modules),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    pub fn stats(&mut self) -> Result<CycleStats, ClientError> {
+        match self.request(Request::Stats)? {
+            Reply::Stats(stats) => Ok(stats),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    ///Get events when signals change, all signals when `signals` is empty,
+    ///replaces an earlier subscription of this connection
+    pub fn subscribe(&mut self, signals: Vec<String>) -> Result<(), ClientError> {
+        match self.request(Request::Subscribe { signals })? {
+            Reply::Subscribed => Ok(()),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    pub fn unsubscribe(&mut self) -> Result<(), ClientError> {
+        match self.request(Request::Unsubscribe)? {
+            Reply::Unsubscribed => Ok(()),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    ///Wait for the next event of the subscription, None when the timeout passes first
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, ClientError> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+        self.writer.set_read_timeout(timeout)?;
+        let message = self.receive();
+        self.writer.set_read_timeout(None)?;
+        match message {
+            Ok(ServerMessage::Event { cycle, changes }) => Ok(Some(Event { cycle, changes })),
+            Ok(ServerMessage::Response { reply, .. }) => Err(ClientError::UnexpectedReply(reply)),
+            Err(ClientError::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn request(&mut self, request: Request) -> Result<Reply, ClientError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut line = serde_json::to_vec(&RequestFrame { id, request })?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        loop {
+            match self.receive()? {
+                ServerMessage::Event { cycle, changes } => {
+                    self.events.push_back(Event { cycle, changes })
+                }
+                ServerMessage::Response {
+                    id: reply_id,
+                    reply,
+                } if reply_id == id => {
+                    return match reply {
+                        Reply::Error { message } => Err(ClientError::Daemon(message)),
+                        reply => Ok(reply),
+                    }
+                }
+                ServerMessage::Response { reply, .. } => {
+                    return Err(ClientError::UnexpectedReply(reply))
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<ServerMessage, ClientError> {
+        //what was read before an error stays in partial, so a timeout does not lose half a message
+        self.reader.read_until(b'\n', &mut self.partial)?;
+        if self.partial.last() != Some(&b'\n') {
+            return Err(ClientError::Closed);
+        }
+        let line = std::mem::take(&mut self.partial);
+        Ok(serde_json::from_slice(&line)?)
+    }
+}
@@ -0,0 +1,141 @@
+//!Messages exchanged with the I/O daemon, every message is one line of JSON.
+//!
+//!A client starts with [Request::Hello], the daemon answers with the version it speaks and
+//!closes the connection when it does not support the version of the client. Every reply carries
+//!the id of its request, events of a subscription are sent in between replies.
+//!```json
+//!{"id":1,"request":"hello","version":1}
+//!{"type":"response","id":1,"reply":"hello","version":1}
+//!{"id":2,"request":"write_outputs","outputs":[{"target":{"signal":"boom_valve"},"value":500}]}
+//!{"type":"response","id":2,"reply":"written"}
+//!{"type":"event","cycle":1520,"changes":{"boom_pressure":{"input":2350}}}
+//!```
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+///Where the daemon listens when no other socket is configured
+pub const DEFAULT_SOCKET: &str = "/run/go-iod.sock";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestFrame {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Hello {
+        version: u32,
+    },
+    ///The inputs of the last I/O cycle
+    ReadInputs,
+    ///Write outputs, either all of them are applied in the same cycle or none when one is invalid
+    WriteOutputs {
+        outputs: Vec<OutputWrite>,
+    },
+    ///Identity and communication statistics of the modules
+    Modules,
+    ///Timing statistics of the I/O cycle
+    Stats,
+    ///Get an event whenever one of the signals changes, all signals when the list is empty
+    Subscribe {
+        signals: Vec<String>,
+    },
+    Unsubscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Signal(String),
+    Channel { slot: u8, channel: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputWrite {
+    pub target: Target,
+    ///Duty cycle setpoint, a u16 for outputs and an i16 for bridges
+    pub value: i32,
+}
+
+///Reading of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Value {
+    Input(u32),
+    Output { current: i16, duty: u16 },
+    Bridge { current: i16, duty: i16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Response {
+        id: u64,
+        #[serde(flatten)]
+        reply: Reply,
+    },
+    ///Signals of a subscription that changed, None when the module did not answer in the cycle
+    Event {
+        cycle: u64,
+        changes: BTreeMap<String, Option<Value>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Hello { version: u32 },
+    Inputs(Inputs),
+    Written,
+    Modules { modules: Vec<ModuleInfo> },
+    Stats(CycleStats),
+    Subscribed,
+    Unsubscribed,
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inputs {
+    pub cycle: u64,
+    pub modules: Vec<ModuleInputs>,
+    ///Every named signal, None when its module did not answer in the cycle
+    pub signals: BTreeMap<String, Option<Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleInputs {
+    pub slot: u8,
+    ///The channels numbered from 1, empty when the exchange failed
+    pub channels: Vec<Value>,
+    ///Why the exchange with the module failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleInfo {
+    pub slot: u8,
+    pub kind: String,
+    pub id: [u8; 3],
+    pub hardware_version: u8,
+    pub firmware_version: [u8; 3],
+    pub exchanges: u32,
+    pub failures: u32,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CycleStats {
+    pub period_us: u64,
+    pub cycles: u64,
+    pub overruns: u64,
+    pub skipped: u64,
+    pub max_jitter_us: u64,
+    pub mean_jitter_us: u64,
+    pub max_execution_us: u64,
+}
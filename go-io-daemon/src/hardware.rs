@@ -0,0 +1,353 @@
+use std::{sync::RwLock, time::Duration};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+use go_io_client::protocol::{
+    CycleStats, Inputs, ModuleInfo, ModuleInputs, OutputWrite, Target, Value,
+};
+use go_mainboard::{
+    gpio::GpioInterrupt, process_image::ProcessImage, reset_line::ResetLine, ControllerSlot,
+};
+use go_module_base::ModuleDriver;
+use go_modules::any_module::ChannelValue;
+use linux_embedded_hal::SpidevDevice;
+
+use crate::server::Io;
+
+///The modules of the controller, run by the I/O task of a process image until [Hardware::shutdown]
+pub struct Hardware<
+    SPI = SpidevDevice,
+    ResetPin = ResetLine,
+    InterruptPin = GpioInterrupt,
+    Delay = linux_embedded_hal::Delay,
+> where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    ///None once the I/O is shut down
+    image: RwLock<Option<ProcessImage<SPI, ResetPin, InterruptPin, Delay>>>,
+    period: Duration,
+}
+
+fn value(value: ChannelValue) -> Value {
+    match value {
+        ChannelValue::Input(value) => Value::Input(value),
+        ChannelValue::Output { current, duty } => Value::Output { current, duty },
+        ChannelValue::Bridge { current, duty } => Value::Bridge { current, duty },
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> Hardware<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice + Send + 'static,
+    SPI::Error: Send + Sync,
+    ResetPin: OutputPin + Send + 'static,
+    ResetPin::Error: Send + Sync,
+    InterruptPin: InputPin + Send + 'static,
+    InterruptPin::Error: Send + Sync,
+    Delay: DelayNs + Send + 'static,
+{
+    pub fn new(image: ProcessImage<SPI, ResetPin, InterruptPin, Delay>, period: Duration) -> Self {
+        Hardware {
+            image: RwLock::new(Some(image)),
+            period,
+        }
+    }
+
+    ///Stop the I/O task and put every module in its safe state, requests are refused afterwards.
+    ///Returns the slots whose module could not be put in its safe state
+    pub fn shutdown(&self) -> Vec<(ControllerSlot, String)> {
+        let Some(image) = self.image.write().unwrap().take() else {
+            return Vec::new();
+        };
+        let (mut io, lost) = image.stop();
+        let mut failed: Vec<_> = io
+            .modules_mut()
+            .filter_map(|(slot, module)| {
+                module
+                    .safe_state()
+                    .err()
+                    .map(|err| (slot, format!("{err:?}")))
+            })
+            .collect();
+        failed.extend(
+            lost.into_iter()
+                .map(|slot| (slot, "the I/O task panicked".to_owned())),
+        );
+        failed
+    }
+
+    fn resolve(
+        image: &ProcessImage<SPI, ResetPin, InterruptPin, Delay>,
+        target: &Target,
+    ) -> Result<(ControllerSlot, u8), String> {
+        match target {
+            Target::Signal(name) => image
+                .signals()
+                .find(|(signal, _)| signal == name)
+                .map(|(_, signal)| (signal.slot, signal.channel))
+                .ok_or_else(|| format!("no signal named {name}")),
+            Target::Channel { slot, channel } => ControllerSlot::from_number(*slot)
+                .map(|slot| (slot, *channel))
+                .ok_or_else(|| format!("no slot {slot}")),
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> Io for Hardware<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice + Send + 'static,
+    SPI::Error: Send + Sync,
+    ResetPin: OutputPin + Send + 'static,
+    ResetPin::Error: Send + Sync,
+    InterruptPin: InputPin + Send + 'static,
+    InterruptPin::Error: Send + Sync,
+    Delay: DelayNs + Send + 'static,
+{
+    fn inputs(&self) -> Option<Inputs> {
+        let image = self.image.read().unwrap();
+        let image = image.as_ref()?;
+        let inputs = image.inputs()?;
+        let modules = image
+            .modules()
+            .iter()
+            .map(|module| {
+                let (channels, error) = match inputs.module(module.slot) {
+                    Some(Ok(values)) => (
+                        (1..=module.kind.channels())
+                            .filter_map(|channel| values.channel(channel).map(value))
+                            .collect(),
+                        None,
+                    ),
+                    Some(Err(err)) => (Vec::new(), Some(format!("{err:?}"))),
+                    None => (Vec::new(), Some("not exchanged".to_owned())),
+                };
+                ModuleInputs {
+                    slot: module.slot.number(),
+                    channels,
+                    error,
+                }
+            })
+            .collect();
+        let signals = image
+            .signals()
+            .map(|(name, _)| (name.to_owned(), inputs.signal(name).map(value)))
+            .collect();
+        Some(Inputs {
+            cycle: inputs.cycle(),
+            modules,
+            signals,
+        })
+    }
+
+    fn write(&self, outputs: &[OutputWrite]) -> Result<(), String> {
+        let image = self.image.read().unwrap();
+        let image = image.as_ref().ok_or("the I/O is shut down")?;
+        let targets = outputs
+            .iter()
+            .map(|output| Self::resolve(image, &output.target))
+            .collect::<Result<Vec<_>, _>>()?;
+        image.write(|image| {
+            let mut previous = Vec::new();
+            for ((slot, channel), output) in targets.into_iter().zip(outputs) {
+                let written = image
+                    .channel(slot, channel)
+                    .filter(|_| image.set_channel(slot, channel, output.value));
+                match written {
+                    Some(value) => previous.push((slot, channel, value)),
+                    None => {
+                        //undo what was written so far, so none of the writes are applied
+                        for (slot, channel, value) in previous.into_iter().rev() {
+                            image.set_channel(slot, channel, value);
+                        }
+                        return Err(format!(
+                            "{:?} is not an output that takes {}",
+                            output.target, output.value
+                        ));
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn modules(&self) -> Vec<ModuleInfo> {
+        let image = self.image.read().unwrap();
+        let Some(image) = image.as_ref() else {
+            return Vec::new();
+        };
+        let inputs = image.inputs();
+        image
+            .modules()
+            .iter()
+            .map(|module| {
+                let status = inputs
+                    .as_ref()
+                    .and_then(|inputs| inputs.status(module.slot))
+                    .unwrap_or_default();
+                ModuleInfo {
+                    slot: module.slot.number(),
+                    kind: format!("{:?}", module.kind),
+                    id: module.identity.id,
+                    hardware_version: module.identity.hardware_version,
+                    firmware_version: module.identity.firmware_version,
+                    exchanges: status.exchanges,
+                    failures: status.failures,
+                    consecutive_failures: status.consecutive_failures,
+                }
+            })
+            .collect()
+    }
+
+    fn stats(&self) -> CycleStats {
+        let stats = self
+            .image
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|image| image.stats())
+            .unwrap_or_default();
+        CycleStats {
+            period_us: self.period.as_micros() as u64,
+            cycles: stats.cycles,
+            overruns: stats.overruns,
+            skipped: stats.skipped,
+            max_jitter_us: stats.max_jitter.as_micros() as u64,
+            mean_jitter_us: stats.mean_jitter().as_micros() as u64,
+            max_execution_us: stats.max_execution.as_micros() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_mainboard::io_config::{ControllerIo, Signal};
+    use go_module_base::{
+        mock::{module_frame, NoDelay, ScriptedModule, ScriptedPin, ScriptedSpi},
+        ModuleCommunicationType,
+    };
+    use go_modules::any_module::{AnyModule, AnyModuleConfiguration, ModuleKind};
+
+    use super::*;
+
+    type ScriptedHardware = Hardware<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>;
+
+    ///Output modules in slot 1 and 2 with a signal on each, they answer every exchange
+    fn start() -> (ScriptedHardware, Vec<ScriptedSpi>) {
+        let mut modules = Vec::new();
+        let mut spis = Vec::new();
+        for slot in [ControllerSlot::Slot1, ControllerSlot::Slot2] {
+            let (module, spi) =
+                ScriptedModule::identifying_as(slot.number(), ModuleKind::Output6Channel.id());
+            for _ in 0..5000 {
+                spi.reply(module_frame(
+                    22,
+                    ModuleCommunicationType::Feedback,
+                    1,
+                    &[],
+                    44,
+                ));
+            }
+            let module = AnyModule::build(
+                module,
+                AnyModuleConfiguration::default_for(ModuleKind::Output6Channel),
+            )
+            .ok()
+            .unwrap();
+            modules.push((slot, module));
+            spis.push(spi);
+        }
+        let signals = [
+            ("left", ControllerSlot::Slot1),
+            ("right", ControllerSlot::Slot2),
+        ]
+        .into_iter()
+        .map(|(name, slot)| {
+            let signal = Signal {
+                slot,
+                channel: 1,
+                kind: ModuleKind::Output6Channel,
+            };
+            (name.to_owned(), signal)
+        })
+        .collect();
+        let period = Duration::from_millis(1);
+        let image = ProcessImage::start(ControllerIo::from_parts(modules, signals), period, None)
+            .ok()
+            .unwrap();
+        (Hardware::new(image, period), spis)
+    }
+
+    fn write(target: Target, value: i32) -> OutputWrite {
+        OutputWrite { target, value }
+    }
+
+    fn requested(hardware: &ScriptedHardware, signal: &str) -> Option<i32> {
+        let image = hardware.image.read().unwrap();
+        image.as_ref()?.write(|image| image.signal(signal))
+    }
+
+    #[test]
+    fn applies_all_writes_or_none() {
+        let (hardware, _) = start();
+        let left = || Target::Signal("left".to_owned());
+        let right = || Target::Signal("right".to_owned());
+        hardware
+            .write(&[write(left(), 100), write(right(), 200)])
+            .unwrap();
+
+        //the second write is out of range, the first is undone
+        assert!(hardware
+            .write(&[write(left(), 300), write(right(), -1)])
+            .is_err());
+        assert_eq!(requested(&hardware, "left"), Some(100));
+        assert_eq!(requested(&hardware, "right"), Some(200));
+
+        //a target that does not exist fails before anything is written
+        let missing = Target::Channel {
+            slot: 3,
+            channel: 1,
+        };
+        assert!(hardware
+            .write(&[write(left(), 300), write(missing, 1)])
+            .is_err());
+        assert_eq!(requested(&hardware, "left"), Some(100));
+        hardware.shutdown();
+    }
+
+    #[test]
+    fn shuts_down_into_the_safe_state() {
+        let (hardware, spis) = start();
+        hardware
+            .write(&[write(Target::Signal("left".to_owned()), 100)])
+            .unwrap();
+        //channel 1 is in bytes 6 and 7 of the setpoint frame
+        let duty = |spi: &ScriptedSpi| spi.written().last().map(|frame| frame[6..8].to_vec());
+        while duty(&spis[0]) != Some(vec![100, 0]) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(hardware.shutdown().is_empty());
+        assert_eq!(duty(&spis[0]), Some(vec![0, 0]));
+        let written: Vec<_> = spis.iter().map(|spi| spi.written().len()).collect();
+
+        //nothing is exchanged or accepted after the safe state
+        assert!(hardware
+            .write(&[write(Target::Signal("left".to_owned()), 100)])
+            .is_err());
+        assert!(hardware.inputs().is_none());
+        assert!(hardware.modules().is_empty());
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(
+            spis.iter()
+                .map(|spi| spi.written().len())
+                .collect::<Vec<_>>(),
+            written
+        );
+        assert!(hardware.shutdown().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+//!go-iod owns the modules of a controller and serves their I/O to other processes over a
+//!unix socket, see the go-io-client crate for the protocol.
+//!
+//!`go-iod [--socket PATH] [--period-ms MS] [--priority PRIO] IO_CONFIG`
+mod hardware;
+mod server;
+
+use std::{
+    fs,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use go_io_client::protocol::DEFAULT_SOCKET;
+use go_mainboard::{
+    controller::{Controller, DiscoverError},
+    io_config::{IoBuildError, IoConfig, IoConfigError},
+    process_image::ProcessImage,
+    scheduler::SchedulerError,
+};
+
+use crate::{hardware::Hardware, server::Server};
+
+struct Args {
+    config: PathBuf,
+    socket: PathBuf,
+    period: Duration,
+    priority: Option<i32>,
+}
+
+#[derive(Debug)]
+enum DaemonError {
+    Args(String),
+    Config(IoConfigError),
+    Discover(DiscoverError),
+    Build(IoBuildError),
+    Start(SchedulerError),
+    Socket(PathBuf, std::io::Error),
+    ///Another daemon is serving on the socket
+    InUse(PathBuf),
+}
+
+impl std::fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonError::Args(message) => write!(f, "{message}"),
+            DaemonError::Config(err) => write!(f, "could not load the I/O configuration: {err:?}"),
+            DaemonError::Discover(err) => write!(f, "could not discover the controller: {err:?}"),
+            DaemonError::Build(err) => write!(f, "could not set up the modules: {err:?}"),
+            DaemonError::Start(err) => write!(f, "could not start the I/O task: {err:?}"),
+            DaemonError::Socket(path, err) => write!(f, "socket {}: {err}", path.display()),
+            DaemonError::InUse(path) => {
+                write!(f, "another daemon is serving on {}", path.display())
+            }
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, DaemonError> {
+    let mut config = None;
+    let mut socket = PathBuf::from(DEFAULT_SOCKET);
+    let mut period = Duration::from_millis(10);
+    let mut priority = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| DaemonError::Args(format!("{name} needs a value")))
+        };
+        match arg.as_str() {
+            "--socket" => socket = value("--socket")?.into(),
+            "--period-ms" => {
+                let ms = value("--period-ms")?;
+                period = match ms.parse() {
+                    Ok(0) | Err(_) => {
+                        return Err(DaemonError::Args(format!("invalid period {ms}")))
+                    }
+                    Ok(ms) => Duration::from_millis(ms),
+                };
+            }
+            "--priority" => {
+                let prio = value("--priority")?;
+                priority = Some(
+                    prio.parse()
+                        .map_err(|_| DaemonError::Args(format!("invalid priority {prio}")))?,
+                );
+            }
+            _ if config.is_none() && !arg.starts_with("--") => config = Some(PathBuf::from(arg)),
+            _ => return Err(DaemonError::Args(format!("unexpected argument {arg}"))),
+        }
+    }
+    Ok(Args {
+        config: config.ok_or_else(|| DaemonError::Args("no I/O configuration given".to_owned()))?,
+        socket,
+        period,
+        priority,
+    })
+}
+
+fn run(args: Args) -> Result<(), DaemonError> {
+    //checked before the modules are touched, they belong to the running daemon
+    if UnixStream::connect(&args.socket).is_ok() {
+        return Err(DaemonError::InUse(args.socket));
+    }
+    //caught before the modules are set up, so they are always put back in their safe state
+    catch_shutdown();
+    let config = IoConfig::load(&args.config).map_err(DaemonError::Config)?;
+    let mut controller = Controller::discover().map_err(DaemonError::Discover)?;
+    let io = config.build(&mut controller).map_err(DaemonError::Build)?;
+    let image = ProcessImage::start(io, args.period, args.priority)
+        .map_err(|(err, _)| DaemonError::Start(err))?;
+    let hardware = Arc::new(Hardware::new(image, args.period));
+    let served = serve(&args, hardware.clone());
+    for (slot, err) in hardware.shutdown() {
+        eprintln!(
+            "go-iod: could not put the module in slot {} in its safe state: {err}",
+            slot.number()
+        );
+    }
+    served
+}
+
+///Serve the I/O on the socket until SIGTERM or SIGINT
+fn serve(args: &Args, hardware: Arc<Hardware>) -> Result<(), DaemonError> {
+    let server = Server::new(hardware);
+    //nobody answered on it, so it was left behind by an earlier run and would make bind fail
+    let _ = fs::remove_file(&args.socket);
+    let listener = UnixListener::bind(&args.socket)
+        .map_err(|err| DaemonError::Socket(args.socket.clone(), err))?;
+    let notifier = server.clone();
+    let period = args.period;
+    thread::spawn(move || notifier.notify(period));
+    let listening = server.clone();
+    thread::spawn(move || listening.serve(listener));
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    let _ = fs::remove_file(&args.socket);
+    Ok(())
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn shutdown(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+///Let SIGTERM and SIGINT end the daemon through [run] instead of killing it with the outputs driven
+fn catch_shutdown() {
+    let handler: extern "C" fn(libc::c_int) = shutdown;
+    //SAFETY: the handler only stores to an atomic, which is async signal safe
+    unsafe {
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("go-iod: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, DaemonError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn needs_a_period_of_at_least_1_ms() {
+        let parsed = args(&["--period-ms", "5", "io.toml"]).unwrap();
+        assert_eq!(parsed.period, Duration::from_millis(5));
+        assert_eq!(parsed.config, PathBuf::from("io.toml"));
+        assert!(matches!(
+            args(&["--period-ms", "0", "io.toml"]),
+            Err(DaemonError::Args(_))
+        ));
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use go_io_client::protocol::{
+    CycleStats, Inputs, ModuleInfo, OutputWrite, Reply, Request, RequestFrame, ServerMessage,
+    Value, PROTOCOL_VERSION,
+};
+
+///The I/O the daemon serves, the process image of the controller outside of tests
+pub trait Io: Send + Sync {
+    ///Inputs of the last cycle, None before the first cycle
+    fn inputs(&self) -> Option<Inputs>;
+    ///Apply all writes in the same cycle, or none of them when one is invalid
+    fn write(&self, outputs: &[OutputWrite]) -> Result<(), String>;
+    fn modules(&self) -> Vec<ModuleInfo>;
+    fn stats(&self) -> CycleStats;
+}
+
+///A subscriber that does not read its events for this long is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+struct Subscriber {
+    connection: u64,
+    writer: Arc<Mutex<UnixStream>>,
+    ///Empty for every signal
+    signals: Vec<String>,
+}
+
+impl Subscriber {
+    fn wants(&self, signal: &str) -> bool {
+        self.signals.is_empty() || self.signals.iter().any(|wanted| wanted == signal)
+    }
+}
+
+pub struct Server {
+    io: Arc<dyn Io>,
+    subscribers: Mutex<Vec<Subscriber>>,
+    next_connection: AtomicU64,
+}
+
+impl Server {
+    pub fn new(io: Arc<dyn Io>) -> Arc<Self> {
+        Arc::new(Server {
+            io,
+            subscribers: Mutex::new(Vec::new()),
+            next_connection: AtomicU64::new(1),
+        })
+    }
+
+    ///Serve every client that connects in a thread of its own, does not return
+    pub fn serve(self: &Arc<Self>, listener: UnixListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = self.clone();
+                    thread::spawn(move || server.handle(stream));
+                }
+                //for example out of file descriptors, connections that close make room again
+                Err(err) => {
+                    eprintln!("go-iod: could not accept a connection: {err}");
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+
+    ///Check for changed signals every `interval` and send them to the subscribers, does not return
+    pub fn notify(self: &Arc<Self>, interval: Duration) {
+        let mut last_cycle = None;
+        let mut last: BTreeMap<String, Option<Value>> = BTreeMap::new();
+        loop {
+            thread::sleep(interval);
+            let Some(inputs) = self.io.inputs() else {
+                continue;
+            };
+            if last_cycle == Some(inputs.cycle) {
+                continue;
+            }
+            last_cycle = Some(inputs.cycle);
+            let changes: BTreeMap<_, _> = inputs
+                .signals
+                .into_iter()
+                .filter(|(name, value)| last.get(name) != Some(value))
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+            last.extend(changes.clone());
+            //sent without holding the lock, so a slow subscriber does not hold up the others
+            let subscribers = self.subscribers.lock().unwrap().clone();
+            for subscriber in subscribers {
+                if send_event(&subscriber, inputs.cycle, &changes).is_err() {
+                    self.drop_subscriber(&subscriber);
+                }
+            }
+        }
+    }
+
+    fn handle(&self, stream: UnixStream) {
+        let connection = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let Ok(writer) = stream.try_clone() else {
+            return;
+        };
+        if writer.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+            return;
+        }
+        let writer = Arc::new(Mutex::new(writer));
+        let mut greeted = false;
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let frame: RequestFrame = match serde_json::from_str(&line) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    let reply = Reply::Error {
+                        message: format!("invalid request: {err}"),
+                    };
+                    if send(&writer, &ServerMessage::Response { id: 0, reply }).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let id = frame.id;
+            let (reply, close) = match (greeted, frame.request) {
+                (_, Request::Hello { version }) => {
+                    greeted = version == PROTOCOL_VERSION;
+                    let reply = Reply::Hello {
+                        version: PROTOCOL_VERSION,
+                    };
+                    (reply, !greeted)
+                }
+                (false, _) => {
+                    let reply = Reply::Error {
+                        message: "the first request has to be hello".to_owned(),
+                    };
+                    (reply, true)
+                }
+                (true, request) => (self.reply(connection, &writer, request), false),
+            };
+            let subscribed = reply == Reply::Subscribed;
+            if send(&writer, &ServerMessage::Response { id, reply }).is_err() || close {
+                break;
+            }
+            if subscribed && self.send_current(connection).is_err() {
+                break;
+            }
+        }
+        self.unsubscribe(connection);
+    }
+
+    fn reply(&self, connection: u64, writer: &Arc<Mutex<UnixStream>>, request: Request) -> Reply {
+        match request {
+            Request::Hello { .. } => unreachable!("hello is answered by the connection"),
+            Request::ReadInputs => match self.io.inputs() {
+                Some(inputs) => Reply::Inputs(inputs),
+                None => Reply::Error {
+                    message: "no I/O cycle has finished yet".to_owned(),
+                },
+            },
+            Request::WriteOutputs { outputs } => match self.io.write(&outputs) {
+                Ok(()) => Reply::Written,
+                Err(message) => Reply::Error { message },
+            },
+            Request::Modules => Reply::Modules {
+                modules: self.io.modules(),
+            },
+            Request::Stats => Reply::Stats(self.io.stats()),
+            Request::Subscribe { signals } => {
+                self.unsubscribe(connection);
+                self.subscribers.lock().unwrap().push(Subscriber {
+                    connection,
+                    writer: writer.clone(),
+                    signals,
+                });
+                Reply::Subscribed
+            }
+            Request::Unsubscribe => {
+                self.unsubscribe(connection);
+                Reply::Unsubscribed
+            }
+        }
+    }
+
+    ///A new subscriber starts with the current value of its signals
+    fn send_current(&self, connection: u64) -> io::Result<()> {
+        let Some(inputs) = self.io.inputs() else {
+            return Ok(());
+        };
+        let subscriber = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|subscriber| subscriber.connection == connection)
+            .cloned();
+        match subscriber {
+            Some(subscriber) => send_event(&subscriber, inputs.cycle, &inputs.signals),
+            None => Ok(()),
+        }
+    }
+
+    ///A write to the subscriber failed or timed out, part of a line may have been sent
+    ///so the connection is closed as well
+    fn drop_subscriber(&self, subscriber: &Subscriber) {
+        self.unsubscribe(subscriber.connection);
+        let _ = subscriber
+            .writer
+            .lock()
+            .unwrap()
+            .shutdown(std::net::Shutdown::Both);
+    }
+
+    fn unsubscribe(&self, connection: u64) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.connection != connection);
+    }
+}
+
+fn send_event(
+    subscriber: &Subscriber,
+    cycle: u64,
+    signals: &BTreeMap<String, Option<Value>>,
+) -> io::Result<()> {
+    let changes: BTreeMap<_, _> = signals
+        .iter()
+        .filter(|(name, _)| subscriber.wants(name))
+        .map(|(name, value)| (name.clone(), *value))
+        .collect();
+    if changes.is_empty() {
+        return Ok(());
+    }
+    send(&subscriber.writer, &ServerMessage::Event { cycle, changes })
+}
+
+fn send(writer: &Mutex<UnixStream>, message: &ServerMessage) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.lock().unwrap().write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use go_io_client::{protocol::Target, Client, ClientError};
+
+    use super::*;
+
+    ///One input signal that follows the last value written to the output signal
+    #[derive(Default)]
+    struct Loopback {
+        cycle: AtomicU64,
+        value: Mutex<i32>,
+    }
+
+    impl Io for Loopback {
+        fn inputs(&self) -> Option<Inputs> {
+            let value = *self.value.lock().unwrap() as u32;
+            Some(Inputs {
+                cycle: self.cycle.fetch_add(1, Ordering::Relaxed),
+                modules: Vec::new(),
+                signals: BTreeMap::from([("feedback".to_owned(), Some(Value::Input(value)))]),
+            })
+        }
+
+        fn write(&self, outputs: &[OutputWrite]) -> Result<(), String> {
+            for output in outputs {
+                if output.target != Target::Signal("valve".to_owned()) {
+                    return Err(format!("no output {:?}", output.target));
+                }
+            }
+            if let Some(output) = outputs.last() {
+                *self.value.lock().unwrap() = output.value;
+            }
+            Ok(())
+        }
+
+        fn modules(&self) -> Vec<ModuleInfo> {
+            Vec::new()
+        }
+
+        fn stats(&self) -> CycleStats {
+            CycleStats {
+                period_us: 10_000,
+                cycles: self.cycle.load(Ordering::Relaxed),
+                overruns: 0,
+                skipped: 0,
+                max_jitter_us: 0,
+                mean_jitter_us: 0,
+                max_execution_us: 0,
+            }
+        }
+    }
+
+    fn start() -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("go-iod.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = Server::new(Arc::new(Loopback::default()));
+        let notifier = server.clone();
+        thread::spawn(move || notifier.notify(Duration::from_millis(5)));
+        thread::spawn(move || server.serve(listener));
+        (dir, socket)
+    }
+
+    #[test]
+    fn serves_requests_and_subscriptions() {
+        let (_dir, socket) = start();
+        let mut client = Client::connect(&socket).unwrap();
+        assert_eq!(client.stats().unwrap().period_us, 10_000);
+
+        client.subscribe(vec!["feedback".to_owned()]).unwrap();
+        let event = client
+            .next_event(Some(Duration::from_secs(1)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.changes["feedback"], Some(Value::Input(0)));
+
+        client
+            .write_outputs(vec![OutputWrite {
+                target: Target::Signal("valve".to_owned()),
+                value: 42,
+            }])
+            .unwrap();
+        let inputs = client.read_inputs().unwrap();
+        assert_eq!(inputs.signals["feedback"], Some(Value::Input(42)));
+        let event = loop {
+            let event = client
+                .next_event(Some(Duration::from_secs(1)))
+                .unwrap()
+                .unwrap();
+            if event.changes["feedback"] == Some(Value::Input(42)) {
+                break event;
+            }
+        };
+        assert_eq!(event.changes.len(), 1);
+
+        let err = client
+            .write_outputs(vec![OutputWrite {
+                target: Target::Channel {
+                    slot: 9,
+                    channel: 1,
+                },
+                value: 1,
+            }])
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Daemon(_)));
+    }
+
+    #[test]
+    fn closes_connections_that_skip_hello() {
+        let (_dir, socket) = start();
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        stream
+            .write_all(b"{\"id\":1,\"request\":\"stats\"}\n")
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let reply: ServerMessage = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert!(matches!(
+            reply,
+            ServerMessage::Response {
+                id: 1,
+                reply: Reply::Error { .. }
+            }
+        ));
+        assert!(lines.next().is_none());
+    }
+}
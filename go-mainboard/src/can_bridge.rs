@@ -0,0 +1,1122 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use go_modules::any_module::{AnyModuleSetpoint, ChannelValue, ModuleKind};
+use serde::{Deserialize, Serialize};
+use socketcan::{
+    CanDataFrame, CanFrame, CanSocket, EmbeddedFrame, ExtendedId, Id, Socket, StandardId,
+};
+
+use crate::{io_config::Signal, process_image::ProcessImage, ControllerSlot};
+
+///Longest wait for a frame when nothing is due
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+///Frames the controller exchanges with a CAN master when it is used as remote I/O.
+///Transmitted frames publish inputs and output feedback every cycle, received frames set outputs.
+///```toml
+///interface = "can0"
+///
+///[[transmit]]
+///id = 0x181
+///cycle_ms = 20
+///fields = [
+///    { signal = "boom_pressure", start = 0, size = 4 },
+///    { slot = 4, channel = 1, start = 4, size = 2, quantity = "Current" },
+///]
+///
+///[[receive]]
+///id = 0x201
+///timeout_ms = 100
+///fields = [{ signal = "boom_valve", start = 0, size = 2, byte_order = "BigEndian" }]
+///```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanBridgeConfig {
+    ///SocketCAN interface, a `vcan` interface works for testing without a bus
+    pub interface: String,
+    #[serde(default)]
+    pub transmit: Vec<TransmitFrameConfig>,
+    #[serde(default)]
+    pub receive: Vec<ReceiveFrameConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransmitFrameConfig {
+    pub id: u32,
+    ///29 bit identifier instead of 11 bit
+    #[serde(default)]
+    pub extended: bool,
+    ///Data length, the end of the last field when not set
+    pub len: Option<u8>,
+    pub cycle_ms: u64,
+    pub fields: Vec<FieldConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiveFrameConfig {
+    pub id: u32,
+    #[serde(default)]
+    pub extended: bool,
+    ///Shortest data length that is accepted, the end of the last field when not set
+    pub len: Option<u8>,
+    ///The outputs of the frame are switched off when it was not received for this long
+    pub timeout_ms: u64,
+    pub fields: Vec<FieldConfig>,
+}
+
+///A value in the data of a frame
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldConfig {
+    #[serde(flatten)]
+    pub channel: ChannelConfig,
+    ///First byte of the field
+    pub start: u8,
+    ///Bytes of the field: 1, 2 or 4
+    pub size: u8,
+    #[serde(default)]
+    pub byte_order: ByteOrder,
+    ///Two's complement, bridge duty cycles and currents are signed
+    #[serde(default)]
+    pub signed: bool,
+    ///What is transmitted, received fields are always setpoints
+    #[serde(default)]
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ChannelConfig {
+    Signal { signal: String },
+    Channel { slot: u8, channel: u8 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ByteOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quantity {
+    ///The reading of an input, the duty cycle of an output
+    #[default]
+    Value,
+    ///Measured current of an output
+    Current,
+}
+
+#[derive(Debug)]
+pub enum CanConfigError {
+    Io(PathBuf, io::Error),
+    Toml(toml::de::Error),
+}
+
+///A frame that can't be mapped on the I/O
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CanMappingError {
+    ///Not a valid 11 or 29 bit identifier
+    InvalidId(u32),
+    ///More than one received frame has the identifier
+    DuplicateId(u32),
+    ///More than one received field sets the output
+    DuplicateOutput {
+        slot: u8,
+        channel: u8,
+    },
+    ///The cycle time or receive timeout of the frame is 0
+    ZeroTime(u32),
+    ///Longer than the 8 data bytes of a classic CAN frame
+    InvalidLength {
+        id: u32,
+        len: u8,
+    },
+    ///The size is not 1, 2 or 4 bytes, or the field is outside the data or overlaps another field
+    InvalidField {
+        id: u32,
+        start: u8,
+        size: u8,
+    },
+    UnknownSignal(String),
+    ///The slot is not run or its module does not have the channel
+    NoSuchChannel {
+        slot: u8,
+        channel: u8,
+    },
+    ///Received fields have to set an output
+    NotAnOutput {
+        slot: u8,
+        channel: u8,
+    },
+    ///Inputs do not measure a current
+    NoCurrent {
+        slot: u8,
+        channel: u8,
+    },
+}
+
+#[derive(Debug)]
+pub enum CanBridgeError {
+    ///Nothing was opened because the frames do not match the I/O
+    Mapping(Vec<CanMappingError>),
+    Open(String, io::Error),
+}
+
+///The CAN interface the bridge exchanges its frames on, implemented by [CanSocket]
+pub trait CanInterface {
+    ///Wait up to `timeout` for a frame, fails with [io::ErrorKind::TimedOut] when none arrived
+    fn read_frame_timeout(&mut self, timeout: Duration) -> io::Result<CanFrame>;
+    fn write_frame(&mut self, frame: &CanDataFrame) -> io::Result<()>;
+}
+
+impl CanInterface for CanSocket {
+    fn read_frame_timeout(&mut self, timeout: Duration) -> io::Result<CanFrame> {
+        Socket::read_frame_timeout(self, timeout)
+    }
+
+    fn write_frame(&mut self, frame: &CanDataFrame) -> io::Result<()> {
+        Socket::write_frame(self, frame)
+    }
+}
+
+///The module I/O the bridge maps to CAN frames, implemented by [ProcessImage]
+pub trait BridgeIo: Send + Sync {
+    ///Kind of the module run in a slot
+    fn module(&self, slot: ControllerSlot) -> Option<ModuleKind>;
+    fn signal(&self, name: &str) -> Option<Signal>;
+    ///Values of channels as read in the same cycle, None for channels without a value in it
+    fn read(&self, channels: &[(ControllerSlot, u8)]) -> Vec<Option<ChannelValue>>;
+    ///Request setpoints that are sent to the modules in the same cycle,
+    ///returns false when any of them is out of range for its output
+    fn write(&self, setpoints: &[(ControllerSlot, u8, i32)]) -> bool;
+}
+
+impl BridgeIo for ProcessImage {
+    fn module(&self, slot: ControllerSlot) -> Option<ModuleKind> {
+        self.modules()
+            .iter()
+            .find(|module| module.slot == slot)
+            .map(|module| module.kind)
+    }
+
+    fn signal(&self, name: &str) -> Option<Signal> {
+        self.signals()
+            .find(|(signal, _)| *signal == name)
+            .map(|(_, signal)| *signal)
+    }
+
+    fn read(&self, channels: &[(ControllerSlot, u8)]) -> Vec<Option<ChannelValue>> {
+        match self.inputs() {
+            Some(inputs) => channels
+                .iter()
+                .map(|(slot, channel)| inputs.channel(*slot, *channel))
+                .collect(),
+            None => vec![None; channels.len()],
+        }
+    }
+
+    fn write(&self, setpoints: &[(ControllerSlot, u8, i32)]) -> bool {
+        ProcessImage::write(self, |outputs| {
+            setpoints
+                .iter()
+                .filter(|(slot, channel, value)| !outputs.set_channel(*slot, *channel, *value))
+                .count()
+                == 0
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CanBridgeStats {
+    pub transmitted: u64,
+    ///Transmit cycles skipped because a module of the frame failed its exchange
+    pub incomplete: u64,
+    ///Frames the socket had no room for, for example because nobody acknowledges them
+    pub dropped: u64,
+    pub received: u64,
+    ///Received frames with a setpoint out of range, the other setpoints of the frame are applied
+    ///but the frame does not count as received for its timeout
+    pub rejected: u64,
+    ///Received frames that were shorter than their fields
+    pub too_short: u64,
+    pub timeouts: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    slot: ControllerSlot,
+    channel: u8,
+    start: usize,
+    size: usize,
+    byte_order: ByteOrder,
+    signed: bool,
+    quantity: Quantity,
+}
+
+impl Field {
+    fn range(&self) -> (i64, i64) {
+        let bits = self.size as u32 * 8;
+        match self.signed {
+            true => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            false => (0, (1 << bits) - 1),
+        }
+    }
+
+    ///Write a value into the data, clamped to what fits in the field
+    fn encode(&self, value: i64, data: &mut [u8]) {
+        let (min, max) = self.range();
+        let bytes = (value.clamp(min, max) as u64).to_le_bytes();
+        let field = &mut data[self.start..self.start + self.size];
+        field.copy_from_slice(&bytes[..self.size]);
+        if self.byte_order == ByteOrder::BigEndian {
+            field.reverse();
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> i64 {
+        let mut bytes = [0; 8];
+        bytes[..self.size].copy_from_slice(&data[self.start..self.start + self.size]);
+        if self.byte_order == ByteOrder::BigEndian {
+            bytes[..self.size].reverse();
+        }
+        let value = u64::from_le_bytes(bytes);
+        match self.signed {
+            true => {
+                let unused = 64 - self.size as u32 * 8;
+                ((value << unused) as i64) >> unused
+            }
+            false => value as i64,
+        }
+    }
+
+    fn value(&self, value: ChannelValue) -> Option<i64> {
+        match (self.quantity, value) {
+            (Quantity::Value, ChannelValue::Input(value)) => Some(value.into()),
+            (Quantity::Value, ChannelValue::Output { duty, .. }) => Some(duty.into()),
+            (Quantity::Value, ChannelValue::Bridge { duty, .. }) => Some(duty.into()),
+            (Quantity::Current, ChannelValue::Input(_)) => None,
+            (Quantity::Current, ChannelValue::Output { current, .. }) => Some(current.into()),
+            (Quantity::Current, ChannelValue::Bridge { current, .. }) => Some(current.into()),
+        }
+    }
+}
+
+struct TransmitFrame {
+    id: Id,
+    len: usize,
+    cycle: Duration,
+    next: Instant,
+    fields: Vec<Field>,
+}
+
+struct ReceiveFrame {
+    id: Id,
+    len: usize,
+    timeout: Duration,
+    ///When the frame was last received, None while its outputs are in the safe state
+    last: Option<Instant>,
+    fields: Vec<Field>,
+}
+
+impl ReceiveFrame {
+    fn safe_state(&self) -> Vec<(ControllerSlot, u8, i32)> {
+        self.fields
+            .iter()
+            .map(|field| (field.slot, field.channel, 0))
+            .collect()
+    }
+}
+
+impl CanBridgeConfig {
+    pub fn from_toml(toml: &str) -> Result<Self, CanConfigError> {
+        toml::from_str(toml).map_err(CanConfigError::Toml)
+    }
+
+    ///Load the configuration from a TOML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CanConfigError> {
+        let path = path.as_ref();
+        let toml =
+            fs::read_to_string(path).map_err(|err| CanConfigError::Io(path.to_path_buf(), err))?;
+        Self::from_toml(&toml)
+    }
+
+    ///Check every frame against the I/O, all errors are reported at once
+    fn map(
+        &self,
+        io: &dyn BridgeIo,
+    ) -> Result<(Vec<TransmitFrame>, Vec<ReceiveFrame>), Vec<CanMappingError>> {
+        let mut errors = Vec::new();
+        let now = Instant::now();
+        let transmit = self
+            .transmit
+            .iter()
+            .map(|frame| TransmitFrame {
+                id: frame_id(frame.id, frame.extended, &mut errors),
+                len: layout(frame.id, frame.len, &frame.fields, &mut errors),
+                cycle: Duration::from_millis(frame.cycle_ms),
+                next: now,
+                fields: fields(io, &frame.fields, false, &mut errors),
+            })
+            .collect();
+        for (id, time) in self
+            .transmit
+            .iter()
+            .map(|frame| (frame.id, frame.cycle_ms))
+            .chain(
+                self.receive
+                    .iter()
+                    .map(|frame| (frame.id, frame.timeout_ms)),
+            )
+        {
+            if time == 0 {
+                errors.push(CanMappingError::ZeroTime(id));
+            }
+        }
+        let receive: Vec<_> = self
+            .receive
+            .iter()
+            .map(|frame| ReceiveFrame {
+                id: frame_id(frame.id, frame.extended, &mut errors),
+                len: layout(frame.id, frame.len, &frame.fields, &mut errors),
+                timeout: Duration::from_millis(frame.timeout_ms),
+                last: None,
+                fields: fields(io, &frame.fields, true, &mut errors),
+            })
+            .collect();
+        let mut outputs = Vec::new();
+        for field in receive.iter().flat_map(|frame| &frame.fields) {
+            if outputs.contains(&(field.slot, field.channel)) {
+                errors.push(CanMappingError::DuplicateOutput {
+                    slot: field.slot.number(),
+                    channel: field.channel,
+                });
+            }
+            outputs.push((field.slot, field.channel));
+        }
+        //invalid identifiers are already reported, they are not a duplicate of each other
+        let ids: Vec<_> = self
+            .receive
+            .iter()
+            .map(|frame| valid_id(frame.id, frame.extended))
+            .collect();
+        for (index, id) in ids.iter().enumerate() {
+            if id.is_some() && ids[..index].contains(id) {
+                errors.push(CanMappingError::DuplicateId(self.receive[index].id));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok((transmit, receive)),
+            false => Err(errors),
+        }
+    }
+}
+
+fn valid_id(id: u32, extended: bool) -> Option<Id> {
+    match extended {
+        true => ExtendedId::new(id).map(Id::Extended),
+        false => u16::try_from(id)
+            .ok()
+            .and_then(StandardId::new)
+            .map(Id::Standard),
+    }
+}
+
+fn frame_id(id: u32, extended: bool, errors: &mut Vec<CanMappingError>) -> Id {
+    valid_id(id, extended).unwrap_or_else(|| {
+        errors.push(CanMappingError::InvalidId(id));
+        Id::Standard(StandardId::ZERO)
+    })
+}
+
+///Data length of a frame, checking that its fields fit in it without overlapping
+fn layout(
+    id: u32,
+    len: Option<u8>,
+    fields: &[FieldConfig],
+    errors: &mut Vec<CanMappingError>,
+) -> usize {
+    let end = fields
+        .iter()
+        .map(|field| field.start.saturating_add(field.size))
+        .max()
+        .unwrap_or(0);
+    let len = len.unwrap_or(end);
+    if len > 8 {
+        errors.push(CanMappingError::InvalidLength { id, len });
+    }
+    let mut used = 0u16;
+    for field in fields {
+        let bytes = match field.size {
+            1 | 2 | 4 if field.start.saturating_add(field.size) <= len.min(8) => {
+                ((1u16 << field.size) - 1) << field.start
+            }
+            _ => 0,
+        };
+        if bytes == 0 || used & bytes != 0 {
+            errors.push(CanMappingError::InvalidField {
+                id,
+                start: field.start,
+                size: field.size,
+            });
+        }
+        used |= bytes;
+    }
+    len.min(8).into()
+}
+
+fn fields(
+    io: &dyn BridgeIo,
+    fields: &[FieldConfig],
+    setpoints: bool,
+    errors: &mut Vec<CanMappingError>,
+) -> Vec<Field> {
+    fields
+        .iter()
+        .filter_map(|field| {
+            let (slot, channel, kind) = match &field.channel {
+                ChannelConfig::Signal { signal } => match io.signal(signal) {
+                    Some(found) => (found.slot, found.channel, Some(found.kind)),
+                    None => {
+                        errors.push(CanMappingError::UnknownSignal(signal.clone()));
+                        return None;
+                    }
+                },
+                ChannelConfig::Channel { slot, channel } => {
+                    let kind = ControllerSlot::from_number(*slot).and_then(|slot| io.module(slot));
+                    match kind {
+                        Some(kind) if (1..=kind.channels()).contains(channel) => (
+                            ControllerSlot::from_number(*slot).unwrap(),
+                            *channel,
+                            Some(kind),
+                        ),
+                        _ => {
+                            errors.push(CanMappingError::NoSuchChannel {
+                                slot: *slot,
+                                channel: *channel,
+                            });
+                            return None;
+                        }
+                    }
+                }
+            };
+            let output =
+                kind.is_some_and(|kind| AnyModuleSetpoint::off(kind).channel(channel).is_some());
+            let number = slot.number();
+            if setpoints && !output {
+                errors.push(CanMappingError::NotAnOutput {
+                    slot: number,
+                    channel,
+                });
+            } else if !setpoints && field.quantity == Quantity::Current && !output {
+                errors.push(CanMappingError::NoCurrent {
+                    slot: number,
+                    channel,
+                });
+            }
+            Some(Field {
+                slot,
+                channel,
+                start: field.start.into(),
+                size: field.size.into(),
+                byte_order: field.byte_order,
+                signed: field.signed,
+                quantity: field.quantity,
+            })
+        })
+        .collect()
+}
+
+///Remote I/O over SocketCAN: publishes inputs and output feedback in cyclic frames and sets
+///outputs from received frames. The outputs of a received frame fall back to the safe state,
+///switched off, when the frame times out and when the bridge is dropped.
+pub struct CanBridge<Interface: CanInterface = CanSocket> {
+    io: Arc<dyn BridgeIo>,
+    socket: Interface,
+    transmit: Vec<TransmitFrame>,
+    receive: Vec<ReceiveFrame>,
+    stats: CanBridgeStats,
+}
+
+impl CanBridge {
+    ///Map the frames on the I/O and open the interface, the outputs stay in the safe state
+    ///until their frame is first received
+    pub fn open(config: &CanBridgeConfig, io: Arc<dyn BridgeIo>) -> Result<Self, CanBridgeError> {
+        let frames = config.map(io.as_ref()).map_err(CanBridgeError::Mapping)?;
+        let socket = CanSocket::open(&config.interface)
+            .map_err(|err| CanBridgeError::Open(config.interface.clone(), err))?;
+        Ok(Self::new(io, socket, frames))
+    }
+}
+
+impl<Interface: CanInterface> CanBridge<Interface> {
+    ///Map the frames on the I/O and exchange them on an interface that is already open,
+    ///`interface` in the configuration is not used
+    pub fn with_interface(
+        config: &CanBridgeConfig,
+        io: Arc<dyn BridgeIo>,
+        socket: Interface,
+    ) -> Result<Self, CanBridgeError> {
+        let frames = config.map(io.as_ref()).map_err(CanBridgeError::Mapping)?;
+        Ok(Self::new(io, socket, frames))
+    }
+
+    fn new(
+        io: Arc<dyn BridgeIo>,
+        socket: Interface,
+        (transmit, receive): (Vec<TransmitFrame>, Vec<ReceiveFrame>),
+    ) -> Self {
+        let mut bridge = CanBridge {
+            io,
+            socket,
+            transmit,
+            receive,
+            stats: CanBridgeStats::default(),
+        };
+        bridge.safe_state();
+        bridge
+    }
+
+    pub fn stats(&self) -> CanBridgeStats {
+        self.stats
+    }
+
+    ///Whether the frame with this identifier was received within its timeout
+    pub fn is_receiving(&self, id: u32) -> bool {
+        self.receive
+            .iter()
+            .any(|frame| raw_id(frame.id) == id && frame.last.is_some())
+    }
+
+    ///Wait for a frame or until a frame has to be transmitted or times out, and handle that
+    pub fn poll(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let deadline = self
+            .transmit
+            .iter()
+            .map(|frame| frame.next)
+            .chain(
+                self.receive
+                    .iter()
+                    .filter_map(|frame| Some(frame.last? + frame.timeout)),
+            )
+            .min()
+            .unwrap_or(now + IDLE_WAIT);
+        let received = loop {
+            match self
+                .socket
+                .read_frame_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                //a signal ended the wait early, wait for the rest of it
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                received => break received,
+            }
+        };
+        match received {
+            Ok(CanFrame::Data(frame)) => self.received(&frame),
+            //remote and error frames carry no setpoints
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+        let now = Instant::now();
+        self.time_out(now);
+        self.transmit_due(now)
+    }
+
+    ///Poll until the interface fails, the outputs are put in the safe state before returning
+    pub fn run(&mut self) -> io::Error {
+        loop {
+            if let Err(err) = self.poll() {
+                self.safe_state();
+                return err;
+            }
+        }
+    }
+
+    ///Switch off the outputs of all received frames until they are received again
+    pub fn safe_state(&mut self) {
+        let setpoints: Vec<_> = self
+            .receive
+            .iter_mut()
+            .flat_map(|frame| {
+                frame.last = None;
+                frame.safe_state()
+            })
+            .collect();
+        self.io.write(&setpoints);
+    }
+
+    fn received(&mut self, data_frame: &CanDataFrame) {
+        let Some(frame) = self
+            .receive
+            .iter_mut()
+            .find(|frame| frame.id == data_frame.id())
+        else {
+            return;
+        };
+        let data = data_frame.data();
+        if data.len() < frame.len {
+            self.stats.too_short += 1;
+            return;
+        }
+        let setpoints: Vec<_> = frame
+            .fields
+            .iter()
+            .map(|field| {
+                let value = field.decode(data).clamp(i32::MIN.into(), i32::MAX.into());
+                (field.slot, field.channel, value as i32)
+            })
+            .collect();
+        self.stats.received += 1;
+        if self.io.write(&setpoints) {
+            frame.last = Some(Instant::now());
+            return;
+        }
+        //a rejected frame does not keep the outputs alive, the ones it did set are switched off
+        //again when the frame is not received within the timeout, right away when it never was
+        self.stats.rejected += 1;
+        if frame.last.is_none() {
+            self.io.write(&frame.safe_state());
+        }
+    }
+
+    fn time_out(&mut self, now: Instant) {
+        for frame in &mut self.receive {
+            if frame.last.is_some_and(|last| now >= last + frame.timeout) {
+                frame.last = None;
+                self.stats.timeouts += 1;
+                self.io.write(&frame.safe_state());
+            }
+        }
+    }
+
+    fn transmit_due(&mut self, now: Instant) -> io::Result<()> {
+        for frame in &mut self.transmit {
+            if now < frame.next {
+                continue;
+            }
+            frame.next += frame.cycle;
+            if frame.next <= now {
+                //fell behind, start the cycle over instead of sending a burst
+                frame.next = now + frame.cycle;
+            }
+            let channels: Vec<_> = frame
+                .fields
+                .iter()
+                .map(|field| (field.slot, field.channel))
+                .collect();
+            let values: Option<Vec<_>> = self
+                .io
+                .read(&channels)
+                .into_iter()
+                .zip(&frame.fields)
+                .map(|(value, field)| field.value(value?))
+                .collect();
+            let Some(values) = values else {
+                self.stats.incomplete += 1;
+                continue;
+            };
+            let mut data = [0; 8];
+            for (field, value) in frame.fields.iter().zip(values) {
+                field.encode(value, &mut data);
+            }
+            //the length was checked when the frame was mapped
+            let data_frame = CanDataFrame::new(frame.id, &data[..frame.len]).unwrap();
+            let written = loop {
+                match self.socket.write_frame(&data_frame) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    written => break written,
+                }
+            };
+            match written {
+                Ok(()) => self.stats.transmitted += 1,
+                Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => self.stats.dropped += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Interface: CanInterface> Drop for CanBridge<Interface> {
+    fn drop(&mut self) {
+        self.safe_state();
+    }
+}
+
+fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw().into(),
+        Id::Extended(id) => id.as_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        sync::Mutex,
+        thread,
+    };
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+interface = "vcan0"
+
+[[transmit]]
+id = 0x181
+cycle_ms = 10
+fields = [
+    { signal = "pressure", start = 0, size = 4 },
+    { slot = 2, channel = 1, start = 4, size = 2, quantity = "Current", byte_order = "BigEndian" },
+]
+
+[[receive]]
+id = 0x201
+timeout_ms = 50
+fields = [{ signal = "valve", start = 0, size = 2 }]
+"#;
+
+    ///An input module in slot 1 and an output module in slot 2, setpoints above 10000 are out of range
+    #[derive(Default)]
+    struct FakeIo {
+        outputs: Mutex<BTreeMap<(u8, u8), i32>>,
+    }
+
+    impl BridgeIo for FakeIo {
+        fn module(&self, slot: ControllerSlot) -> Option<ModuleKind> {
+            match slot {
+                ControllerSlot::Slot1 => Some(ModuleKind::Input6Channel),
+                ControllerSlot::Slot2 => Some(ModuleKind::Output6Channel),
+                _ => None,
+            }
+        }
+
+        fn signal(&self, name: &str) -> Option<Signal> {
+            let (slot, channel) = match name {
+                "pressure" => (ControllerSlot::Slot1, 1),
+                "valve" => (ControllerSlot::Slot2, 1),
+                _ => return None,
+            };
+            Some(Signal {
+                slot,
+                channel,
+                kind: self.module(slot)?,
+            })
+        }
+
+        fn read(&self, channels: &[(ControllerSlot, u8)]) -> Vec<Option<ChannelValue>> {
+            let outputs = self.outputs.lock().unwrap();
+            channels
+                .iter()
+                .map(|(slot, channel)| match slot {
+                    ControllerSlot::Slot1 => Some(ChannelValue::Input(5000 + *channel as u32)),
+                    _ => Some(ChannelValue::Output {
+                        current: 1500,
+                        duty: *outputs.get(&(slot.number(), *channel)).unwrap_or(&0) as u16,
+                    }),
+                })
+                .collect()
+        }
+
+        fn write(&self, setpoints: &[(ControllerSlot, u8, i32)]) -> bool {
+            let mut outputs = self.outputs.lock().unwrap();
+            let mut accepted = true;
+            for (slot, channel, value) in setpoints {
+                match *value > 10_000 {
+                    true => accepted = false,
+                    false => _ = outputs.insert((slot.number(), *channel), *value),
+                }
+            }
+            accepted
+        }
+    }
+
+    ///A CAN bus with a master that sends the queued frames
+    #[derive(Clone, Default)]
+    struct FakeCan {
+        incoming: Arc<Mutex<VecDeque<CanFrame>>>,
+        sent: Arc<Mutex<Vec<CanDataFrame>>>,
+    }
+
+    impl FakeCan {
+        fn send(&self, id: u16, data: &[u8]) {
+            let frame = CanDataFrame::new(StandardId::new(id).unwrap(), data).unwrap();
+            self.incoming
+                .lock()
+                .unwrap()
+                .push_back(CanFrame::Data(frame));
+        }
+    }
+
+    impl CanInterface for FakeCan {
+        fn read_frame_timeout(&mut self, timeout: Duration) -> io::Result<CanFrame> {
+            if let Some(frame) = self.incoming.lock().unwrap().pop_front() {
+                return Ok(frame);
+            }
+            thread::sleep(timeout);
+            Err(io::ErrorKind::TimedOut.into())
+        }
+
+        fn write_frame(&mut self, frame: &CanDataFrame) -> io::Result<()> {
+            self.sent.lock().unwrap().push(*frame);
+            Ok(())
+        }
+    }
+
+    fn bridge(config: &CanBridgeConfig) -> (CanBridge<FakeCan>, Arc<FakeIo>, FakeCan) {
+        let io = Arc::new(FakeIo::default());
+        let can = FakeCan::default();
+        let bridge = CanBridge::with_interface(config, io.clone(), can.clone()).unwrap();
+        (bridge, io, can)
+    }
+
+    fn field(start: usize, size: usize, byte_order: ByteOrder, signed: bool) -> Field {
+        Field {
+            slot: ControllerSlot::Slot1,
+            channel: 1,
+            start,
+            size,
+            byte_order,
+            signed,
+            quantity: Quantity::Value,
+        }
+    }
+
+    #[test]
+    fn encodes_and_decodes_fields() {
+        let mut data = [0; 8];
+        let little = field(0, 2, ByteOrder::LittleEndian, false);
+        let big = field(2, 2, ByteOrder::BigEndian, true);
+        let wide = field(4, 4, ByteOrder::LittleEndian, true);
+        little.encode(0x1234, &mut data);
+        big.encode(-2, &mut data);
+        wide.encode(-100_000, &mut data);
+        assert_eq!(data, [0x34, 0x12, 0xff, 0xfe, 0x60, 0x79, 0xfe, 0xff]);
+        assert_eq!(little.decode(&data), 0x1234);
+        assert_eq!(big.decode(&data), -2);
+        assert_eq!(wide.decode(&data), -100_000);
+
+        //values that do not fit are clamped
+        little.encode(-1, &mut data);
+        big.encode(40_000, &mut data);
+        assert_eq!(data[..4], [0, 0, 0x7f, 0xff]);
+    }
+
+    #[test]
+    fn maps_frames_on_the_io() {
+        let config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        let (transmit, receive) = config.map(&FakeIo::default()).unwrap();
+        assert_eq!(transmit[0].len, 6);
+        assert_eq!(transmit[0].fields[1].slot, ControllerSlot::Slot2);
+        assert_eq!(receive[0].id, Id::Standard(StandardId::new(0x201).unwrap()));
+        assert_eq!(receive[0].fields[0].channel, 1);
+    }
+
+    #[test]
+    fn reports_every_mapping_error() {
+        let mut config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        config.transmit[0].id = 0x800;
+        config.transmit[0].fields[1].channel = ChannelConfig::Channel {
+            slot: 1,
+            channel: 1,
+        };
+        config.transmit[0].fields[1].start = 2;
+        config.receive[0].fields.push(FieldConfig {
+            channel: ChannelConfig::Signal {
+                signal: "pressure".to_owned(),
+            },
+            start: 2,
+            size: 3,
+            byte_order: ByteOrder::LittleEndian,
+            signed: false,
+            quantity: Quantity::Value,
+        });
+        config.receive.push(config.receive[0].clone());
+        config.receive[1].fields[0].channel = ChannelConfig::Signal {
+            signal: "missing".to_owned(),
+        };
+        let Err(errors) = config.map(&FakeIo::default()) else {
+            panic!("frames were mapped");
+        };
+        assert_eq!(
+            errors,
+            [
+                CanMappingError::InvalidId(0x800),
+                CanMappingError::InvalidField {
+                    id: 0x800,
+                    start: 2,
+                    size: 2
+                },
+                CanMappingError::NoCurrent {
+                    slot: 1,
+                    channel: 1
+                },
+                CanMappingError::InvalidField {
+                    id: 0x201,
+                    start: 2,
+                    size: 3
+                },
+                CanMappingError::NotAnOutput {
+                    slot: 1,
+                    channel: 1
+                },
+                CanMappingError::InvalidField {
+                    id: 0x201,
+                    start: 2,
+                    size: 3
+                },
+                CanMappingError::UnknownSignal("missing".to_owned()),
+                CanMappingError::NotAnOutput {
+                    slot: 1,
+                    channel: 1
+                },
+                CanMappingError::DuplicateOutput {
+                    slot: 1,
+                    channel: 1
+                },
+                CanMappingError::DuplicateId(0x201),
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_an_output_from_one_field_only() {
+        let mut config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        config.receive.push(config.receive[0].clone());
+        config.receive[1].id = 0x202;
+        let Err(errors) = config.map(&FakeIo::default()) else {
+            panic!("frames were mapped");
+        };
+        assert_eq!(
+            errors,
+            [CanMappingError::DuplicateOutput {
+                slot: 2,
+                channel: 1
+            }]
+        );
+
+        //invalid identifiers are reported once each and not as duplicates of each other
+        config.receive[0].id = 0x800;
+        config.receive[1].id = 0x900;
+        config.receive[1].fields[0].channel = ChannelConfig::Channel {
+            slot: 2,
+            channel: 2,
+        };
+        let Err(errors) = config.map(&FakeIo::default()) else {
+            panic!("frames were mapped");
+        };
+        assert_eq!(
+            errors,
+            [
+                CanMappingError::InvalidId(0x800),
+                CanMappingError::InvalidId(0x900)
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_safe_state_when_the_master_goes_quiet() {
+        let config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        let (mut bridge, io, can) = bridge(&config);
+        bridge.poll().unwrap();
+        let published = can.sent.lock().unwrap()[0];
+        assert_eq!(
+            published.id(),
+            Id::Standard(StandardId::new(0x181).unwrap())
+        );
+        assert_eq!(published.data(), [0x89, 0x13, 0, 0, 0x05, 0xdc]);
+
+        can.send(0x201, &[0x10, 0x27]);
+        bridge.poll().unwrap();
+        assert!(bridge.is_receiving(0x201));
+        assert_eq!(io.outputs.lock().unwrap()[&(2, 1)], 10_000);
+
+        let start = Instant::now();
+        while bridge.is_receiving(0x201) {
+            bridge.poll().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(io.outputs.lock().unwrap()[&(2, 1)], 0);
+        assert_eq!(bridge.stats().timeouts, 1);
+
+        //the transmit cycle kept running while waiting for the timeout
+        assert!(bridge.stats().transmitted > 1);
+    }
+
+    #[test]
+    fn rejected_setpoints_do_not_keep_the_outputs_alive() {
+        let mut config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        config.receive[0].fields.push(FieldConfig {
+            channel: ChannelConfig::Channel {
+                slot: 2,
+                channel: 2,
+            },
+            start: 2,
+            size: 2,
+            byte_order: ByteOrder::LittleEndian,
+            signed: false,
+            quantity: Quantity::Value,
+        });
+        let (mut bridge, io, can) = bridge(&config);
+
+        //the first frame sets channel 1 but is rejected for channel 2, channel 1 is switched off again
+        can.send(0x201, &[0x10, 0x27, 0x11, 0x27]);
+        bridge.poll().unwrap();
+        assert!(!bridge.is_receiving(0x201));
+        assert_eq!(io.outputs.lock().unwrap()[&(2, 1)], 0);
+        assert_eq!(bridge.stats().rejected, 1);
+
+        can.send(0x201, &[0x10, 0x27, 0, 0]);
+        bridge.poll().unwrap();
+        let last = bridge.receive[0].last;
+        assert!(last.is_some());
+
+        //a rejected frame leaves the timeout running
+        can.send(0x201, &[0x10, 0x27, 0x11, 0x27]);
+        bridge.poll().unwrap();
+        assert_eq!(bridge.receive[0].last, last);
+        assert_eq!(bridge.stats().rejected, 2);
+        assert_eq!(bridge.stats().received, 3);
+    }
+
+    #[test]
+    #[ignore = "needs a vcan0 interface"]
+    fn bridges_a_vcan_interface() {
+        let config = CanBridgeConfig::from_toml(CONFIG).unwrap();
+        let io = Arc::new(FakeIo::default());
+        let mut bridge = CanBridge::open(&config, io.clone()).unwrap();
+        let master = CanSocket::open("vcan0").unwrap();
+        let published = Id::Standard(StandardId::new(0x181).unwrap());
+        let frame = loop {
+            bridge.poll().unwrap();
+            if let Ok(CanFrame::Data(frame)) = master.read_frame_timeout(Duration::ZERO) {
+                if frame.id() == published {
+                    break frame;
+                }
+            }
+        };
+        assert_eq!(frame.data(), [0x89, 0x13, 0, 0, 0x05, 0xdc]);
+
+        let setpoint = CanDataFrame::new(StandardId::new(0x201).unwrap(), &[0x10, 0x27]).unwrap();
+        master.write_frame(&setpoint).unwrap();
+        while !bridge.is_receiving(0x201) {
+            bridge.poll().unwrap();
+        }
+        assert_eq!(io.outputs.lock().unwrap()[&(2, 1)], 10_000);
+
+        //the master goes quiet, the output falls back to the safe state after the timeout
+        let start = Instant::now();
+        while bridge.is_receiving(0x201) {
+            bridge.poll().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(io.outputs.lock().unwrap()[&(2, 1)], 0);
+        assert_eq!(bridge.stats().timeouts, 1);
+    }
+}
@@ -0,0 +1,237 @@
+use std::thread;
+
+use go_module_base::{module_checksum, ModuleIdentity, ModuleSetupError, BOOTMESSAGELENGTH};
+use go_modules::any_module::ModuleKind;
+
+use crate::{
+    detect_controller_type,
+    slot_map::{SlotMap, SlotMapError},
+    ControllerSlot, ControllerType, DetectError, LinuxGoModule, SLOTS,
+};
+
+///What was found in a slot during discovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotState {
+    ///The slot could be opened but no module answered
+    Empty,
+    ///Something is wrong with the slot or the module in it
+    Faulty(SlotFault),
+    ///A module identified itself, the kind is None when there is no driver for it
+    Module {
+        kind: Option<ModuleKind>,
+        identity: ModuleIdentity,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotFault {
+    ///The spi device, reset or interrupt line of the slot could not be opened
+    Open(ModuleSetupError),
+    ///The reset line could not be driven
+    Reset,
+    ///The boot message could not be exchanged
+    NoBootMessage,
+    ///The boot message did not pass its checksum
+    InvalidBootMessage,
+    ///Discovering the slot panicked, the module of the slot is lost until the next discovery
+    Panicked,
+}
+
+///The result of discovering one slot
+pub struct DiscoveredSlot {
+    pub slot: ControllerSlot,
+    pub state: SlotState,
+    module: Option<LinuxGoModule>,
+}
+
+impl DiscoveredSlot {
+    ///Open one slot of the slot map and identify the module in it
+    pub fn discover(slot_map: &SlotMap, slot: ControllerSlot) -> Self {
+        let (state, module) = match slot_map.open(slot) {
+            Err(err) => (SlotState::Faulty(SlotFault::Open(err)), None),
+            Ok(module) => match module.module_reset() {
+                Err(module) => (SlotState::Faulty(SlotFault::Reset), Some(module)),
+                Ok(mut module) => {
+                    let state = match module.escape_module_bootloader() {
+                        Err(_) => SlotState::Faulty(SlotFault::NoBootMessage),
+                        Ok(bootmessage) => identify(&bootmessage),
+                    };
+                    (state, Some(module.degrade()))
+                }
+            },
+        };
+        DiscoveredSlot {
+            slot,
+            state,
+            module,
+        }
+    }
+
+    ///Take the module out of the slot, it has left its bootloader during discovery
+    ///so it has to be reset again before it is built into a module driver
+    pub fn take(&mut self) -> Option<LinuxGoModule> {
+        self.module.take()
+    }
+}
+
+///Why the slots of the running controller could not be discovered
+#[derive(Debug)]
+pub enum DiscoverError {
+    Detect(DetectError),
+    ///The device tree describes the slots, but not in a way that can be used
+    SlotMap(SlotMapError),
+}
+
+///A controller with an inventory of the modules in its slots
+pub struct Controller {
+    controller_type: ControllerType,
+    slots: Vec<DiscoveredSlot>,
+}
+
+impl Controller {
+    ///Detect the controller, open every slot and identify the module in it.
+    ///The slot map comes from the device tree when it describes the slots, the built in one is used otherwise
+    pub fn discover() -> Result<Self, DiscoverError> {
+        let controller_type = detect_controller_type("/").map_err(DiscoverError::Detect)?;
+        let slot_map = SlotMap::detect(&controller_type, "/").map_err(DiscoverError::SlotMap)?;
+        Ok(Self::discover_with(controller_type, &slot_map))
+    }
+
+    ///Open every slot in the slot map and identify the module in it, the modules are reset in parallel.
+    ///A slot that fails is reported in the inventory and does not stop the others from being discovered
+    pub fn discover_with(controller_type: ControllerType, slot_map: &SlotMap) -> Self {
+        let mut slots: Vec<DiscoveredSlot> = thread::scope(|scope| {
+            let handles: Vec<_> = slot_map
+                .slots
+                .iter()
+                .filter_map(|config| SLOTS.get((config.slot as usize).wrapping_sub(1)))
+                .map(|slot| {
+                    let slot = *slot;
+                    (
+                        slot,
+                        scope.spawn(move || DiscoveredSlot::discover(slot_map, slot)),
+                    )
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(slot, handle)| {
+                    handle.join().unwrap_or(DiscoveredSlot {
+                        slot,
+                        state: SlotState::Faulty(SlotFault::Panicked),
+                        module: None,
+                    })
+                })
+                .collect()
+        });
+        slots.sort_by_key(|slot| slot.slot as u8);
+        Controller {
+            controller_type,
+            slots,
+        }
+    }
+
+    pub fn controller_type(&self) -> &ControllerType {
+        &self.controller_type
+    }
+
+    ///The inventory of all slots in the slot map, ordered by slot
+    pub fn slots(&self) -> &[DiscoveredSlot] {
+        &self.slots
+    }
+
+    pub fn slot(&self, slot: ControllerSlot) -> Option<&DiscoveredSlot> {
+        self.slots.iter().find(|discovered| discovered.slot == slot)
+    }
+
+    pub fn slot_mut(&mut self, slot: ControllerSlot) -> Option<&mut DiscoveredSlot> {
+        self.slots
+            .iter_mut()
+            .find(|discovered| discovered.slot == slot)
+    }
+}
+
+#[cfg(test)]
+impl Controller {
+    ///A controller with the given inventory and no modules behind it
+    pub(crate) fn with_inventory(
+        controller_type: ControllerType,
+        inventory: impl IntoIterator<Item = (ControllerSlot, SlotState)>,
+    ) -> Self {
+        Controller {
+            controller_type,
+            slots: inventory
+                .into_iter()
+                .map(|(slot, state)| DiscoveredSlot {
+                    slot,
+                    state,
+                    module: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn identify(bootmessage: &[u8; BOOTMESSAGELENGTH]) -> SlotState {
+    //an empty slot leaves miso floating high or pulled low
+    if bootmessage.iter().all(|byte| *byte == 0) || bootmessage.iter().all(|byte| *byte == 0xff) {
+        return SlotState::Empty;
+    }
+    if module_checksum(bootmessage, BOOTMESSAGELENGTH) != bootmessage[BOOTMESSAGELENGTH - 1] {
+        return SlotState::Faulty(SlotFault::InvalidBootMessage);
+    }
+    let identity = ModuleIdentity::from_bootmessage(bootmessage);
+    SlotState::Module {
+        kind: ModuleKind::from_identity(&identity),
+        identity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::mock::bootmessage;
+
+    use super::*;
+
+    fn identify_frame(frame: &[u8]) -> SlotState {
+        identify(frame.try_into().unwrap())
+    }
+
+    #[test]
+    fn identifies_known_and_unknown_modules() {
+        let identity = ModuleIdentity {
+            id: [20, 20, 2],
+            hardware_version: 3,
+            firmware_version: [1, 2, 0],
+        };
+        assert_eq!(
+            identify_frame(&bootmessage([20, 20, 2], 3, [1, 2, 0])),
+            SlotState::Module {
+                kind: Some(ModuleKind::Output6Channel),
+                identity
+            }
+        );
+        assert_eq!(
+            identify_frame(&bootmessage([20, 90, 1], 3, [1, 2, 0])),
+            SlotState::Module {
+                kind: None,
+                identity: ModuleIdentity {
+                    id: [20, 90, 1],
+                    ..identity
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn tells_an_empty_slot_from_a_garbled_boot_message() {
+        assert_eq!(identify(&[0; BOOTMESSAGELENGTH]), SlotState::Empty);
+        assert_eq!(identify(&[0xff; BOOTMESSAGELENGTH]), SlotState::Empty);
+        let mut garbled = bootmessage([20, 20, 2], 3, [1, 2, 0]);
+        garbled[7] ^= 0x01;
+        assert_eq!(
+            identify_frame(&garbled),
+            SlotState::Faulty(SlotFault::InvalidBootMessage)
+        );
+    }
+}
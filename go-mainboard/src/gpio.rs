@@ -0,0 +1,457 @@
+use std::time::{Duration, Instant};
+
+use embedded_hal::digital::{ErrorKind, ErrorType, InputPin, OutputPin, PinState};
+#[cfg(feature = "tokio")]
+use gpiocdev::line::EdgeKind;
+use gpiocdev::{
+    line::{Bias, EdgeDetection, EdgeEvent, Value},
+    Request,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::slot_map::GpioSpec;
+
+///Bias of a gpio line, left as the kernel configured it when not set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineBias {
+    PullUp,
+    PullDown,
+    Disabled,
+}
+
+impl From<LineBias> for Bias {
+    fn from(bias: LineBias) -> Self {
+        match bias {
+            LineBias::PullUp => Bias::PullUp,
+            LineBias::PullDown => Bias::PullDown,
+            LineBias::Disabled => Bias::Disabled,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GpioError(pub gpiocdev::Error);
+
+impl embedded_hal::digital::Error for GpioError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+///An output line requested through the gpio character device
+pub struct GpioOutput {
+    request: Request,
+    offset: u32,
+}
+
+impl GpioOutput {
+    ///Request the line as an output that starts out low,
+    ///`consumer` is the label the kernel shows for the line
+    pub fn new(gpio: &GpioSpec, consumer: &str) -> Result<Self, gpiocdev::Error> {
+        let request = Request::builder()
+            .on_chip(gpio.path())
+            .with_consumer(consumer)
+            .with_line(gpio.line)
+            .with_bias(gpio.bias.map(Bias::from))
+            .as_output(Value::Inactive)
+            .request()?;
+        Ok(GpioOutput {
+            request,
+            offset: gpio.line,
+        })
+    }
+}
+
+impl ErrorType for GpioOutput {
+    type Error = GpioError;
+}
+
+impl OutputPin for GpioOutput {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.request
+            .set_value(self.offset, Value::Inactive)
+            .map_err(GpioError)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.request
+            .set_value(self.offset, Value::Active)
+            .map_err(GpioError)
+    }
+}
+
+///A module interrupt line, requested with edge detection on both edges
+///so the module becoming ready can be waited on instead of polled
+pub struct GpioInterrupt {
+    request: Request,
+    offset: u32,
+}
+
+impl GpioInterrupt {
+    pub fn new(gpio: &GpioSpec, consumer: &str) -> Result<Self, gpiocdev::Error> {
+        let request = Request::builder()
+            .on_chip(gpio.path())
+            .with_consumer(consumer)
+            .with_line(gpio.line)
+            .with_bias(gpio.bias.map(Bias::from))
+            .as_input()
+            .with_edge_detection(EdgeDetection::BothEdges)
+            .request()?;
+        Ok(GpioInterrupt {
+            request,
+            offset: gpio.line,
+        })
+    }
+
+    ///Wait for the next edge on the line, returns None when the timeout passes without one
+    pub fn wait_for_edge(&self, timeout: Duration) -> Result<Option<EdgeEvent>, gpiocdev::Error> {
+        if self.request.wait_edge_event(timeout)? {
+            self.request.read_edge_event().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    ///Wait until the line is in the given state, returns false when the timeout passes first
+    pub fn wait_for_state(
+        &self,
+        state: PinState,
+        timeout: Duration,
+    ) -> Result<bool, gpiocdev::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            //read the level after draining events so an edge between checking and waiting is not missed
+            while self.request.has_edge_event()? {
+                self.request.read_edge_event()?;
+            }
+            if self.state()? == state {
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !self.request.wait_edge_event(remaining)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    ///The underlying line request, for example to wait on its edge events from an async runtime
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    fn state(&self) -> Result<PinState, gpiocdev::Error> {
+        match self.request.value(self.offset)? {
+            Value::Active => Ok(PinState::High),
+            Value::Inactive => Ok(PinState::Low),
+        }
+    }
+}
+
+impl ErrorType for GpioInterrupt {
+    type Error = GpioError;
+}
+
+impl InputPin for GpioInterrupt {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.state().map_err(GpioError)? == PinState::High)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.state().map_err(GpioError)? == PinState::Low)
+    }
+}
+
+///A module interrupt line for async code, waiting on it is driven by the edge events of the line
+#[cfg(feature = "tokio")]
+pub struct AsyncGpioInterrupt {
+    request: gpiocdev::tokio::AsyncRequest,
+    offset: u32,
+}
+
+#[cfg(feature = "tokio")]
+impl From<GpioInterrupt> for AsyncGpioInterrupt {
+    fn from(interrupt: GpioInterrupt) -> Self {
+        AsyncGpioInterrupt {
+            request: gpiocdev::tokio::AsyncRequest::new(interrupt.request),
+            offset: interrupt.offset,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncGpioInterrupt {
+    pub fn new(gpio: &GpioSpec, consumer: &str) -> Result<Self, gpiocdev::Error> {
+        GpioInterrupt::new(gpio, consumer).map(Self::from)
+    }
+
+    fn state(&self) -> Result<PinState, gpiocdev::Error> {
+        match self.request.as_ref().value(self.offset)? {
+            Value::Active => Ok(PinState::High),
+            Value::Inactive => Ok(PinState::Low),
+        }
+    }
+
+    async fn wait_for_state(&mut self, state: PinState) -> Result<(), gpiocdev::Error> {
+        loop {
+            //read the level after draining events so an edge between checking and waiting is not missed
+            while self.request.as_ref().has_edge_event()? {
+                self.request.as_ref().read_edge_event()?;
+            }
+            if self.state()? == state {
+                return Ok(());
+            }
+            self.request.read_edge_event().await?;
+        }
+    }
+
+    async fn wait_for_edge(&mut self, kind: Option<EdgeKind>) -> Result<(), gpiocdev::Error> {
+        loop {
+            let event = self.request.read_edge_event().await?;
+            if kind.is_none_or(|kind| kind == event.kind) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ErrorType for AsyncGpioInterrupt {
+    type Error = GpioError;
+}
+
+#[cfg(feature = "tokio")]
+impl InputPin for AsyncGpioInterrupt {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.state().map_err(GpioError)? == PinState::High)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.state().map_err(GpioError)? == PinState::Low)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl embedded_hal_async::digital::Wait for AsyncGpioInterrupt {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(PinState::High).await.map_err(GpioError)
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(PinState::Low).await.map_err(GpioError)
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_edge(Some(EdgeKind::Rising))
+            .await
+            .map_err(GpioError)
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_edge(Some(EdgeKind::Falling))
+            .await
+            .map_err(GpioError)
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_edge(None).await.map_err(GpioError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use gpiocdev::line::EdgeKind;
+
+    use super::*;
+
+    ///A simulated gpio chip from the gpio-sim kernel module, removed again when dropped.
+    ///The inputs of the chip read what their simulated pull is set to
+    struct GpioSim {
+        config: PathBuf,
+        device: PathBuf,
+        chip: String,
+    }
+
+    impl GpioSim {
+        ///None when gpio-sim is not available, it needs configfs, the module and root
+        fn new(lines: u32) -> Option<Self> {
+            static CHIPS: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "go-mainboard-{}-{}",
+                std::process::id(),
+                CHIPS.fetch_add(1, Ordering::SeqCst)
+            );
+            let config = PathBuf::from("/sys/kernel/config/gpio-sim").join(name);
+            fs::create_dir(&config).ok()?;
+            //dropping it removes the configuration again when setting up the chip fails
+            let mut sim = GpioSim {
+                config,
+                device: PathBuf::new(),
+                chip: String::new(),
+            };
+            fs::create_dir(sim.config.join("bank0")).ok()?;
+            fs::write(sim.config.join("bank0/num_lines"), lines.to_string()).ok()?;
+            fs::write(sim.config.join("live"), "1").ok()?;
+            let read = |path: PathBuf| Some(fs::read_to_string(path).ok()?.trim().to_owned());
+            let device = read(sim.config.join("dev_name"))?;
+            sim.chip = read(sim.config.join("bank0/chip_name"))?;
+            sim.device = PathBuf::from("/sys/devices/platform")
+                .join(device)
+                .join(&sim.chip);
+            Some(sim)
+        }
+
+        fn spec(&self, line: u32, bias: Option<LineBias>) -> GpioSpec {
+            GpioSpec {
+                chip: self.chip.clone(),
+                line,
+                bias,
+            }
+        }
+
+        ///Drive an input from outside, as the module would
+        fn pull(&self, line: u32, high: bool) {
+            let pull = if high { "pull-up" } else { "pull-down" };
+            fs::write(self.line(line).join("pull"), pull).unwrap();
+        }
+
+        fn pulled_high(&self, line: u32) -> bool {
+            fs::read_to_string(self.line(line).join("pull"))
+                .unwrap()
+                .trim()
+                == "pull-up"
+        }
+
+        ///The level an output is driven to
+        fn value(&self, line: u32) -> bool {
+            fs::read_to_string(self.line(line).join("value"))
+                .unwrap()
+                .trim()
+                == "1"
+        }
+
+        fn line(&self, line: u32) -> PathBuf {
+            self.device.join(format!("sim_gpio{line}"))
+        }
+    }
+
+    impl Drop for GpioSim {
+        fn drop(&mut self) {
+            let _ = fs::write(self.config.join("live"), "0");
+            let _ = fs::remove_dir(self.config.join("bank0"));
+            let _ = fs::remove_dir(&self.config);
+        }
+    }
+
+    macro_rules! gpio_sim {
+        ($lines:expr) => {
+            match GpioSim::new($lines) {
+                Some(sim) => sim,
+                None => {
+                    eprintln!("gpio-sim is not available, skipped");
+                    return;
+                }
+            }
+        };
+    }
+
+    #[test]
+    fn drives_an_output() {
+        let sim = gpio_sim!(2);
+        let mut output = GpioOutput::new(&sim.spec(1, None), "test reset").unwrap();
+        assert!(!sim.value(1));
+        output.set_high().unwrap();
+        assert!(sim.value(1));
+        output.set_low().unwrap();
+        assert!(!sim.value(1));
+    }
+
+    #[test]
+    fn requests_the_configured_bias() {
+        let sim = gpio_sim!(2);
+        let mut up = GpioInterrupt::new(&sim.spec(0, Some(LineBias::PullUp)), "test").unwrap();
+        let mut down = GpioInterrupt::new(&sim.spec(1, Some(LineBias::PullDown)), "test").unwrap();
+        assert!(sim.pulled_high(0));
+        assert!(up.is_high().unwrap());
+        assert!(!sim.pulled_high(1));
+        assert!(down.is_low().unwrap());
+    }
+
+    #[test]
+    fn waits_for_edges_and_states() {
+        let sim = gpio_sim!(1);
+        sim.pull(0, false);
+        let mut interrupt = GpioInterrupt::new(&sim.spec(0, None), "test interrupt").unwrap();
+        assert!(interrupt.is_low().unwrap());
+        assert!(interrupt
+            .wait_for_edge(Duration::from_millis(10))
+            .unwrap()
+            .is_none());
+
+        sim.pull(0, true);
+        let edge = interrupt.wait_for_edge(Duration::from_secs(1)).unwrap();
+        assert_eq!(edge.map(|edge| edge.kind), Some(EdgeKind::Rising));
+
+        //a state the line already has is there right away, even with edges still queued
+        sim.pull(0, false);
+        sim.pull(0, true);
+        assert!(interrupt
+            .wait_for_state(PinState::High, Duration::ZERO)
+            .unwrap());
+        assert!(!interrupt
+            .wait_for_state(PinState::Low, Duration::from_millis(10))
+            .unwrap());
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                sim.pull(0, false);
+            });
+            assert!(interrupt
+                .wait_for_state(PinState::Low, Duration::from_secs(1))
+                .unwrap());
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn waits_asynchronously() {
+        use embedded_hal_async::digital::Wait;
+
+        let sim = gpio_sim!(1);
+        sim.pull(0, false);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut interrupt =
+                AsyncGpioInterrupt::new(&sim.spec(0, None), "test interrupt").unwrap();
+            interrupt.wait_for_low().await.unwrap();
+
+            let rising = tokio::task::spawn_blocking({
+                let line = sim.line(0);
+                move || {
+                    thread::sleep(Duration::from_millis(20));
+                    fs::write(line.join("pull"), "pull-up").unwrap();
+                }
+            });
+            interrupt.wait_for_rising_edge().await.unwrap();
+            rising.await.unwrap();
+            assert!(interrupt.is_high().unwrap());
+            interrupt.wait_for_high().await.unwrap();
+
+            //an edge from before the wait is queued and still counts
+            sim.pull(0, false);
+            interrupt.wait_for_falling_edge().await.unwrap();
+            interrupt.wait_for_low().await.unwrap();
+        });
+    }
+}
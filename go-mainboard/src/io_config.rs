@@ -0,0 +1,545 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use go_module_base::{ConfigurationError, ModuleDriver, ModuleIdentity};
+use go_modules::any_module::{AnyModule, AnyModuleConfiguration, ModuleKind};
+use linux_embedded_hal::{Delay, SpidevDevice};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controller::{Controller, SlotFault, SlotState},
+    gpio::GpioInterrupt,
+    reset_line::ResetLine,
+    ControllerSlot, SLOTS,
+};
+
+pub type LinuxAnyModule = AnyModule<SpidevDevice, ResetLine, GpioInterrupt, Delay>;
+
+///The I/O of a whole controller: the module expected in every slot with its configuration,
+///and names for the channels that are wired to the machine.
+///```toml
+///[[slots]]
+///slot = 3
+///[slots.module.Input6Channel]
+///supplies = ["On", "On", "Off"]
+///channels = [
+///    { func = { AnalogmV = 100 }, pu = "None", pd = "PD10k", volt = "Voltage24V" },
+///    { func = { AnalogmV = 100 }, pu = "None", pd = "PD10k", volt = "Voltage24V" },
+///    { func = "Frequency", pu = "PU4_7k", pd = "None", volt = "Voltage12V" },
+///    { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+///    { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+///    { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+///]
+///
+///[[slots]]
+///slot = 4
+///[slots.module.Output6Channel]
+///frequencies = ["Hz1_000", "Hz100", "Hz1_000"]
+///channels = [
+///    { func = "HighSideDuty", max_current = 2500 },
+///    { func = "HighSideDuty", max_current = 2500 },
+///    { func = "Disabled", max_current = 0 },
+///    { func = "Disabled", max_current = 0 },
+///    { func = "Disabled", max_current = 0 },
+///    { func = "Disabled", max_current = 0 },
+///]
+///
+///[signals]
+///boom_pressure = { slot = 3, channel = 2 }
+///boom_valve = { slot = 4, channel = 1 }
+///```
+#[derive(Serialize, Deserialize)]
+pub struct IoConfig {
+    pub slots: Vec<SlotIoConfig>,
+    #[serde(default)]
+    pub signals: BTreeMap<String, SignalConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SlotIoConfig {
+    ///Slot number as printed on the controller, starting at 1
+    pub slot: u8,
+    pub module: AnyModuleConfiguration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignalConfig {
+    pub slot: u8,
+    ///Channel number as printed on the module, starting at 1
+    pub channel: u8,
+}
+
+///A difference between the I/O configuration and the hardware that was discovered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoConfigMismatch {
+    NoSuchSlot(u8),
+    ///The slot is configured more than once
+    DuplicateSlot(u8),
+    FaultySlot {
+        slot: u8,
+        fault: SlotFault,
+    },
+    EmptySlot {
+        slot: u8,
+        expected: ModuleKind,
+    },
+    ///A different module is in the slot, found is None when there is no driver for it
+    WrongModule {
+        slot: u8,
+        expected: ModuleKind,
+        found: Option<ModuleKind>,
+        identity: ModuleIdentity,
+    },
+    ///The signal is on a slot that is not configured
+    UnconfiguredSignalSlot {
+        signal: String,
+        slot: u8,
+    },
+    NoSuchChannel {
+        signal: String,
+        slot: u8,
+        channel: u8,
+    },
+}
+
+#[derive(Debug)]
+pub enum IoConfigError {
+    Io(PathBuf, std::io::Error),
+    Toml(toml::de::Error),
+}
+
+#[derive(Debug)]
+pub enum IoBuildError {
+    ///Nothing was built because the configuration does not match the hardware
+    Mismatch(Vec<IoConfigMismatch>),
+    ///The module in the slot could not be reset
+    Reset(u8),
+    ///The module in the slot did not accept its configuration,
+    ///the modules that were already built have been put in their safe state
+    Configuration { slot: u8, error: ConfigurationError },
+}
+
+///A named channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal {
+    pub slot: ControllerSlot,
+    pub channel: u8,
+    pub kind: ModuleKind,
+}
+
+///The configured modules of a controller with their named signals
+pub struct ControllerIo<
+    SPI = SpidevDevice,
+    ResetPin = ResetLine,
+    InterruptPin = GpioInterrupt,
+    Delay = linux_embedded_hal::Delay,
+> {
+    modules: Vec<(
+        ControllerSlot,
+        AnyModule<SPI, ResetPin, InterruptPin, Delay>,
+    )>,
+    signals: BTreeMap<String, Signal>,
+}
+
+impl IoConfig {
+    pub fn from_toml(toml: &str) -> Result<Self, IoConfigError> {
+        toml::from_str(toml).map_err(IoConfigError::Toml)
+    }
+
+    ///Load the configuration from a TOML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IoConfigError> {
+        let path = path.as_ref();
+        let toml =
+            fs::read_to_string(path).map_err(|err| IoConfigError::Io(path.to_path_buf(), err))?;
+        Self::from_toml(&toml)
+    }
+
+    ///Compare the configuration with the discovered hardware, all mismatches are reported at once
+    pub fn validate(&self, controller: &Controller) -> Result<(), Vec<IoConfigMismatch>> {
+        let mut mismatches = Vec::new();
+        let mut configured: BTreeMap<u8, ModuleKind> = BTreeMap::new();
+        for config in &self.slots {
+            let expected = config.module.kind();
+            if configured.insert(config.slot, expected).is_some() {
+                mismatches.push(IoConfigMismatch::DuplicateSlot(config.slot));
+                continue;
+            }
+            let Some(discovered) = slot(config.slot).and_then(|slot| controller.slot(slot)) else {
+                mismatches.push(IoConfigMismatch::NoSuchSlot(config.slot));
+                continue;
+            };
+            match discovered.state {
+                SlotState::Empty => mismatches.push(IoConfigMismatch::EmptySlot {
+                    slot: config.slot,
+                    expected,
+                }),
+                SlotState::Faulty(fault) => mismatches.push(IoConfigMismatch::FaultySlot {
+                    slot: config.slot,
+                    fault,
+                }),
+                SlotState::Module { kind, identity } if kind != Some(expected) => {
+                    mismatches.push(IoConfigMismatch::WrongModule {
+                        slot: config.slot,
+                        expected,
+                        found: kind,
+                        identity,
+                    })
+                }
+                SlotState::Module { .. } => {}
+            }
+        }
+        for (signal, config) in &self.signals {
+            match configured.get(&config.slot) {
+                None => mismatches.push(IoConfigMismatch::UnconfiguredSignalSlot {
+                    signal: signal.clone(),
+                    slot: config.slot,
+                }),
+                Some(kind) if !(1..=kind.channels()).contains(&config.channel) => {
+                    mismatches.push(IoConfigMismatch::NoSuchChannel {
+                        signal: signal.clone(),
+                        slot: config.slot,
+                        channel: config.channel,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+
+    ///Validate the configuration and build all modules, nothing is sent to the modules
+    ///when the configuration does not match the hardware
+    pub fn build(self, controller: &mut Controller) -> Result<ControllerIo, IoBuildError> {
+        self.validate(controller).map_err(IoBuildError::Mismatch)?;
+        let mut modules: Vec<(ControllerSlot, LinuxAnyModule)> = Vec::new();
+        for config in self.slots {
+            //validation made sure the slot exists and holds the expected module
+            let Some(slot) = slot(config.slot) else {
+                continue;
+            };
+            let result = controller
+                .slot_mut(slot)
+                .and_then(|discovered| discovered.take())
+                .ok_or(IoBuildError::Reset(config.slot))
+                .and_then(|module| {
+                    module
+                        .module_reset()
+                        .map_err(|_| IoBuildError::Reset(config.slot))
+                })
+                .and_then(|module| {
+                    AnyModule::build(module, config.module).map_err(|(_, _, error)| {
+                        IoBuildError::Configuration {
+                            slot: config.slot,
+                            error,
+                        }
+                    })
+                });
+            match result {
+                Ok(module) => modules.push((slot, module)),
+                Err(err) => {
+                    for (_, module) in &mut modules {
+                        let _ = module.safe_state();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        let signals = self
+            .signals
+            .into_iter()
+            .filter_map(|(name, config)| {
+                let (slot, module) = modules
+                    .iter()
+                    .find(|(slot, _)| *slot as u8 + 1 == config.slot)?;
+                Some((
+                    name,
+                    Signal {
+                        slot: *slot,
+                        channel: config.channel,
+                        kind: module.kind(),
+                    },
+                ))
+            })
+            .collect();
+        Ok(ControllerIo { modules, signals })
+    }
+}
+
+fn slot(number: u8) -> Option<ControllerSlot> {
+    SLOTS.get((number as usize).wrapping_sub(1)).copied()
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> ControllerIo<SPI, ResetPin, InterruptPin, Delay> {
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.get(name)
+    }
+
+    pub fn signals(&self) -> impl Iterator<Item = (&str, &Signal)> {
+        self.signals
+            .iter()
+            .map(|(name, signal)| (name.as_str(), signal))
+    }
+
+    pub fn module(
+        &self,
+        slot: ControllerSlot,
+    ) -> Option<&AnyModule<SPI, ResetPin, InterruptPin, Delay>> {
+        self.modules
+            .iter()
+            .find(|(module_slot, _)| *module_slot == slot)
+            .map(|(_, module)| module)
+    }
+
+    pub fn module_mut(
+        &mut self,
+        slot: ControllerSlot,
+    ) -> Option<&mut AnyModule<SPI, ResetPin, InterruptPin, Delay>> {
+        self.modules
+            .iter_mut()
+            .find(|(module_slot, _)| *module_slot == slot)
+            .map(|(_, module)| module)
+    }
+
+    ///The module a signal is on, together with the channel of the signal
+    #[allow(clippy::type_complexity)]
+    pub fn signal_module_mut(
+        &mut self,
+        name: &str,
+    ) -> Option<(&mut AnyModule<SPI, ResetPin, InterruptPin, Delay>, u8)> {
+        let signal = *self.signals.get(name)?;
+        self.module_mut(signal.slot)
+            .map(|module| (module, signal.channel))
+    }
+
+    ///Modules that were built without [IoConfig::build], for example on scripted hardware
+    pub fn from_parts(
+        modules: Vec<(
+            ControllerSlot,
+            AnyModule<SPI, ResetPin, InterruptPin, Delay>,
+        )>,
+        signals: BTreeMap<String, Signal>,
+    ) -> Self {
+        ControllerIo { modules, signals }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn into_parts(
+        self,
+    ) -> (
+        Vec<(
+            ControllerSlot,
+            AnyModule<SPI, ResetPin, InterruptPin, Delay>,
+        )>,
+        BTreeMap<String, Signal>,
+    ) {
+        (self.modules, self.signals)
+    }
+
+    ///The slots with a module, ordered like in the configuration
+    pub fn slots(&self) -> impl Iterator<Item = ControllerSlot> + '_ {
+        self.modules.iter().map(|(slot, _)| *slot)
+    }
+
+    ///All modules, ordered like the slots in the configuration
+    pub fn modules_mut(
+        &mut self,
+    ) -> impl Iterator<
+        Item = (
+            ControllerSlot,
+            &mut AnyModule<SPI, ResetPin, InterruptPin, Delay>,
+        ),
+    > {
+        self.modules
+            .iter_mut()
+            .map(|(slot, module)| (*slot, module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::mock::doc_example;
+
+    use super::*;
+    use crate::{ControllerType, HwVersion};
+
+    #[test]
+    fn parses_the_documented_example() {
+        let doc = doc_example(include_str!("io_config.rs"), "toml");
+        let config = IoConfig::from_toml(&doc).unwrap();
+        let slots: Vec<_> = config
+            .slots
+            .iter()
+            .map(|slot| (slot.slot, slot.module.kind()))
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                (3, ModuleKind::Input6Channel),
+                (4, ModuleKind::Output6Channel)
+            ]
+        );
+        assert_eq!(
+            config.signals["boom_pressure"],
+            SignalConfig {
+                slot: 3,
+                channel: 2
+            }
+        );
+        assert_eq!(
+            config.signals["boom_valve"],
+            SignalConfig {
+                slot: 4,
+                channel: 1
+            }
+        );
+    }
+
+    fn module(kind: ModuleKind) -> SlotState {
+        let identity = ModuleIdentity {
+            id: kind.id(),
+            ..Default::default()
+        };
+        SlotState::Module {
+            kind: Some(kind),
+            identity,
+        }
+    }
+
+    fn controller() -> Controller {
+        Controller::with_inventory(
+            ControllerType::ModulineIV(HwVersion::new(3, 6)),
+            [
+                (ControllerSlot::Slot1, module(ModuleKind::Output6Channel)),
+                (ControllerSlot::Slot2, SlotState::Empty),
+                (ControllerSlot::Slot3, SlotState::Faulty(SlotFault::Reset)),
+                (ControllerSlot::Slot4, module(ModuleKind::Input6Channel)),
+                (
+                    ControllerSlot::Slot5,
+                    SlotState::Module {
+                        kind: None,
+                        identity: ModuleIdentity {
+                            id: [20, 90, 1],
+                            ..Default::default()
+                        },
+                    },
+                ),
+            ],
+        )
+    }
+
+    fn config(slots: &[(u8, ModuleKind)], signals: &[(&str, u8, u8)]) -> IoConfig {
+        IoConfig {
+            slots: slots
+                .iter()
+                .map(|(slot, kind)| SlotIoConfig {
+                    slot: *slot,
+                    module: AnyModuleConfiguration::default_for(*kind),
+                })
+                .collect(),
+            signals: signals
+                .iter()
+                .map(|(name, slot, channel)| {
+                    (
+                        name.to_string(),
+                        SignalConfig {
+                            slot: *slot,
+                            channel: *channel,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_a_configuration_that_matches_the_inventory() {
+        let config = config(
+            &[
+                (1, ModuleKind::Output6Channel),
+                (4, ModuleKind::Input6Channel),
+            ],
+            &[("valve", 1, 6), ("pressure", 4, 1)],
+        );
+        assert_eq!(config.validate(&controller()), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_mismatch_with_the_inventory() {
+        let config = config(
+            &[
+                (1, ModuleKind::Output6Channel),
+                (1, ModuleKind::Output6Channel),
+                (0, ModuleKind::Output6Channel),
+                (6, ModuleKind::Output6Channel),
+                (9, ModuleKind::Output6Channel),
+                (2, ModuleKind::Output6Channel),
+                (3, ModuleKind::Input6Channel),
+                (4, ModuleKind::Output6Channel),
+                (5, ModuleKind::Bridge2Channel),
+            ],
+            &[
+                ("first", 1, 0),
+                ("past_the_last", 1, 7),
+                ("last", 1, 6),
+                ("unconfigured", 7, 1),
+            ],
+        );
+        assert_eq!(
+            config.validate(&controller()),
+            Err(vec![
+                IoConfigMismatch::DuplicateSlot(1),
+                IoConfigMismatch::NoSuchSlot(0),
+                //slot 6 exists on the controller, but is not in the inventory
+                IoConfigMismatch::NoSuchSlot(6),
+                IoConfigMismatch::NoSuchSlot(9),
+                IoConfigMismatch::EmptySlot {
+                    slot: 2,
+                    expected: ModuleKind::Output6Channel
+                },
+                IoConfigMismatch::FaultySlot {
+                    slot: 3,
+                    fault: SlotFault::Reset
+                },
+                IoConfigMismatch::WrongModule {
+                    slot: 4,
+                    expected: ModuleKind::Output6Channel,
+                    found: Some(ModuleKind::Input6Channel),
+                    identity: ModuleIdentity {
+                        id: ModuleKind::Input6Channel.id(),
+                        ..Default::default()
+                    },
+                },
+                IoConfigMismatch::WrongModule {
+                    slot: 5,
+                    expected: ModuleKind::Bridge2Channel,
+                    found: None,
+                    identity: ModuleIdentity {
+                        id: [20, 90, 1],
+                        ..Default::default()
+                    },
+                },
+                IoConfigMismatch::NoSuchChannel {
+                    signal: "first".to_owned(),
+                    slot: 1,
+                    channel: 0
+                },
+                IoConfigMismatch::NoSuchChannel {
+                    signal: "past_the_last".to_owned(),
+                    slot: 1,
+                    channel: 7
+                },
+                IoConfigMismatch::UnconfiguredSignalSlot {
+                    signal: "unconfigured".to_owned(),
+                    slot: 7
+                },
+            ])
+        );
+    }
+}
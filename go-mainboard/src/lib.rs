@@ -1,5 +1,54 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#[cfg(all(feature = "modules", feature = "tokio"))]
+compile_error!(
+    "the modules feature drives the blocking go-modules drivers, it can't be combined with the async API the tokio feature enables"
+);
+#[cfg(feature = "can")]
+pub mod can_bridge;
+#[cfg(feature = "modules")]
+pub mod controller;
+#[cfg(feature = "std")]
+pub mod gpio;
+#[cfg(feature = "modules")]
+pub mod io_config;
+#[cfg(feature = "std")]
+pub mod power;
+#[cfg(feature = "modules")]
+pub mod process_image;
+#[cfg(feature = "std")]
+pub mod reset_line;
+#[cfg(feature = "std")]
+pub mod scheduler;
+#[cfg(feature = "std")]
+pub mod slot_map;
+#[cfg(feature = "std")]
+mod slots;
+#[cfg(feature = "std")]
+pub mod status_leds;
+#[cfg(feature = "modules")]
+pub mod supervisor;
+#[cfg(feature = "std")]
+pub mod supply;
+#[cfg(feature = "std")]
 pub mod sysfs_reset;
+#[cfg(feature = "tokio")]
+pub mod tokio_hal;
+#[cfg(feature = "std")]
+pub mod watchdog;
 
+use go_module_base::GoModuleUnknown;
+#[cfg(feature = "std")]
+use go_module_base::ModuleSetupError;
+#[cfg(feature = "std")]
+use gpio::GpioInterrupt;
+#[cfg(feature = "std")]
+use linux_embedded_hal::{Delay, SpidevDevice};
+#[cfg(feature = "std")]
+use reset_line::ResetLine;
+#[cfg(feature = "std")]
+use slot_map::SlotMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControllerType {
     #[cfg(feature = "std")]
     ModulineIV(HwVersion),
@@ -13,12 +62,51 @@ pub enum ControllerType {
     ModulineDisplay(HwVersion),
 }
 
+///Hardware revision of a controller, ordered by major and then minor version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct HwVersion {
     version_major: u8,
     version_minor: u8,
 }
 
+impl HwVersion {
+    pub const fn new(version_major: u8, version_minor: u8) -> Self {
+        HwVersion {
+            version_major,
+            version_minor,
+        }
+    }
+
+    pub fn major(&self) -> u8 {
+        self.version_major
+    }
+
+    pub fn minor(&self) -> u8 {
+        self.version_minor
+    }
+}
+
+///Formats the version the way it is printed on the controller, e.g. `V3.06`
+impl core::fmt::Display for HwVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "V{}.{:02}", self.version_major, self.version_minor)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ControllerType {
+    pub fn hw_version(&self) -> HwVersion {
+        match self {
+            ControllerType::ModulineIV(hw)
+            | ControllerType::ModulineIII(hw)
+            | ControllerType::ModulineMini(hw)
+            | ControllerType::ModulineDisplay(hw) => *hw,
+        }
+    }
+}
+
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControllerSlot {
     Slot1,
     Slot2,
@@ -30,219 +118,259 @@ pub enum ControllerSlot {
     Slot8,
 }
 
-pub fn get_controller_type() -> Result<ControllerType, ()> {
+impl ControllerSlot {
+    ///Number of the slot as printed on the controller, counted from 1
+    pub fn number(&self) -> u8 {
+        *self as u8 + 1
+    }
+
+    pub fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1 => Some(ControllerSlot::Slot1),
+            2 => Some(ControllerSlot::Slot2),
+            3 => Some(ControllerSlot::Slot3),
+            4 => Some(ControllerSlot::Slot4),
+            5 => Some(ControllerSlot::Slot5),
+            6 => Some(ControllerSlot::Slot6),
+            7 => Some(ControllerSlot::Slot7),
+            8 => Some(ControllerSlot::Slot8),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+const SLOTS: [ControllerSlot; 8] = [
+    ControllerSlot::Slot1,
+    ControllerSlot::Slot2,
+    ControllerSlot::Slot3,
+    ControllerSlot::Slot4,
+    ControllerSlot::Slot5,
+    ControllerSlot::Slot6,
+    ControllerSlot::Slot7,
+    ControllerSlot::Slot8,
+];
+
+///Why the controller type could not be detected
+#[derive(Debug)]
+pub enum DetectError {
+    ///The hardware description could not be read
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    ///The hardware description does not name a known controller, holds the raw description
+    #[cfg(feature = "std")]
+    UnknownController(String),
+    ///The hardware version could not be parsed, holds the raw description
+    #[cfg(feature = "std")]
+    InvalidVersion(String),
+}
+
+///Detect the controller this is running on
+pub fn get_controller_type() -> Result<ControllerType, DetectError> {
     #[cfg(not(feature = "std"))]
     {
-        Ok(ControllerType::ModulineII(HwVersion {
-            //default to V1.04
-            version_major: 1,
-            version_minor: 4,
-        }))
+        //default to V1.04
+        Ok(ControllerType::ModulineII(HwVersion::new(1, 4)))
     }
     #[cfg(feature = "std")]
     {
-        let hw =
-            std::fs::read_to_string("/sys/firmware/devicetree/base/hardware").map_err(|err| {
-                eprintln!("{:?}", err);
-                ()
-            })?;
-        let mut version = hw
-            .split(" ")
-            .last()
-            .ok_or_else(|| eprintln!("failed to get version part of hardware"))?
-            .split(".");
-        let version_major = u8::from_str_radix(
-            version
-                .nth(0)
-                .ok_or_else(|| eprintln!("failed to get hw version major"))?,
-            10,
-        )
-        .map_err(|err| {
-            eprintln!("failed to parse controller hw version major\n{:?}", err);
-            ()
-        })?;
-        let version_minor = u8::from_str_radix(
-            version
-                .nth(1)
-                .ok_or_else(|| eprintln!("failed to get hw version minor"))?,
-            10,
-        )
-        .map_err(|err| {
-            eprintln!("failed to parse controller hw version minor\n{:?}", err);
-            ()
-        })?;
-        if hw.contains("Moduline IV") {
-            Ok(ControllerType::ModulineIV(HwVersion {
-                version_major,
-                version_minor,
-            }))
-        } else if hw.contains("Moduline Mini") {
-            Ok(ControllerType::ModulineMini(HwVersion {
-                version_major,
-                version_minor,
-            }))
-        } else if hw.contains("Moduline Display") {
-            Ok(ControllerType::ModulineDisplay(HwVersion {
-                version_major,
-                version_minor,
-            }))
-        } else if hw.contains("Moduline III") {
-            Ok(ControllerType::ModulineIII(HwVersion {
-                version_major,
-                version_minor,
-            }))
-        } else {
-            Err(())
-        }
+        detect_controller_type("/")
     }
 }
 
-impl<SPI, ResetPin, InterruptPin> GoModule<SPI, ResetPin, InterruptPin>
-where
-    SPI: SpiDevice,
-    ResetPin: OutputPin,
-    InterruptPin: InputPin,
-{
-    #[cfg(feature = "std")]
-    ///Get all of the available modules for the given controller type
-    ///These modules can then be embedded into function specific modules like the InputModule6Channel
-    pub fn get_modules(
-        controller_type: ControllerType,
-        slot: ControllerSlot,
-    ) -> Result<GoModules<SpidevDevice, CdevPin, CdevPin>, ModuleSetupError> {
-        extern crate std;
-        match controller_type {
-            ControllerType::ModulineIV(_) => {
-                return Ok(GoModules([
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev1.0")?,
-                        reset: get_module_reset("gpiochip0", 7, ControllerSlot::Slot1)?,
-                        interrupt: get_module_interrupt("gpiochip0", 6, ControllerSlot::Slot1)?,
-                        slot: ControllerSlot::Slot1,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev1.1")?,
-                        reset: get_module_reset("gpiochip2", 10, ControllerSlot::Slot2)?,
-                        interrupt: get_module_interrupt("gpiochip4", 20, ControllerSlot::Slot2)?,
-                        slot: ControllerSlot::Slot2,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev2.0")?,
-                        reset: get_module_reset("gpiochip0", 4, ControllerSlot::Slot3)?,
-                        interrupt: get_module_interrupt("gpiochip0", 7, ControllerSlot::Slot3)?,
-                        slot: ControllerSlot::Slot3,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev2.1")?,
-                        reset: get_module_reset("gpiochip0", 2, ControllerSlot::Slot4)?,
-                        interrupt: get_module_interrupt("gpiochip4", 21, ControllerSlot::Slot4)?,
-                        slot: ControllerSlot::Slot4,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev2.2")?,
-                        reset: get_module_reset("gpiochip3", 24, ControllerSlot::Slot5)?,
-                        interrupt: get_module_interrupt("gpiochip4", 1, ControllerSlot::Slot5)?,
-                        slot: ControllerSlot::Slot5,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev2.3")?,
-                        reset: get_module_reset("gpiochip3", 27, ControllerSlot::Slot6)?,
-                        interrupt: get_module_interrupt("gpiochip3", 26, ControllerSlot::Slot6)?,
-                        slot: ControllerSlot::Slot6,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev0.0")?,
-                        reset: get_module_reset("gpiochip2", 24, ControllerSlot::Slot7)?,
-                        interrupt: get_module_interrupt("gpiochip2", 19, ControllerSlot::Slot7)?,
-                        slot: ControllerSlot::Slot7,
-                    }),
-                    Some(GoModule {
-                        spi: get_module_spi("/dev/spidev0.1")?,
-                        reset: get_module_reset("gpiochip2", 20, ControllerSlot::Slot8)?,
-                        interrupt: get_module_interrupt("gpiochip2", 22, ControllerSlot::Slot8)?,
-                        slot: ControllerSlot::Slot8,
-                    }),
-                ]))
-            }
-            _ => todo!("implement all the hardware"),
-        };
+#[cfg(feature = "std")]
+///Detect the controller from the device tree below the filesystem `root`, normally `/`
+pub fn detect_controller_type(
+    root: impl AsRef<std::path::Path>,
+) -> Result<ControllerType, DetectError> {
+    let hw = std::fs::read_to_string(root.as_ref().join("sys/firmware/devicetree/base/hardware"))
+        .map_err(DetectError::Io)?;
+    parse_hardware(&hw)
+}
+
+#[cfg(feature = "std")]
+///Parse a hardware description like `Moduline IV V3.06`, the `V` in front of the version is optional
+fn parse_hardware(raw: &str) -> Result<ControllerType, DetectError> {
+    //device tree strings are null terminated
+    let hw = raw.trim_end_matches('\0').trim();
+    let invalid_version = || DetectError::InvalidVersion(raw.to_owned());
+    let (name, version) = hw.rsplit_once(' ').ok_or_else(invalid_version)?;
+    let version = version.strip_prefix(['V', 'v']).unwrap_or(version);
+    let (version_major, version_minor) = version.split_once('.').ok_or_else(invalid_version)?;
+    let hw_version = HwVersion {
+        version_major: version_major.parse().map_err(|_| invalid_version())?,
+        version_minor: version_minor.parse().map_err(|_| invalid_version())?,
+    };
+    if name.ends_with("Moduline IV") {
+        Ok(ControllerType::ModulineIV(hw_version))
+    } else if name.ends_with("Moduline Mini") {
+        Ok(ControllerType::ModulineMini(hw_version))
+    } else if name.ends_with("Moduline Display") {
+        Ok(ControllerType::ModulineDisplay(hw_version))
+    } else if name.ends_with("Moduline III") {
+        Ok(ControllerType::ModulineIII(hw_version))
+    } else {
+        Err(DetectError::UnknownController(raw.to_owned()))
     }
+}
 
-    #[cfg(not(feature = "std"))]
-    pub fn new(
-        controller_type: ControllerType,
+#[cfg(feature = "std")]
+pub type LinuxGoModule = GoModuleUnknown<SpidevDevice, ResetLine, GpioInterrupt, Delay>;
+
+///A module on a Linux controller for tokio services
+#[cfg(feature = "tokio")]
+pub type AsyncLinuxGoModule = GoModuleUnknown<
+    tokio_hal::AsyncSpidev,
+    ResetLine,
+    gpio::AsyncGpioInterrupt,
+    tokio_hal::TokioDelay,
+>;
+
+///The modules in all slots of a controller, a slot is empty when the controller does not have it
+///or when its module has been taken out
+pub struct GoModules<SPI, ResetPin, InterruptPin, Delay>(
+    pub [Option<GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>>; 8],
+);
+
+impl<SPI, ResetPin, InterruptPin, Delay> GoModules<SPI, ResetPin, InterruptPin, Delay> {
+    ///Take the module out of a slot so it can be embedded into a function specific module like the InputModule6Channel
+    pub fn take(
+        &mut self,
         slot: ControllerSlot,
-        dp: &mut Peripherals,
-    ) -> Result<Self, ModuleSetupError> {
-        let (interrupt, reset, spi) = match ControllerType {
-            ControllerType::ModulineII(_) => match slot {
-                ControllerSlot::Slot1 => {
-                    todo!("figure out Moduline 2 stuff")
-                }
-                _ => todo!("more slots"),
-            },
-        };
-        Ok(GoModule {
-            spi,
-            reset,
-            interrupt,
-        })
+    ) -> Option<GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>> {
+        self.0[slot as usize].take()
     }
 }
 
 #[cfg(feature = "std")]
-fn get_module_interrupt(
-    chip: &str,
-    line: u32,
-    slot: ControllerSlot,
-) -> Result<CdevPin, ModuleSetupError> {
-    extern crate std;
-    let mut chip = Chip::new(chip).map_err(|_| ModuleSetupError::InterruptPin)?;
-    let line = chip
-        .get_line(line)
-        .map_err(|_| ModuleSetupError::InterruptPin)?;
-    let line_handle = line
-        .request(
-            LineRequestFlags::INPUT,
-            0,
-            std::format!("slot {} module interrupt", slot as u8 + 1).as_str(),
-        )
-        .map_err(|_| ModuleSetupError::InterruptPin)?;
-    Ok(CdevPin::new(line_handle).map_err(|_| ModuleSetupError::InterruptPin)?)
+impl GoModules<SpidevDevice, ResetLine, GpioInterrupt, Delay> {
+    ///Get all of the available modules for the given controller type,
+    ///this needs a built in slot map, see [SlotMap::builtin]
+    pub fn new(controller_type: &ControllerType) -> Result<Self, ModuleSetupError> {
+        Self::from_slot_map(&SlotMap::builtin(controller_type).ok_or(ModuleSetupError::NoSlotMap)?)
+    }
+
+    ///Get the modules of all slots in the slot map
+    pub fn from_slot_map(slot_map: &SlotMap) -> Result<Self, ModuleSetupError> {
+        let mut modules = GoModules([None, None, None, None, None, None, None, None]);
+        for config in &slot_map.slots {
+            let slot = *SLOTS
+                .get((config.slot as usize).wrapping_sub(1))
+                .ok_or(ModuleSetupError::NoSuchSlot)?;
+            modules.0[slot as usize] = Some(slot_map.open(slot)?);
+        }
+        Ok(modules)
+    }
 }
 
 #[cfg(feature = "std")]
-fn get_module_reset(
-    chip: &str,
-    line: u32,
+///Get the module in one slot of the given controller type
+///This module can then be reset and embedded into a function specific module like the InputModule6Channel
+pub fn get_module(
+    controller_type: &ControllerType,
     slot: ControllerSlot,
-) -> Result<CdevPin, ModuleSetupError> {
-    extern crate std;
-    let mut chip = Chip::new(chip).map_err(|_| ModuleSetupError::InterruptPin)?;
-    let line = chip
-        .get_line(line)
-        .map_err(|_| ModuleSetupError::ResetPin)?;
-    let line_handle = line
-        .request(
-            LineRequestFlags::OUTPUT,
-            0,
-            std::format!("slot {} module reset", slot as u8 + 1).as_str(),
-        )
-        .map_err(|_| ModuleSetupError::ResetPin)?;
-    Ok(CdevPin::new(line_handle).map_err(|_| ModuleSetupError::ResetPin)?)
+) -> Result<LinuxGoModule, ModuleSetupError> {
+    SlotMap::builtin(controller_type)
+        .ok_or(ModuleSetupError::NoSlotMap)?
+        .open(slot)
 }
 
-#[cfg(feature = "std")]
-fn get_module_spi(spidev: &str) -> Result<SpidevDevice, ModuleSetupError> {
-    extern crate std;
-    let mut spi = SpidevDevice::open(spidev).map_err(|_| ModuleSetupError::Spi)?;
-    let spi_opts = SpidevOptions {
-        bits_per_word: Some(8),
-        max_speed_hz: Some(2_000_000),
-        lsb_first: None,
-        spi_mode: Some(SpiModeFlags::SPI_MODE_0),
-    };
-    spi.configure(&spi_opts)
-        .map_err(|_| ModuleSetupError::Spi)?;
-    Ok(spi)
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn detect(hardware: &[u8]) -> Result<ControllerType, DetectError> {
+        let root = tempfile::tempdir().unwrap();
+        let base = root.path().join("sys/firmware/devicetree/base");
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::write(base.join("hardware"), hardware).unwrap();
+        detect_controller_type(root.path())
+    }
+
+    #[test]
+    fn detects_every_controller() {
+        let cases: &[(&[u8], ControllerType)] = &[
+            (
+                b"Moduline IV V3.06\0",
+                ControllerType::ModulineIV(HwVersion::new(3, 6)),
+            ),
+            (
+                b"Moduline IV V3.05",
+                ControllerType::ModulineIV(HwVersion::new(3, 5)),
+            ),
+            (
+                b"Moduline IV 3.06\n",
+                ControllerType::ModulineIV(HwVersion::new(3, 6)),
+            ),
+            (
+                b"Moduline III V3.02\0",
+                ControllerType::ModulineIII(HwVersion::new(3, 2)),
+            ),
+            (
+                b"Moduline Mini V1.05\0",
+                ControllerType::ModulineMini(HwVersion::new(1, 5)),
+            ),
+            (
+                b"Moduline Mini V1.11\0",
+                ControllerType::ModulineMini(HwVersion::new(1, 11)),
+            ),
+            (
+                b"Moduline Display V1.01\0",
+                ControllerType::ModulineDisplay(HwVersion::new(1, 1)),
+            ),
+            (
+                b"Moduline Display V1.04 \0",
+                ControllerType::ModulineDisplay(HwVersion::new(1, 4)),
+            ),
+            (
+                b"GOcontroll Moduline IV v3.06\0",
+                ControllerType::ModulineIV(HwVersion::new(3, 6)),
+            ),
+        ];
+        for (hardware, expected) in cases {
+            match detect(hardware) {
+                Ok(controller) => assert_eq!(controller, *expected, "{:?}", hardware),
+                Err(err) => panic!("{:?} failed to detect: {:?}", hardware, err),
+            }
+        }
+    }
+
+    #[test]
+    fn orders_and_formats_versions() {
+        assert!(HwVersion::new(1, 5) < HwVersion::new(1, 6));
+        assert!(HwVersion::new(1, 11) > HwVersion::new(1, 6));
+        assert!(HwVersion::new(2, 0) > HwVersion::new(1, 11));
+        assert_eq!(HwVersion::new(3, 6).to_string(), "V3.06");
+    }
+
+    #[test]
+    fn reports_unknown_controllers() {
+        match detect(b"Moduline V V1.00\0") {
+            Err(DetectError::UnknownController(raw)) => assert_eq!(raw, "Moduline V V1.00\0"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_invalid_versions() {
+        for hardware in [
+            &b"Moduline IV V3\0"[..],
+            b"Moduline IV",
+            b"Moduline IV Vx.06",
+        ] {
+            match detect(hardware) {
+                Err(DetectError::InvalidVersion(_)) => {}
+                other => panic!("{:?} gave unexpected result {:?}", hardware, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_missing_hardware_description() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            detect_controller_type(root.path()),
+            Err(DetectError::Io(_))
+        ));
+    }
 }
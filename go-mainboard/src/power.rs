@@ -0,0 +1,309 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_hal::digital::OutputPin;
+
+use crate::supply::{SupplyBackend, SupplyError, SupplyEvent, SupplyMonitor};
+
+pub type ShutdownHook = Box<dyn FnOnce() -> Result<(), String> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerConfig {
+    ///How long the ignition has to be off before shutting down, so a short dip does not shut down
+    pub ignition_off_delay: Duration,
+    ///Time all shutdown hooks together may take, after this the power is released anyway
+    pub deadline: Duration,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            ignition_off_delay: Duration::from_secs(2),
+            deadline: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    IgnitionOn,
+    ///The ignition is off but not yet for long enough to shut down
+    IgnitionOff {
+        remaining: Duration,
+    },
+    ShutdownRequested,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookOutcome {
+    Done,
+    Failed(String),
+    ///The hook was still running at the deadline
+    TimedOut,
+    ///The deadline passed before the hook was started
+    NotRun,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub hooks: Vec<(String, HookOutcome)>,
+    ///The power was released because the deadline passed
+    pub forced: bool,
+}
+
+#[derive(Debug)]
+pub enum PowerError<LatchError> {
+    Supply(SupplyError),
+    Latch(LatchError),
+}
+
+///Keeps the controller powered through the power hold line while the ignition is on
+///and shuts it down in order once the ignition is switched off
+pub struct PowerManager<Latch, Backend> {
+    latch: Latch,
+    monitor: SupplyMonitor<Backend>,
+    config: PowerConfig,
+    hooks: Vec<(String, ShutdownHook)>,
+    ignition_off_since: Option<Instant>,
+}
+
+impl<Latch, Backend> PowerManager<Latch, Backend>
+where
+    Latch: OutputPin,
+    Backend: SupplyBackend,
+{
+    ///Take over the power hold line, it is driven high to keep the controller powered
+    pub fn new(
+        mut latch: Latch,
+        monitor: SupplyMonitor<Backend>,
+        config: PowerConfig,
+    ) -> Result<Self, Latch::Error> {
+        latch.set_high()?;
+        Ok(PowerManager {
+            latch,
+            monitor,
+            config,
+            hooks: Vec::new(),
+            ignition_off_since: None,
+        })
+    }
+
+    ///Add a hook to run at shutdown, hooks run one after the other in the order they were registered
+    pub fn register_hook(
+        &mut self,
+        name: impl Into<String>,
+        hook: impl FnOnce() -> Result<(), String> + Send + 'static,
+    ) {
+        self.hooks.push((name.into(), Box::new(hook)));
+    }
+
+    ///Read the supply inputs and check whether it is time to shut down
+    pub fn poll(&mut self) -> Result<(PowerState, Vec<SupplyEvent>), SupplyError> {
+        let events = self.monitor.poll()?;
+        if self.monitor.ignition() {
+            self.ignition_off_since = None;
+            return Ok((PowerState::IgnitionOn, events));
+        }
+        let off_for = self
+            .ignition_off_since
+            .get_or_insert_with(Instant::now)
+            .elapsed();
+        let state = match self.config.ignition_off_delay.checked_sub(off_for) {
+            Some(remaining) if !remaining.is_zero() => PowerState::IgnitionOff { remaining },
+            _ => PowerState::ShutdownRequested,
+        };
+        Ok((state, events))
+    }
+
+    ///Poll every `interval` until the ignition has been off long enough, then shut down
+    pub fn run(&mut self, interval: Duration) -> Result<ShutdownReport, PowerError<Latch::Error>> {
+        loop {
+            let (state, _) = self.poll().map_err(PowerError::Supply)?;
+            if state == PowerState::ShutdownRequested {
+                return self.shutdown().map_err(PowerError::Latch);
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    ///Run the shutdown hooks and release the power hold line, a hook that is still running at the
+    ///deadline is left behind and the power is released anyway
+    pub fn shutdown(&mut self) -> Result<ShutdownReport, Latch::Error> {
+        let hooks = std::mem::take(&mut self.hooks);
+        let mut report = ShutdownReport {
+            hooks: hooks
+                .iter()
+                .map(|(name, _)| (name.clone(), HookOutcome::NotRun))
+                .collect(),
+            forced: false,
+        };
+        let deadline = Instant::now() + self.config.deadline;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (index, (_, hook)) in hooks.into_iter().enumerate() {
+                //signal the start so a hook that hangs is told apart from one that never started
+                if sender.send((index, None)).is_err() {
+                    return;
+                }
+                let outcome = match catch_unwind(AssertUnwindSafe(hook)) {
+                    Ok(Ok(())) => HookOutcome::Done,
+                    Ok(Err(err)) => HookOutcome::Failed(err),
+                    Err(_) => HookOutcome::Failed("hook panicked".to_owned()),
+                };
+                if sender.send((index, Some(outcome))).is_err() {
+                    return;
+                }
+            }
+        });
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((index, None)) => report.hooks[index].1 = HookOutcome::TimedOut,
+                Ok((index, Some(outcome))) => report.hooks[index].1 = outcome,
+                Err(RecvTimeoutError::Timeout) => {
+                    report.forced = true;
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.latch.set_low()?;
+        Ok(report)
+    }
+
+    pub fn monitor(&self) -> &SupplyMonitor<Backend> {
+        &self.monitor
+    }
+
+    pub fn monitor_mut(&mut self) -> &mut SupplyMonitor<Backend> {
+        &mut self.monitor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+    use crate::supply::{FakeSupply, SupplyInput, SupplyThresholds};
+
+    #[derive(Clone, Default)]
+    struct FakeLatch(Arc<AtomicBool>);
+
+    impl ErrorType for FakeLatch {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for FakeLatch {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn manager(latch: &FakeLatch, config: PowerConfig) -> PowerManager<FakeLatch, FakeSupply> {
+        let mut supply = FakeSupply::default();
+        supply.set(SupplyInput::K30, 24_000);
+        supply.set(SupplyInput::K15A, 24_000);
+        let monitor = SupplyMonitor::with_inputs(
+            supply,
+            [SupplyInput::K30, SupplyInput::K15A],
+            SupplyThresholds::default(),
+        );
+        PowerManager::new(latch.clone(), monitor, config).unwrap()
+    }
+
+    #[test]
+    fn waits_for_the_ignition_to_stay_off() {
+        let latch = FakeLatch::default();
+        let mut power = manager(
+            &latch,
+            PowerConfig {
+                ignition_off_delay: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+        assert!(latch.0.load(Ordering::SeqCst));
+        assert_eq!(power.poll().unwrap().0, PowerState::IgnitionOn);
+        power.monitor_mut().backend_mut().set(SupplyInput::K15A, 0);
+        assert!(matches!(
+            power.poll().unwrap().0,
+            PowerState::IgnitionOff { .. }
+        ));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(power.poll().unwrap().0, PowerState::ShutdownRequested);
+    }
+
+    #[test]
+    fn runs_hooks_in_order_then_releases_power() {
+        let latch = FakeLatch::default();
+        let mut power = manager(&latch, PowerConfig::default());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for name in ["outputs", "logs", "counters"] {
+            let order = order.clone();
+            power.register_hook(name, move || {
+                order.lock().unwrap().push(name);
+                if name == "logs" {
+                    Err("disk full".to_owned())
+                } else {
+                    Ok(())
+                }
+            });
+        }
+        let report = power.shutdown().unwrap();
+        assert_eq!(*order.lock().unwrap(), ["outputs", "logs", "counters"]);
+        assert_eq!(
+            report.hooks,
+            [
+                ("outputs".to_owned(), HookOutcome::Done),
+                (
+                    "logs".to_owned(),
+                    HookOutcome::Failed("disk full".to_owned())
+                ),
+                ("counters".to_owned(), HookOutcome::Done),
+            ]
+        );
+        assert!(!report.forced);
+        assert!(!latch.0.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn forces_power_off_when_a_hook_hangs() {
+        let latch = FakeLatch::default();
+        let mut power = manager(
+            &latch,
+            PowerConfig {
+                deadline: Duration::from_millis(100),
+                ..Default::default()
+            },
+        );
+        power.register_hook("hangs", || {
+            thread::sleep(Duration::from_secs(5));
+            Ok(())
+        });
+        power.register_hook("after", || Ok(()));
+        let started = Instant::now();
+        let report = power.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(report.forced);
+        assert_eq!(report.hooks[0].1, HookOutcome::TimedOut);
+        assert_eq!(report.hooks[1].1, HookOutcome::NotRun);
+        assert!(!latch.0.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+use go_module_base::{ModuleDriver, ModuleIdentity, ModuleStatus};
+use go_modules::any_module::{
+    AnyModule, AnyModuleSetpoint, AnyModuleValues, ChannelValue, ModuleKind,
+};
+use linux_embedded_hal::SpidevDevice;
+
+use crate::{
+    gpio::GpioInterrupt,
+    io_config::{ControllerIo, LinuxAnyModule, Signal},
+    reset_line::ResetLine,
+    scheduler::{CycleStats, InputSnapshot, Outputs, Scheduler, SchedulerBuilder, SchedulerError},
+    ControllerSlot,
+};
+
+pub type ModuleError = <LinuxAnyModule as ModuleDriver>::Error;
+
+///A module run by the I/O task, as it was when the process image was started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageModule {
+    pub slot: ControllerSlot,
+    pub kind: ModuleKind,
+    pub identity: ModuleIdentity,
+}
+
+///PLC style process image: one I/O task owns every module and exchanges all of them each cycle,
+///application threads read the inputs of a whole cycle and write output requests without touching SPI
+pub struct ProcessImage<
+    SPI = SpidevDevice,
+    ResetPin = ResetLine,
+    InterruptPin = GpioInterrupt,
+    Delay = linux_embedded_hal::Delay,
+> where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    scheduler: Scheduler<AnyModule<SPI, ResetPin, InterruptPin, Delay>>,
+    signals: Arc<BTreeMap<String, Signal>>,
+    modules: Vec<ImageModule>,
+}
+
+type DriverError<SPI, ResetPin, InterruptPin, Delay> =
+    <AnyModule<SPI, ResetPin, InterruptPin, Delay> as ModuleDriver>::Error;
+
+impl<SPI, ResetPin, InterruptPin, Delay> ProcessImage<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice + Send + 'static,
+    SPI::Error: Send + Sync,
+    ResetPin: OutputPin + Send + 'static,
+    ResetPin::Error: Send + Sync,
+    InterruptPin: InputPin + Send + 'static,
+    InterruptPin::Error: Send + Sync,
+    Delay: DelayNs + Send + 'static,
+{
+    ///Start the I/O task, it exchanges with all modules every `period`, with SCHED_FIFO `priority` when set.
+    ///The outputs start switched off, on failure the I/O is handed back
+    #[allow(clippy::type_complexity)]
+    pub fn start(
+        io: ControllerIo<SPI, ResetPin, InterruptPin, Delay>,
+        period: Duration,
+        priority: Option<i32>,
+    ) -> Result<
+        Self,
+        (
+            SchedulerError,
+            ControllerIo<SPI, ResetPin, InterruptPin, Delay>,
+        ),
+    > {
+        let (modules, signals) = io.into_parts();
+        let image_modules = modules
+            .iter()
+            .map(|(slot, module)| ImageModule {
+                slot: *slot,
+                kind: module.kind(),
+                identity: module.identity(),
+            })
+            .collect();
+        let mut builder = SchedulerBuilder::new();
+        if let Some(priority) = priority {
+            builder = builder.priority(priority);
+        }
+        for (slot, module) in modules {
+            let setpoint = AnyModuleSetpoint::off(module.kind());
+            builder = builder.module(period, slot, module, setpoint);
+        }
+        match builder.start() {
+            Ok(scheduler) => Ok(ProcessImage {
+                scheduler,
+                signals: Arc::new(signals),
+                modules: image_modules,
+            }),
+            Err((err, modules)) => Err((err, ControllerIo::from_parts(modules, signals))),
+        }
+    }
+
+    ///The inputs of the last finished cycle, None before the first cycle is done
+    pub fn inputs(&self) -> Option<InputImage<DriverError<SPI, ResetPin, InterruptPin, Delay>>> {
+        let snapshot = self.scheduler.snapshots().into_iter().next()?.1?;
+        Some(InputImage {
+            snapshot,
+            signals: self.signals.clone(),
+        })
+    }
+
+    ///Request outputs, everything written in `write` is sent to the modules in the same cycle
+    pub fn write<R>(&self, write: impl FnOnce(&mut OutputImage) -> R) -> R {
+        self.scheduler.set_outputs(|outputs| {
+            write(&mut OutputImage {
+                outputs,
+                signals: &self.signals,
+            })
+        })
+    }
+
+    pub fn modules(&self) -> &[ImageModule] {
+        &self.modules
+    }
+
+    pub fn signals(&self) -> impl Iterator<Item = (&str, &Signal)> {
+        self.signals
+            .iter()
+            .map(|(name, signal)| (name.as_str(), signal))
+    }
+
+    pub fn stats(&self) -> CycleStats {
+        self.scheduler
+            .stats()
+            .into_iter()
+            .next()
+            .map(|(_, stats)| stats)
+            .unwrap_or_default()
+    }
+
+    ///Stop the I/O task after its current cycle, the outputs keep the last requested values.
+    ///When the I/O task panicked its modules are lost, their slots are returned with the I/O
+    pub fn stop(
+        self,
+    ) -> (
+        ControllerIo<SPI, ResetPin, InterruptPin, Delay>,
+        Vec<ControllerSlot>,
+    ) {
+        let (modules, lost) = self.scheduler.stop();
+        (
+            ControllerIo::from_parts(modules, Arc::unwrap_or_clone(self.signals)),
+            lost,
+        )
+    }
+}
+
+///The inputs of all modules as read in one cycle
+pub struct InputImage<Error = ModuleError> {
+    snapshot: Arc<InputSnapshot<AnyModuleValues, Error>>,
+    signals: Arc<BTreeMap<String, Signal>>,
+}
+
+//derived Clone would require the error to be Clone
+impl<Error> Clone for InputImage<Error> {
+    fn clone(&self) -> Self {
+        InputImage {
+            snapshot: self.snapshot.clone(),
+            signals: self.signals.clone(),
+        }
+    }
+}
+
+impl<Error> InputImage<Error> {
+    pub fn cycle(&self) -> u64 {
+        self.snapshot.cycle
+    }
+
+    ///When the I/O of the cycle was finished
+    pub fn time(&self) -> Instant {
+        self.snapshot.time
+    }
+
+    ///What the module in a slot answered, or why the exchange with it failed
+    pub fn module(&self, slot: ControllerSlot) -> Option<&Result<AnyModuleValues, Error>> {
+        self.snapshot.get(slot)
+    }
+
+    ///Communication statistics of the module in a slot after the cycle
+    pub fn status(&self, slot: ControllerSlot) -> Option<ModuleStatus> {
+        self.snapshot.status(slot)
+    }
+
+    ///Value of a channel, None when the module has no such channel or the exchange failed this cycle
+    pub fn channel(&self, slot: ControllerSlot, channel: u8) -> Option<ChannelValue> {
+        self.module(slot)?.as_ref().ok()?.channel(channel)
+    }
+
+    pub fn signal(&self, name: &str) -> Option<ChannelValue> {
+        let signal = self.signals.get(name)?;
+        self.channel(signal.slot, signal.channel)
+    }
+
+    ///Every exchange of the cycle succeeded
+    pub fn is_complete(&self) -> bool {
+        self.snapshot
+            .modules
+            .iter()
+            .all(|(_, values)| values.is_ok())
+    }
+}
+
+///Output requests, read by the I/O task at the start of its next cycle
+pub struct OutputImage<'a> {
+    outputs: &'a mut Outputs<AnyModuleSetpoint>,
+    signals: &'a BTreeMap<String, Signal>,
+}
+
+impl OutputImage<'_> {
+    pub fn channel(&self, slot: ControllerSlot, channel: u8) -> Option<i32> {
+        self.outputs.get(slot)?.channel(channel)
+    }
+
+    ///Request a setpoint for a channel, returns false when there is no such output
+    ///or the value is out of range for it
+    pub fn set_channel(&mut self, slot: ControllerSlot, channel: u8, value: i32) -> bool {
+        self.outputs
+            .get_mut(slot)
+            .is_some_and(|setpoint| setpoint.set_channel(channel, value))
+    }
+
+    pub fn signal(&self, name: &str) -> Option<i32> {
+        let signal = self.signals.get(name)?;
+        self.channel(signal.slot, signal.channel)
+    }
+
+    pub fn set_signal(&mut self, name: &str, value: i32) -> bool {
+        match self.signals.get(name) {
+            Some(signal) => self.set_channel(signal.slot, signal.channel, value),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use go_module_base::{
+        mock::{module_frame, NoDelay, ScriptedModule, ScriptedPin, ScriptedSpi},
+        ModuleCommunicationType,
+    };
+    use go_modules::any_module::AnyModuleConfiguration;
+
+    use super::*;
+
+    type ScriptedImage = ProcessImage<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>;
+    type ScriptedInputs = InputImage<DriverError<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>>;
+
+    ///Feedback of an output module, the temperature numbers the exchange
+    fn feedback(exchange: i16) -> Vec<u8> {
+        module_frame(
+            22,
+            ModuleCommunicationType::Feedback,
+            1,
+            &exchange.to_le_bytes(),
+            44,
+        )
+    }
+
+    ///Output modules in slot 1 and 2 that answer 5000 exchanges, with a signal on each
+    fn start() -> (ScriptedImage, [ScriptedSpi; 2]) {
+        let mut modules = Vec::new();
+        let mut spis = Vec::new();
+        for slot in [ControllerSlot::Slot1, ControllerSlot::Slot2] {
+            let (module, spi) =
+                ScriptedModule::identifying_as(slot.number(), ModuleKind::Output6Channel.id());
+            for exchange in 1..=5000 {
+                spi.reply(feedback(exchange));
+            }
+            let module = AnyModule::build(
+                module,
+                AnyModuleConfiguration::default_for(ModuleKind::Output6Channel),
+            )
+            .ok()
+            .unwrap();
+            modules.push((slot, module));
+            spis.push(spi);
+        }
+        let signals = [
+            ("left", ControllerSlot::Slot1, 1),
+            ("right", ControllerSlot::Slot2, 6),
+        ]
+        .into_iter()
+        .map(|(name, slot, channel)| {
+            (
+                name.to_owned(),
+                Signal {
+                    slot,
+                    channel,
+                    kind: ModuleKind::Output6Channel,
+                },
+            )
+        })
+        .collect();
+        let image = ProcessImage::start(
+            ControllerIo::from_parts(modules, signals),
+            Duration::from_millis(1),
+            None,
+        )
+        .ok()
+        .unwrap();
+        (image, spis.try_into().ok().unwrap())
+    }
+
+    fn wait_for_cycle(image: &ScriptedImage, cycle: u64) -> ScriptedInputs {
+        loop {
+            if let Some(inputs) = image.inputs().filter(|inputs| inputs.cycle() >= cycle) {
+                return inputs;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn exchange(inputs: &ScriptedInputs, slot: ControllerSlot) -> i16 {
+        match inputs.module(slot) {
+            Some(Ok(AnyModuleValues::Output6Channel(values))) => values.temperature,
+            _ => panic!("no feedback from {slot:?} in cycle {}", inputs.cycle()),
+        }
+    }
+
+    #[test]
+    fn inputs_of_all_modules_come_from_the_same_cycle() {
+        let (image, _spis) = start();
+        let mut previous = None;
+        for cycle in [2, 5, 10, 20] {
+            let inputs = wait_for_cycle(&image, cycle);
+            let left = exchange(&inputs, ControllerSlot::Slot1);
+            assert_eq!(left, exchange(&inputs, ControllerSlot::Slot2));
+            //one exchange per module and cycle
+            if let Some((previous_cycle, previous_exchange)) = previous {
+                assert_eq!(
+                    inputs.cycle() - previous_cycle,
+                    (left - previous_exchange) as u64
+                );
+            }
+            previous = Some((inputs.cycle(), left));
+        }
+        image.stop();
+    }
+
+    #[test]
+    fn applies_one_write_in_one_cycle() {
+        let (image, spis) = start();
+        wait_for_cycle(&image, 3);
+        assert!(image.write(|outputs| {
+            outputs.set_signal("left", 1000)
+                && outputs.set_channel(ControllerSlot::Slot1, 2, 2000)
+                && outputs.set_signal("right", 3000)
+        }));
+        let after = wait_for_cycle(&image, image.inputs().unwrap().cycle() + 3);
+        assert!(after.is_complete());
+        image.stop();
+
+        //the escape, both configuration frames and the first feedback come before the cycles
+        let changed = spis.map(|spi| {
+            let frames = spi.written().split_off(4);
+            let first = frames.iter().position(|frame| *frame != frames[0]).unwrap();
+            //both channels of slot 1 changed at once, nothing changed after
+            assert!(frames[first..].iter().all(|frame| *frame == frames[first]));
+            first
+        });
+        assert_eq!(changed[0], changed[1]);
+    }
+
+    #[test]
+    fn hands_the_io_back_when_stopped() {
+        let (image, _spis) = start();
+        wait_for_cycle(&image, 1);
+        assert_eq!(
+            image
+                .modules()
+                .iter()
+                .map(|module| module.slot)
+                .collect::<Vec<_>>(),
+            [ControllerSlot::Slot1, ControllerSlot::Slot2]
+        );
+        let (mut io, lost) = image.stop();
+        assert!(lost.is_empty());
+        assert_eq!(
+            io.slots().collect::<Vec<_>>(),
+            [ControllerSlot::Slot1, ControllerSlot::Slot2]
+        );
+        assert_eq!(io.signal("right").unwrap().channel, 6);
+        //the modules can be driven again without the I/O task
+        let module = io.module_mut(ControllerSlot::Slot1).unwrap();
+        assert_eq!(module.kind(), ModuleKind::Output6Channel);
+        assert!(module.safe_state().is_ok());
+    }
+
+    #[test]
+    fn can_be_shared_with_application_threads() {
+        fn shared<T: Send + Sync>() {}
+        shared::<ProcessImage>();
+        shared::<InputImage>();
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    gpio::{GpioError, GpioOutput},
+    slot_map::{GpioSpec, ResetSpec},
+    sysfs_reset::{SysfsOutput, SysfsOutputError},
+};
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+use std::path::Path;
+
+///How the module in a slot is reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetKind {
+    ///A gpio line per slot
+    Gpio,
+    ///An LED per slot in the sysfs LED class, see [crate::sysfs_reset::SysfsOutput]
+    SysfsLed,
+}
+
+///The reset of a module slot, depending on the controller this is a gpio line or a sysfs LED
+pub enum ResetLine {
+    Gpio(GpioOutput),
+    Sysfs(SysfsOutput),
+}
+
+#[derive(Debug)]
+pub enum ResetLineError {
+    Gpio(GpioError),
+    Sysfs(SysfsOutputError),
+}
+
+///Why a reset line could not be opened
+#[derive(Debug)]
+pub enum ResetLineOpenError {
+    NoSuchSlot,
+    Gpio(gpiocdev::Error),
+    Led(std::io::Error),
+    ///Neither of the interfaces of an automatic reset line could be opened
+    Unavailable {
+        gpio: gpiocdev::Error,
+        led: std::io::Error,
+    },
+}
+
+impl ResetLine {
+    ///Open a reset line as described in a slot map,
+    ///`label` is the consumer name of the gpio line
+    pub fn open(spec: &ResetSpec, label: &str) -> Result<Self, ResetLineOpenError> {
+        Self::open_with_root(spec, label, "/sys")
+    }
+
+    ///Open a reset line as described in a slot map, LEDs are looked up below a sysfs root, normally `/sys`
+    pub fn open_with_root(
+        spec: &ResetSpec,
+        label: &str,
+        root: impl AsRef<Path>,
+    ) -> Result<Self, ResetLineOpenError> {
+        let root = root.as_ref();
+        match spec {
+            ResetSpec::Gpio(gpio) => Self::gpio(gpio, label).map_err(ResetLineOpenError::Gpio),
+            ResetSpec::Led(name) => {
+                Self::led_with_root(root, name).map_err(ResetLineOpenError::Led)
+            }
+            ResetSpec::Auto {
+                prefer: ResetKind::Gpio,
+                gpio,
+                led,
+            } => Self::gpio(gpio, label).or_else(|gpio| {
+                Self::led_with_root(root, led)
+                    .map_err(|led| ResetLineOpenError::Unavailable { gpio, led })
+            }),
+            ResetSpec::Auto {
+                prefer: ResetKind::SysfsLed,
+                gpio,
+                led,
+            } => Self::led_with_root(root, led).or_else(|led| {
+                Self::gpio(gpio, label)
+                    .map_err(|gpio| ResetLineOpenError::Unavailable { gpio, led })
+            }),
+        }
+    }
+
+    pub fn gpio(gpio: &GpioSpec, label: &str) -> Result<Self, gpiocdev::Error> {
+        GpioOutput::new(gpio, label).map(ResetLine::Gpio)
+    }
+
+    pub fn led(name: &str) -> Result<Self, std::io::Error> {
+        Self::led_with_root("/sys", name)
+    }
+
+    ///The LED with the given name below a sysfs root, normally `/sys`
+    pub fn led_with_root(root: impl AsRef<Path>, name: &str) -> Result<Self, std::io::Error> {
+        SysfsOutput::from_led_with_root(root, name).map(ResetLine::Sysfs)
+    }
+
+    pub fn kind(&self) -> ResetKind {
+        match self {
+            ResetLine::Gpio(_) => ResetKind::Gpio,
+            ResetLine::Sysfs(_) => ResetKind::SysfsLed,
+        }
+    }
+}
+
+impl embedded_hal::digital::Error for ResetLineError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl ErrorType for ResetLine {
+    type Error = ResetLineError;
+}
+
+impl OutputPin for ResetLine {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        match self {
+            ResetLine::Gpio(pin) => pin.set_low().map_err(ResetLineError::Gpio),
+            ResetLine::Sysfs(pin) => pin.set_low().map_err(ResetLineError::Sysfs),
+        }
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        match self {
+            ResetLine::Gpio(pin) => pin.set_high().map_err(ResetLineError::Gpio),
+            ResetLine::Sysfs(pin) => pin.set_high().map_err(ResetLineError::Sysfs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///A sysfs root with the given LEDs
+    fn sysfs(leds: &[&str]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for led in leds {
+            let dir = root.path().join("class/leds").join(led);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("brightness"), "0").unwrap();
+        }
+        root
+    }
+
+    fn auto(prefer: ResetKind) -> ResetSpec {
+        ResetSpec::Auto {
+            prefer,
+            gpio: GpioSpec {
+                chip: "gpiochip-that-does-not-exist".to_owned(),
+                line: 7,
+                bias: None,
+            },
+            led: "ResetM-1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn drives_an_led_below_the_root() {
+        let root = sysfs(&["ResetM-1"]);
+        let spec = ResetSpec::Led("ResetM-1".to_owned());
+        let mut line = ResetLine::open_with_root(&spec, "test", root.path()).unwrap();
+        let brightness = root.path().join("class/leds/ResetM-1/brightness");
+        line.set_high().unwrap();
+        assert_eq!(std::fs::read_to_string(&brightness).unwrap(), "1");
+        line.set_low().unwrap();
+        assert_eq!(std::fs::read_to_string(&brightness).unwrap(), "0");
+    }
+
+    #[test]
+    fn falls_back_to_the_led_when_the_gpio_is_missing() {
+        let root = sysfs(&["ResetM-1"]);
+        for prefer in [ResetKind::Gpio, ResetKind::SysfsLed] {
+            let line = ResetLine::open_with_root(&auto(prefer), "test", root.path()).unwrap();
+            assert_eq!(line.kind(), ResetKind::SysfsLed);
+        }
+    }
+
+    #[test]
+    fn reports_both_errors_when_neither_interface_is_there() {
+        let root = sysfs(&["ResetM-2"]);
+        for prefer in [ResetKind::Gpio, ResetKind::SysfsLed] {
+            match ResetLine::open_with_root(&auto(prefer), "test", root.path()) {
+                Err(ResetLineOpenError::Unavailable { led, .. }) => {
+                    assert_eq!(led.kind(), std::io::ErrorKind::NotFound)
+                }
+                Err(err) => panic!("expected both interfaces to be unavailable, got {err:?}"),
+                Ok(_) => panic!("opened a reset line that does not exist"),
+            }
+        }
+        assert!(matches!(
+            ResetLine::open_with_root(&ResetSpec::Led("ResetM-1".to_owned()), "test", root.path()),
+            Err(ResetLineOpenError::Led(_))
+        ));
+    }
+}
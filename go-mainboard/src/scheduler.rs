@@ -0,0 +1,633 @@
+use std::{
+    io, mem,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use go_module_base::{ModuleDriver, ModuleStatus};
+
+use crate::ControllerSlot;
+
+#[derive(Debug)]
+pub enum SchedulerError {
+    ///No modules were added to the scheduler
+    NoModules,
+    ///A module was added with a period of 0
+    ZeroPeriod,
+    ///More than one module was added for this slot
+    DuplicateSlot(ControllerSlot),
+    ///A cycle group could not get its realtime priority, usually because the process lacks CAP_SYS_NICE
+    Priority(io::Error),
+    Spawn(io::Error),
+}
+
+///Timing statistics of a cycle group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    pub cycles: u64,
+    ///Cycles whose I/O was not finished when the next cycle was due
+    pub overruns: u64,
+    ///Cycles that were left out to get back on schedule after an overrun
+    pub skipped: u64,
+    ///How late the last cycle started
+    pub last_jitter: Duration,
+    pub max_jitter: Duration,
+    ///Time the I/O of the last cycle took
+    pub last_execution: Duration,
+    pub max_execution: Duration,
+    total_jitter: Duration,
+}
+
+impl CycleStats {
+    pub fn mean_jitter(&self) -> Duration {
+        if self.cycles == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.total_jitter.as_nanos() / self.cycles as u128) as u64)
+    }
+}
+
+///What the modules of a cycle group answered in one cycle, all taken in the same cycle
+pub struct InputSnapshot<Values, Error> {
+    pub cycle: u64,
+    ///When the I/O of the cycle was finished
+    pub time: Instant,
+    pub modules: Vec<(ControllerSlot, Result<Values, Error>)>,
+    ///Communication statistics of the modules after the cycle, in the same order as `modules`
+    pub status: Vec<ModuleStatus>,
+}
+
+impl<Values, Error> InputSnapshot<Values, Error> {
+    pub fn get(&self, slot: ControllerSlot) -> Option<&Result<Values, Error>> {
+        self.modules
+            .iter()
+            .find(|(module_slot, _)| *module_slot == slot)
+            .map(|(_, values)| values)
+    }
+
+    pub fn status(&self, slot: ControllerSlot) -> Option<ModuleStatus> {
+        self.modules
+            .iter()
+            .position(|(module_slot, _)| *module_slot == slot)
+            .map(|index| self.status[index])
+    }
+}
+
+///The setpoints of all modules, changes made in one call to [Scheduler::set_outputs] are sent in the same cycle
+pub struct Outputs<Setpoint> {
+    setpoints: Vec<(ControllerSlot, Setpoint)>,
+}
+
+impl<Setpoint> Outputs<Setpoint> {
+    pub fn get(&self, slot: ControllerSlot) -> Option<&Setpoint> {
+        self.setpoints
+            .iter()
+            .find(|(module_slot, _)| *module_slot == slot)
+            .map(|(_, setpoint)| setpoint)
+    }
+
+    pub fn get_mut(&mut self, slot: ControllerSlot) -> Option<&mut Setpoint> {
+        self.setpoints
+            .iter_mut()
+            .find(|(module_slot, _)| *module_slot == slot)
+            .map(|(_, setpoint)| setpoint)
+    }
+
+    ///Replace the setpoint of the module in a slot, returns false when there is no module in the slot
+    pub fn set(&mut self, slot: ControllerSlot, setpoint: Setpoint) -> bool {
+        match self.get_mut(slot) {
+            Some(current) => {
+                *current = setpoint;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+type Snapshot<D> = Arc<InputSnapshot<<D as ModuleDriver>::Values, <D as ModuleDriver>::Error>>;
+
+struct GroupShared<D: ModuleDriver> {
+    period: Duration,
+    ///Where the setpoints of the modules in this group are in [Outputs]
+    outputs: Range<usize>,
+    slots: Vec<ControllerSlot>,
+    inputs: Mutex<Option<Snapshot<D>>>,
+    stats: Mutex<CycleStats>,
+}
+
+struct Shared<D: ModuleDriver> {
+    running: AtomicBool,
+    outputs: Mutex<Outputs<D::Setpoint>>,
+    groups: Vec<GroupShared<D>>,
+}
+
+type GroupModules<D> = Vec<(ControllerSlot, D, <D as ModuleDriver>::Setpoint)>;
+
+///Collects the modules and the cycle time each of them runs at before starting the scheduler
+pub struct SchedulerBuilder<D: ModuleDriver> {
+    groups: Vec<(Duration, GroupModules<D>)>,
+    priority: Option<i32>,
+}
+
+impl<D: ModuleDriver> Default for SchedulerBuilder<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: ModuleDriver> SchedulerBuilder<D> {
+    pub fn new() -> Self {
+        SchedulerBuilder {
+            groups: Vec::new(),
+            priority: None,
+        }
+    }
+
+    ///Run the cycle groups as SCHED_FIFO threads with this priority (1 to 99)
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    ///Exchange with the module every `period`, modules with the same period run one after
+    ///the other in the same cycle group, `setpoint` is sent until it is changed
+    pub fn module(
+        mut self,
+        period: Duration,
+        slot: ControllerSlot,
+        module: D,
+        setpoint: D::Setpoint,
+    ) -> Self {
+        match self.groups.iter_mut().find(|(group, _)| *group == period) {
+            Some((_, modules)) => modules.push((slot, module, setpoint)),
+            None => self.groups.push((period, vec![(slot, module, setpoint)])),
+        }
+        self
+    }
+
+    ///Start a thread for every cycle group, when a period is 0, a slot has more than one module or
+    ///the groups can't all get their priority the modules are handed back
+    #[allow(clippy::type_complexity)]
+    pub fn start(self) -> Result<Scheduler<D>, (SchedulerError, Vec<(ControllerSlot, D)>)>
+    where
+        D: Send + 'static,
+        D::Setpoint: Clone + Send,
+        D::Values: Send + Sync,
+        D::Error: Send + Sync,
+    {
+        if self.groups.is_empty() {
+            return Err((SchedulerError::NoModules, Vec::new()));
+        }
+        let mut slots = Vec::new();
+        let duplicate = self
+            .groups
+            .iter()
+            .flat_map(|(_, modules)| modules)
+            .map(|(slot, _, _)| *slot)
+            .find(|slot| {
+                let seen = slots.contains(slot);
+                slots.push(*slot);
+                seen
+            });
+        let error = if self.groups.iter().any(|(period, _)| period.is_zero()) {
+            Some(SchedulerError::ZeroPeriod)
+        } else {
+            duplicate.map(SchedulerError::DuplicateSlot)
+        };
+        if let Some(error) = error {
+            let modules = self
+                .groups
+                .into_iter()
+                .flat_map(|(_, modules)| modules)
+                .map(|(slot, module, _)| (slot, module))
+                .collect();
+            return Err((error, modules));
+        }
+        let mut setpoints = Vec::new();
+        let mut groups = Vec::new();
+        let mut group_modules = Vec::new();
+        for (period, modules) in self.groups {
+            let start = setpoints.len();
+            let mut slots = Vec::new();
+            let mut drivers = Vec::new();
+            for (slot, module, setpoint) in modules {
+                slots.push(slot);
+                drivers.push((slot, module));
+                setpoints.push((slot, setpoint));
+            }
+            groups.push(GroupShared {
+                period,
+                outputs: start..setpoints.len(),
+                slots,
+                inputs: Mutex::new(None),
+                stats: Mutex::new(CycleStats::default()),
+            });
+            group_modules.push(drivers);
+        }
+        let shared = Arc::new(Shared {
+            running: AtomicBool::new(true),
+            outputs: Mutex::new(Outputs { setpoints }),
+            groups,
+        });
+
+        //the groups only start cycling once every thread is running with its priority
+        let (ready_sender, ready) = mpsc::channel();
+        let mut starts = Vec::new();
+        let mut threads = Vec::new();
+        let mut error = None;
+        let mut group_modules = group_modules.into_iter().enumerate();
+        for (index, modules) in group_modules.by_ref() {
+            let shared = shared.clone();
+            let ready_sender = ready_sender.clone();
+            let (start_sender, start) = mpsc::channel::<()>();
+            let priority = self.priority;
+            let thread = thread::Builder::new()
+                .name(format!(
+                    "go-io-{}ms",
+                    shared.groups[index].period.as_millis()
+                ))
+                .spawn(move || {
+                    let _ = ready_sender.send(priority.map_or(Ok(()), set_fifo_priority));
+                    //the sender is dropped without sending when the scheduler does not start
+                    match start.recv() {
+                        Ok(()) => run_group(index, modules, &shared),
+                        Err(_) => modules,
+                    }
+                });
+            match thread {
+                Ok(thread) => {
+                    threads.push(thread);
+                    starts.push(start_sender);
+                }
+                Err(err) => {
+                    error = Some(SchedulerError::Spawn(err));
+                    break;
+                }
+            }
+        }
+        for _ in 0..threads.len() {
+            if let Ok(Err(err)) = ready.recv() {
+                error.get_or_insert(SchedulerError::Priority(err));
+            }
+        }
+
+        let mut scheduler = Scheduler { shared, threads };
+        match error {
+            None => {
+                for start in starts {
+                    let _ = start.send(());
+                }
+                Ok(scheduler)
+            }
+            Some(error) => {
+                drop(starts);
+                let mut modules: Vec<_> = group_modules.flat_map(|(_, modules)| modules).collect();
+                //the groups never exchanged, so there is nothing a thread could have panicked on
+                modules.extend(scheduler.join().0);
+                Err((error, modules))
+            }
+        }
+    }
+}
+
+///Runs the I/O of every module at its cycle time, with absolute deadlines so the cycles don't drift
+pub struct Scheduler<D: ModuleDriver> {
+    shared: Arc<Shared<D>>,
+    threads: Vec<JoinHandle<Vec<(ControllerSlot, D)>>>,
+}
+
+impl<D: ModuleDriver> Scheduler<D> {
+    ///The latest inputs of the cycle group the module in a slot is in
+    pub fn inputs(&self, slot: ControllerSlot) -> Option<Snapshot<D>> {
+        self.group(slot)
+            .and_then(|group| group.inputs.lock().unwrap().clone())
+    }
+
+    ///The latest inputs of every cycle group, with the period of the group
+    pub fn snapshots(&self) -> Vec<(Duration, Option<Snapshot<D>>)> {
+        self.shared
+            .groups
+            .iter()
+            .map(|group| (group.period, group.inputs.lock().unwrap().clone()))
+            .collect()
+    }
+
+    ///Change setpoints, everything changed in `update` is sent in the same cycle
+    pub fn set_outputs<R>(&self, update: impl FnOnce(&mut Outputs<D::Setpoint>) -> R) -> R {
+        update(&mut self.shared.outputs.lock().unwrap())
+    }
+
+    ///Timing statistics of every cycle group, with the period of the group
+    pub fn stats(&self) -> Vec<(Duration, CycleStats)> {
+        self.shared
+            .groups
+            .iter()
+            .map(|group| (group.period, *group.stats.lock().unwrap()))
+            .collect()
+    }
+
+    ///The slots in each cycle group, with the period of the group
+    pub fn groups(&self) -> impl Iterator<Item = (Duration, &[ControllerSlot])> {
+        self.shared
+            .groups
+            .iter()
+            .map(|group| (group.period, group.slots.as_slice()))
+    }
+
+    ///Stop after the current cycles and hand back the modules, their outputs keep the last setpoint.
+    ///The modules of a cycle group whose thread panicked are lost, their slots are returned next
+    ///to the modules
+    #[allow(clippy::type_complexity)]
+    pub fn stop(mut self) -> (Vec<(ControllerSlot, D)>, Vec<ControllerSlot>) {
+        self.shared.running.store(false, Ordering::Release);
+        self.join()
+    }
+
+    fn group(&self, slot: ControllerSlot) -> Option<&GroupShared<D>> {
+        self.shared
+            .groups
+            .iter()
+            .find(|group| group.slots.contains(&slot))
+    }
+
+    ///The modules handed back by the group threads and the slots of the groups whose thread panicked
+    #[allow(clippy::type_complexity)]
+    fn join(&mut self) -> (Vec<(ControllerSlot, D)>, Vec<ControllerSlot>) {
+        let mut modules = Vec::new();
+        let mut lost = Vec::new();
+        //the threads are in the same order as the groups
+        for (thread, group) in mem::take(&mut self.threads)
+            .into_iter()
+            .zip(&self.shared.groups)
+        {
+            match thread.join() {
+                Ok(group_modules) => modules.extend(group_modules),
+                Err(_) => lost.extend_from_slice(&group.slots),
+            }
+        }
+        (modules, lost)
+    }
+}
+
+impl<D: ModuleDriver> Drop for Scheduler<D> {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+        self.join();
+    }
+}
+
+fn run_group<D>(
+    index: usize,
+    mut modules: Vec<(ControllerSlot, D)>,
+    shared: &Shared<D>,
+) -> Vec<(ControllerSlot, D)>
+where
+    D: ModuleDriver,
+    D::Setpoint: Clone,
+{
+    let group = &shared.groups[index];
+    let mut setpoints = Vec::with_capacity(modules.len());
+    let mut deadline = monotonic_now();
+    let mut cycle = 0;
+    while shared.running.load(Ordering::Acquire) {
+        sleep_until(deadline);
+        let start = monotonic_now();
+        setpoints.clear();
+        setpoints.extend(
+            shared.outputs.lock().unwrap().setpoints[group.outputs.clone()]
+                .iter()
+                .map(|(_, setpoint)| setpoint.clone()),
+        );
+        let values = modules
+            .iter_mut()
+            .zip(&setpoints)
+            .map(|((slot, module), setpoint)| (*slot, module.exchange(setpoint)))
+            .collect();
+        let end = monotonic_now();
+        cycle += 1;
+        *group.inputs.lock().unwrap() = Some(Arc::new(InputSnapshot {
+            cycle,
+            time: Instant::now(),
+            modules: values,
+            status: modules.iter().map(|(_, module)| module.status()).collect(),
+        }));
+
+        let mut stats = group.stats.lock().unwrap();
+        let jitter = start.saturating_sub(deadline);
+        let execution = end - start;
+        stats.cycles += 1;
+        stats.last_jitter = jitter;
+        stats.max_jitter = stats.max_jitter.max(jitter);
+        stats.total_jitter += jitter;
+        stats.last_execution = execution;
+        stats.max_execution = stats.max_execution.max(execution);
+        deadline += group.period;
+        if end > deadline {
+            stats.overruns += 1;
+            while end > deadline {
+                deadline += group.period;
+                stats.skipped += 1;
+            }
+        }
+    }
+    modules
+}
+
+///Time on the monotonic clock, the clock the deadlines of clock_nanosleep are on
+fn monotonic_now() -> Duration {
+    // SAFETY: timespec is plain data, zeroed is a valid value
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    // SAFETY: now is a valid timespec to write to, CLOCK_MONOTONIC is always available on linux
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+fn sleep_until(deadline: Duration) {
+    // SAFETY: timespec is plain data, zeroed is a valid value
+    let mut time: libc::timespec = unsafe { mem::zeroed() };
+    time.tv_sec = deadline.as_secs() as libc::time_t;
+    time.tv_nsec = deadline.subsec_nanos() as libc::c_long;
+    //with an absolute deadline an interrupted sleep can simply be started again
+    // SAFETY: time is a valid timespec, the remaining time is not used with TIMER_ABSTIME
+    while unsafe {
+        libc::clock_nanosleep(
+            libc::CLOCK_MONOTONIC,
+            libc::TIMER_ABSTIME,
+            &time,
+            std::ptr::null_mut(),
+        )
+    } == libc::EINTR
+    {}
+}
+
+fn set_fifo_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // SAFETY: param is a valid sched_param and pthread_self is the calling thread
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::{ModuleIdentity, ModuleStatus};
+
+    use super::*;
+
+    ///Answers with the setpoint it was sent, panics on a setpoint of u16::MAX
+    struct Echo;
+
+    impl ModuleDriver for Echo {
+        type Configuration = ();
+        type Setpoint = (u16, u16);
+        type Values = (u16, u16);
+        type Module = ();
+        type Error = ();
+
+        fn identity(&self) -> ModuleIdentity {
+            ModuleIdentity::default()
+        }
+
+        fn degrade(self) -> Self::Module {}
+
+        fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+            ((), ())
+        }
+
+        fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+            assert_ne!(setpoint.0, u16::MAX, "exchange failed");
+            Ok(*setpoint)
+        }
+
+        fn status(&self) -> ModuleStatus {
+            ModuleStatus::default()
+        }
+
+        fn safe_state(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn runs_groups_and_applies_setpoints_in_one_cycle() {
+        let fast = Duration::from_millis(2);
+        let slow = Duration::from_millis(10);
+        let scheduler = SchedulerBuilder::new()
+            .module(fast, ControllerSlot::Slot1, Echo, (0, 0))
+            .module(slow, ControllerSlot::Slot2, Echo, (0, 0))
+            .module(fast, ControllerSlot::Slot3, Echo, (0, 0))
+            .start()
+            .map_err(|(err, _)| err)
+            .unwrap();
+        let groups: Vec<_> = scheduler.groups().collect();
+        assert_eq!(
+            groups,
+            [
+                (fast, &[ControllerSlot::Slot1, ControllerSlot::Slot3][..]),
+                (slow, &[ControllerSlot::Slot2][..])
+            ]
+        );
+
+        assert!(scheduler.set_outputs(|outputs| {
+            outputs.set(ControllerSlot::Slot1, (1, 1))
+                && outputs.set(ControllerSlot::Slot3, (1, 1))
+                && !outputs.set(ControllerSlot::Slot4, (1, 1))
+        }));
+        thread::sleep(Duration::from_millis(50));
+
+        let inputs = scheduler.inputs(ControllerSlot::Slot3).unwrap();
+        assert_eq!(inputs.get(ControllerSlot::Slot1), Some(&Ok((1, 1))));
+        assert_eq!(inputs.get(ControllerSlot::Slot3), Some(&Ok((1, 1))));
+        assert_eq!(inputs.get(ControllerSlot::Slot2), None);
+        let stats = scheduler.stats();
+        assert!(stats[0].1.cycles > stats[1].1.cycles);
+        assert!(stats[1].1.cycles >= 2);
+        assert!(stats[0].1.max_jitter >= stats[0].1.mean_jitter());
+
+        let (modules, lost) = scheduler.stop();
+        assert_eq!(modules.len(), 3);
+        assert!(lost.is_empty());
+    }
+
+    #[test]
+    fn reports_the_modules_of_a_panicked_group() {
+        let fast = Duration::from_millis(2);
+        let slow = Duration::from_millis(5);
+        let scheduler = SchedulerBuilder::new()
+            .module(fast, ControllerSlot::Slot1, Echo, (0, 0))
+            .module(slow, ControllerSlot::Slot2, Echo, (0, 0))
+            .module(slow, ControllerSlot::Slot3, Echo, (0, 0))
+            .start()
+            .map_err(|(err, _)| err)
+            .unwrap();
+        scheduler.set_outputs(|outputs| outputs.set(ControllerSlot::Slot3, (u16::MAX, 0)));
+        thread::sleep(Duration::from_millis(20));
+
+        let (modules, lost) = scheduler.stop();
+        let slots: Vec<_> = modules.iter().map(|(slot, _)| *slot).collect();
+        assert_eq!(slots, [ControllerSlot::Slot1]);
+        assert_eq!(lost, [ControllerSlot::Slot2, ControllerSlot::Slot3]);
+    }
+
+    #[test]
+    fn needs_modules() {
+        assert!(matches!(
+            SchedulerBuilder::<Echo>::new().start(),
+            Err((SchedulerError::NoModules, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_zero_period() {
+        let Err((err, modules)) = SchedulerBuilder::new()
+            .module(
+                Duration::from_millis(2),
+                ControllerSlot::Slot1,
+                Echo,
+                (0, 0),
+            )
+            .module(Duration::ZERO, ControllerSlot::Slot2, Echo, (0, 0))
+            .start()
+        else {
+            panic!("the scheduler started");
+        };
+        assert!(matches!(err, SchedulerError::ZeroPeriod));
+        let slots: Vec<_> = modules.iter().map(|(slot, _)| *slot).collect();
+        assert_eq!(slots, [ControllerSlot::Slot1, ControllerSlot::Slot2]);
+    }
+
+    #[test]
+    fn rejects_a_duplicate_slot() {
+        let Err((err, modules)) = SchedulerBuilder::new()
+            .module(
+                Duration::from_millis(2),
+                ControllerSlot::Slot1,
+                Echo,
+                (0, 0),
+            )
+            .module(
+                Duration::from_millis(10),
+                ControllerSlot::Slot1,
+                Echo,
+                (0, 0),
+            )
+            .start()
+        else {
+            panic!("the scheduler started");
+        };
+        assert!(matches!(
+            err,
+            SchedulerError::DuplicateSlot(ControllerSlot::Slot1)
+        ));
+        assert_eq!(modules.len(), 2);
+    }
+}
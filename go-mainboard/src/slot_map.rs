@@ -0,0 +1,643 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use go_module_base::{GoModuleUnknown, ModuleSetupError};
+use linux_embedded_hal::{Delay, SpidevDevice};
+use serde::{Deserialize, Serialize};
+use spidev::{SpiModeFlags, SpidevOptions};
+
+use crate::{
+    gpio::{GpioInterrupt, LineBias},
+    reset_line::{ResetKind, ResetLine, ResetLineOpenError},
+    slots, ControllerSlot, ControllerType, LinuxGoModule, SLOTS,
+};
+
+const DEFAULTSPISPEED: u32 = 2_000_000;
+
+///Where the hardware of every module slot of a controller can be found
+///
+///The Moduline IV table is built in, see [SlotMap::builtin]. The other controllers, custom
+///carriers and board revisions describe their slots in the device tree or in a TOML file:
+///```toml
+///[[slots]]
+///slot = 1
+///spidev = "/dev/spidev1.0"
+///spi_speed_hz = 2000000
+///spi_mode = 0
+///reset = { gpio = { chip = "gpiochip0", line = 7 } }
+///interrupt = { chip = "gpiochip0", line = 6 }
+///
+///[[slots]]
+///slot = 2
+///spidev = "/dev/spidev1.1"
+///reset = { led = "ResetM-2" }
+///interrupt = { chip = "gpiochip4", line = 20, bias = "pull_up" }
+///```
+///`spi_speed_hz` and `spi_mode` can be left out, they default to 2MHz and mode 0,
+///a gpio line without `bias` keeps the bias the kernel configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotMap {
+    pub slots: Vec<SlotConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotConfig {
+    ///Slot number as printed on the controller, starting at 1
+    pub slot: u8,
+    pub spidev: PathBuf,
+    #[serde(default = "default_spi_speed")]
+    pub spi_speed_hz: u32,
+    #[serde(default)]
+    pub spi_mode: u8,
+    pub reset: ResetSpec,
+    pub interrupt: GpioSpec,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpioSpec {
+    pub chip: String,
+    pub line: u32,
+    #[serde(default)]
+    pub bias: Option<LineBias>,
+}
+
+impl GpioSpec {
+    ///Path of the gpio chip, a bare chip name like `gpiochip0` is looked up in `/dev`
+    pub fn path(&self) -> PathBuf {
+        Path::new("/dev").join(&self.chip)
+    }
+}
+
+///A module reset is either a gpio line or an LED in the sysfs LED class
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetSpec {
+    Gpio(GpioSpec),
+    Led(String),
+    ///Use the preferred interface and fall back to the other one when the kernel does not expose it:
+    ///```toml
+    ///reset = { auto = { prefer = "sysfs_led", gpio = { chip = "gpiochip0", line = 7 }, led = "ResetM-1" } }
+    ///```
+    Auto {
+        prefer: ResetKind,
+        gpio: GpioSpec,
+        led: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum SlotMapError {
+    Io(PathBuf, std::io::Error),
+    Toml(toml::de::Error),
+    ///A device tree property is missing or does not hold the expected value
+    Devicetree(PathBuf),
+    ///The slot number is not one of the slots of a controller, slots are numbered from 1
+    InvalidSlot(u8),
+    ///The slot is described more than once
+    DuplicateSlot(u8),
+    ///Only SPI modes 0 to 3 exist
+    InvalidSpiMode {
+        slot: u8,
+        spi_mode: u32,
+    },
+    ///The device tree has no `module-slots` node and there is no slot map built in for the controller
+    NoBuiltinMap,
+}
+
+fn default_spi_speed() -> u32 {
+    DEFAULTSPISPEED
+}
+
+impl SlotMap {
+    ///The slot map that is built into this crate for the given controller, `None` when there is
+    ///none. The reset lines prefer the gpio line and fall back to the `ResetM-n` LED of the slot
+    pub fn builtin(controller_type: &ControllerType) -> Option<Self> {
+        Some(SlotMap {
+            slots: slots::slot_table(controller_type)?
+                .iter()
+                .enumerate()
+                .map(|(i, mapping)| SlotConfig {
+                    slot: i as u8 + 1,
+                    spidev: PathBuf::from(mapping.spidev),
+                    spi_speed_hz: DEFAULTSPISPEED,
+                    spi_mode: 0,
+                    reset: ResetSpec::Auto {
+                        prefer: ResetKind::Gpio,
+                        gpio: GpioSpec {
+                            chip: mapping.reset.0.to_owned(),
+                            line: mapping.reset.1,
+                            bias: None,
+                        },
+                        led: format!("ResetM-{}", i + 1),
+                    },
+                    interrupt: GpioSpec {
+                        chip: mapping.interrupt.0.to_owned(),
+                        line: mapping.interrupt.1,
+                        bias: None,
+                    },
+                })
+                .collect(),
+        })
+    }
+
+    ///The slot map of the running controller: from the device tree below the filesystem `root`,
+    ///normally `/`, when it has a `module-slots` node, the built in one otherwise.
+    ///Without either there is no way to know the wiring and [SlotMapError::NoBuiltinMap] is returned.
+    ///A node that is there but can't be read is an error, the built in map could drive the wrong
+    ///lines on such a board
+    pub fn detect(
+        controller_type: &ControllerType,
+        root: impl AsRef<Path>,
+    ) -> Result<Self, SlotMapError> {
+        let base = root.as_ref().join("sys/firmware/devicetree/base");
+        let node = base.join("module-slots");
+        match node.try_exists() {
+            Ok(true) => Self::from_devicetree(base),
+            Ok(false) => Self::builtin(controller_type).ok_or(SlotMapError::NoBuiltinMap),
+            Err(err) => Err(SlotMapError::Io(node, err)),
+        }
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, SlotMapError> {
+        toml::from_str::<Self>(toml)
+            .map_err(SlotMapError::Toml)?
+            .validate()
+    }
+
+    ///Load a slot map from a TOML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SlotMapError> {
+        let path = path.as_ref();
+        let toml =
+            fs::read_to_string(path).map_err(|err| SlotMapError::Io(path.to_path_buf(), err))?;
+        Self::from_toml(&toml)
+    }
+
+    ///Read the slot map from the device tree, `base` is normally `/sys/firmware/devicetree/base`.
+    ///
+    ///Every slot is a node `module-slots/slot@N` with the properties:
+    /// - `spidev`: path of the spidev device
+    /// - `spi-max-frequency`: optional, in Hz
+    /// - `spi-mode`: optional
+    /// - `reset-gpio-chip` and `reset-gpio-line`, or `reset-led`
+    /// - `interrupt-gpio-chip` and `interrupt-gpio-line`
+    /// - `reset-gpio-bias` and `interrupt-gpio-bias`: optional, `pull-up`, `pull-down` or `disabled`
+    pub fn from_devicetree(base: impl AsRef<Path>) -> Result<Self, SlotMapError> {
+        let dir = base.as_ref().join("module-slots");
+        let entries = fs::read_dir(&dir).map_err(|err| SlotMapError::Io(dir.clone(), err))?;
+        let mut slots = Vec::new();
+        for entry in entries {
+            let node = entry
+                .map_err(|err| SlotMapError::Io(dir.clone(), err))?
+                .path();
+            let Some(slot) = node
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("slot@"))
+                .and_then(|number| number.parse::<u8>().ok())
+            else {
+                continue;
+            };
+            let reset = if node.join("reset-led").exists() {
+                ResetSpec::Led(dt_string(&node, "reset-led")?)
+            } else {
+                ResetSpec::Gpio(GpioSpec {
+                    chip: dt_string(&node, "reset-gpio-chip")?,
+                    line: dt_u32(&node, "reset-gpio-line")?,
+                    bias: dt_bias(&node, "reset-gpio-bias")?,
+                })
+            };
+            slots.push(SlotConfig {
+                slot,
+                spidev: PathBuf::from(dt_string(&node, "spidev")?),
+                spi_speed_hz: dt_optional_u32(&node, "spi-max-frequency")?
+                    .unwrap_or(DEFAULTSPISPEED),
+                spi_mode: match dt_optional_u32(&node, "spi-mode")? {
+                    Some(spi_mode) => u8::try_from(spi_mode)
+                        .ok()
+                        .filter(|spi_mode| *spi_mode <= 3)
+                        .ok_or(SlotMapError::InvalidSpiMode { slot, spi_mode })?,
+                    None => 0,
+                },
+                reset,
+                interrupt: GpioSpec {
+                    chip: dt_string(&node, "interrupt-gpio-chip")?,
+                    line: dt_u32(&node, "interrupt-gpio-line")?,
+                    bias: dt_bias(&node, "interrupt-gpio-bias")?,
+                },
+            });
+        }
+        slots.sort_by_key(|slot| slot.slot);
+        SlotMap { slots }.validate()
+    }
+
+    ///Check what the file format can't: every slot is a slot a controller can have and is
+    ///described only once, and its SPI mode exists
+    fn validate(self) -> Result<Self, SlotMapError> {
+        let mut described = [false; SLOTS.len()];
+        for config in &self.slots {
+            let described = described
+                .get_mut((config.slot as usize).wrapping_sub(1))
+                .ok_or(SlotMapError::InvalidSlot(config.slot))?;
+            if std::mem::replace(described, true) {
+                return Err(SlotMapError::DuplicateSlot(config.slot));
+            }
+            if config.spi_mode > 3 {
+                return Err(SlotMapError::InvalidSpiMode {
+                    slot: config.slot,
+                    spi_mode: config.spi_mode.into(),
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn slot(&self, slot: ControllerSlot) -> Option<&SlotConfig> {
+        self.slots
+            .iter()
+            .find(|config| config.slot == slot as u8 + 1)
+    }
+
+    ///Open only the reset line of a slot, unlike [SlotMap::open] this reports why the reset line
+    ///could not be opened
+    pub fn open_reset(&self, slot: ControllerSlot) -> Result<ResetLine, ResetLineOpenError> {
+        let config = self.slot(slot).ok_or(ResetLineOpenError::NoSuchSlot)?;
+        ResetLine::open(
+            &config.reset,
+            &format!("slot {} module reset", slot as u8 + 1),
+        )
+    }
+
+    ///Open the hardware of a slot
+    ///This module can then be reset and embedded into a function specific module like the InputModule6Channel
+    pub fn open(&self, slot: ControllerSlot) -> Result<LinuxGoModule, ModuleSetupError> {
+        let config = self.slot(slot).ok_or(ModuleSetupError::NoSuchSlot)?;
+        Ok(GoModuleUnknown::new(
+            get_module_spi(config)?,
+            self.open_reset(slot)
+                .map_err(|_| ModuleSetupError::ResetPin)?,
+            get_module_interrupt(&config.interrupt, slot)?,
+            Delay,
+            slot as u8 + 1,
+        ))
+    }
+
+    ///Open the hardware of a slot for use with the async module API,
+    ///this has to be called from within a tokio runtime
+    #[cfg(feature = "tokio")]
+    pub fn open_async(
+        &self,
+        slot: ControllerSlot,
+    ) -> Result<crate::AsyncLinuxGoModule, ModuleSetupError> {
+        let config = self.slot(slot).ok_or(ModuleSetupError::NoSuchSlot)?;
+        Ok(GoModuleUnknown::new(
+            crate::tokio_hal::AsyncSpidev::new(get_module_spi(config)?),
+            self.open_reset(slot)
+                .map_err(|_| ModuleSetupError::ResetPin)?,
+            get_module_interrupt(&config.interrupt, slot)?.into(),
+            crate::tokio_hal::TokioDelay,
+            slot as u8 + 1,
+        ))
+    }
+}
+
+fn dt_property(node: &Path, property: &str) -> Result<Option<Vec<u8>>, SlotMapError> {
+    let path = node.join(property);
+    match fs::read(&path) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(SlotMapError::Io(path, err)),
+    }
+}
+
+fn dt_string(node: &Path, property: &str) -> Result<String, SlotMapError> {
+    dt_property(node, property)?
+        .and_then(|value| {
+            //device tree strings are null terminated
+            let value = value.split(|byte| *byte == 0).next()?;
+            String::from_utf8(value.to_vec()).ok()
+        })
+        .ok_or_else(|| SlotMapError::Devicetree(node.join(property)))
+}
+
+fn dt_optional_u32(node: &Path, property: &str) -> Result<Option<u32>, SlotMapError> {
+    match dt_property(node, property)? {
+        //device tree cells are big endian
+        Some(value) => match value.try_into() {
+            Ok(cell) => Ok(Some(u32::from_be_bytes(cell))),
+            Err(_) => Err(SlotMapError::Devicetree(node.join(property))),
+        },
+        None => Ok(None),
+    }
+}
+
+fn dt_u32(node: &Path, property: &str) -> Result<u32, SlotMapError> {
+    dt_optional_u32(node, property)?.ok_or_else(|| SlotMapError::Devicetree(node.join(property)))
+}
+
+fn dt_bias(node: &Path, property: &str) -> Result<Option<LineBias>, SlotMapError> {
+    if !node.join(property).exists() {
+        return Ok(None);
+    }
+    match dt_string(node, property)?.as_str() {
+        "pull-up" => Ok(Some(LineBias::PullUp)),
+        "pull-down" => Ok(Some(LineBias::PullDown)),
+        "disabled" => Ok(Some(LineBias::Disabled)),
+        _ => Err(SlotMapError::Devicetree(node.join(property))),
+    }
+}
+
+fn get_module_interrupt(
+    gpio: &GpioSpec,
+    slot: ControllerSlot,
+) -> Result<GpioInterrupt, ModuleSetupError> {
+    GpioInterrupt::new(gpio, &format!("slot {} module interrupt", slot as u8 + 1))
+        .map_err(|_| ModuleSetupError::InterruptPin)
+}
+
+fn get_module_spi(config: &SlotConfig) -> Result<SpidevDevice, ModuleSetupError> {
+    let mut spi = SpidevDevice::open(&config.spidev).map_err(|_| ModuleSetupError::Spi)?;
+    let spi_mode = match config.spi_mode {
+        0 => SpiModeFlags::SPI_MODE_0,
+        1 => SpiModeFlags::SPI_MODE_1,
+        2 => SpiModeFlags::SPI_MODE_2,
+        3 => SpiModeFlags::SPI_MODE_3,
+        _ => return Err(ModuleSetupError::Spi),
+    };
+    let spi_opts = SpidevOptions {
+        bits_per_word: Some(8),
+        max_speed_hz: Some(config.spi_speed_hz),
+        lsb_first: None,
+        spi_mode: Some(spi_mode),
+    };
+    spi.configure(&spi_opts)
+        .map_err(|_| ModuleSetupError::Spi)?;
+    Ok(spi)
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::mock::doc_example;
+
+    use super::*;
+    use crate::HwVersion;
+
+    #[test]
+    fn parses_the_documented_example() {
+        let doc = doc_example(include_str!("slot_map.rs"), "toml");
+        let slot_map = SlotMap::from_toml(&doc).unwrap();
+        assert_eq!(
+            slot_map.slots,
+            [
+                SlotConfig {
+                    slot: 1,
+                    spidev: PathBuf::from("/dev/spidev1.0"),
+                    spi_speed_hz: 2_000_000,
+                    spi_mode: 0,
+                    reset: ResetSpec::Gpio(GpioSpec {
+                        chip: "gpiochip0".to_owned(),
+                        line: 7,
+                        bias: None,
+                    }),
+                    interrupt: GpioSpec {
+                        chip: "gpiochip0".to_owned(),
+                        line: 6,
+                        bias: None,
+                    },
+                },
+                SlotConfig {
+                    slot: 2,
+                    spidev: PathBuf::from("/dev/spidev1.1"),
+                    spi_speed_hz: DEFAULTSPISPEED,
+                    spi_mode: 0,
+                    reset: ResetSpec::Led("ResetM-2".to_owned()),
+                    interrupt: GpioSpec {
+                        chip: "gpiochip4".to_owned(),
+                        line: 20,
+                        bias: Some(LineBias::PullUp),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_an_automatic_reset() {
+        let slot_map = SlotMap::from_toml(
+            r#"
+            [[slots]]
+            slot = 1
+            spidev = "/dev/spidev1.0"
+            reset = { auto = { prefer = "sysfs_led", gpio = { chip = "gpiochip0", line = 7 }, led = "ResetM-1" } }
+            interrupt = { chip = "gpiochip0", line = 6 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            slot_map.slots[0].reset,
+            ResetSpec::Auto {
+                prefer: ResetKind::SysfsLed,
+                gpio: GpioSpec {
+                    chip: "gpiochip0".to_owned(),
+                    line: 7,
+                    bias: None,
+                },
+                led: "ResetM-1".to_owned(),
+            }
+        );
+    }
+
+    ///Name and raw value of the device tree properties of one node
+    type Properties<'a> = &'a [(&'a str, &'a [u8])];
+
+    ///A device tree below a filesystem root, with the given properties for every slot node
+    fn devicetree(slots: &[(&str, Properties)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root
+            .path()
+            .join("sys/firmware/devicetree/base/module-slots");
+        fs::create_dir_all(&dir).unwrap();
+        for (node, properties) in slots {
+            let node = dir.join(node);
+            fs::create_dir(&node).unwrap();
+            for (property, value) in *properties {
+                fs::write(node.join(property), value).unwrap();
+            }
+        }
+        root
+    }
+
+    fn base(root: &tempfile::TempDir) -> PathBuf {
+        root.path().join("sys/firmware/devicetree/base")
+    }
+
+    const SLOT1: Properties = &[
+        ("spidev", b"/dev/spidev1.0\0"),
+        ("spi-max-frequency", &1_000_000u32.to_be_bytes()),
+        ("spi-mode", &3u32.to_be_bytes()),
+        ("reset-gpio-chip", b"gpiochip0\0"),
+        ("reset-gpio-line", &7u32.to_be_bytes()),
+        ("reset-gpio-bias", b"pull-down\0"),
+        ("interrupt-gpio-chip", b"gpiochip0\0"),
+        ("interrupt-gpio-line", &6u32.to_be_bytes()),
+    ];
+
+    const SLOT2: Properties = &[
+        ("spidev", b"/dev/spidev1.1\0"),
+        ("reset-led", b"ResetM-2\0"),
+        ("interrupt-gpio-chip", b"gpiochip4\0"),
+        ("interrupt-gpio-line", &20u32.to_be_bytes()),
+        ("interrupt-gpio-bias", b"pull-up\0"),
+    ];
+
+    #[test]
+    fn reads_the_slots_from_the_devicetree() {
+        let root = devicetree(&[("slot@2", SLOT2), ("slot@1", SLOT1), ("name", &[])]);
+        let slot_map = SlotMap::from_devicetree(base(&root)).unwrap();
+        assert_eq!(
+            slot_map.slots,
+            [
+                SlotConfig {
+                    slot: 1,
+                    spidev: PathBuf::from("/dev/spidev1.0"),
+                    spi_speed_hz: 1_000_000,
+                    spi_mode: 3,
+                    reset: ResetSpec::Gpio(GpioSpec {
+                        chip: "gpiochip0".to_owned(),
+                        line: 7,
+                        bias: Some(LineBias::PullDown),
+                    }),
+                    interrupt: GpioSpec {
+                        chip: "gpiochip0".to_owned(),
+                        line: 6,
+                        bias: None,
+                    },
+                },
+                SlotConfig {
+                    slot: 2,
+                    spidev: PathBuf::from("/dev/spidev1.1"),
+                    spi_speed_hz: DEFAULTSPISPEED,
+                    spi_mode: 0,
+                    reset: ResetSpec::Led("ResetM-2".to_owned()),
+                    interrupt: GpioSpec {
+                        chip: "gpiochip4".to_owned(),
+                        line: 20,
+                        bias: Some(LineBias::PullUp),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_devicetree_properties() {
+        let invalid = |property: &str, value: &[u8]| {
+            let mut properties = SLOT1.to_vec();
+            properties.retain(|(name, _)| *name != property);
+            properties.push((property, value));
+            let root = devicetree(&[("slot@1", &properties)]);
+            match SlotMap::from_devicetree(base(&root)) {
+                Err(SlotMapError::Devicetree(path)) => assert!(path.ends_with(property)),
+                result => panic!("{property} was accepted: {result:?}"),
+            }
+        };
+        invalid("spi-max-frequency", &[0, 1]);
+        invalid("reset-gpio-bias", b"pull-sideways\0");
+        invalid("interrupt-gpio-chip", &[0xff, 0xfe, 0]);
+
+        let mut missing = SLOT1.to_vec();
+        missing.retain(|(name, _)| *name != "interrupt-gpio-line");
+        let root = devicetree(&[("slot@1", &missing)]);
+        assert!(matches!(
+            SlotMap::from_devicetree(base(&root)),
+            Err(SlotMapError::Devicetree(path)) if path.ends_with("interrupt-gpio-line")
+        ));
+
+        //too large for the mode byte, it must not wrap around to mode 0
+        for spi_mode in [4, 256] {
+            let mut properties = SLOT1.to_vec();
+            let value = u32::to_be_bytes(spi_mode);
+            properties.retain(|(name, _)| *name != "spi-mode");
+            properties.push(("spi-mode", &value));
+            let root = devicetree(&[("slot@1", &properties)]);
+            assert!(matches!(
+                SlotMap::from_devicetree(base(&root)),
+                Err(SlotMapError::InvalidSpiMode { slot: 1, spi_mode: mode }) if mode == spi_mode
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_slots_a_controller_cant_have() {
+        let slot = |slot: u8, spi_mode: u8| {
+            format!(
+                "[[slots]]\nslot = {slot}\nspidev = \"/dev/spidev1.0\"\nspi_mode = {spi_mode}\n\
+                 reset = {{ led = \"ResetM-1\" }}\ninterrupt = {{ chip = \"gpiochip0\", line = 6 }}\n"
+            )
+        };
+        assert!(SlotMap::from_toml(&(slot(1, 3) + &slot(8, 0))).is_ok());
+        assert!(matches!(
+            SlotMap::from_toml(&(slot(2, 0) + &slot(2, 1))),
+            Err(SlotMapError::DuplicateSlot(2))
+        ));
+        assert!(matches!(
+            SlotMap::from_toml(&slot(0, 0)),
+            Err(SlotMapError::InvalidSlot(0))
+        ));
+        assert!(matches!(
+            SlotMap::from_toml(&slot(9, 0)),
+            Err(SlotMapError::InvalidSlot(9))
+        ));
+        assert!(matches!(
+            SlotMap::from_toml(&slot(1, 4)),
+            Err(SlotMapError::InvalidSpiMode {
+                slot: 1,
+                spi_mode: 4
+            })
+        ));
+
+        let root = devicetree(&[("slot@1", SLOT1), ("slot@01", SLOT2)]);
+        assert!(matches!(
+            SlotMap::from_devicetree(base(&root)),
+            Err(SlotMapError::DuplicateSlot(1))
+        ));
+    }
+
+    #[test]
+    fn detects_the_devicetree_below_the_root_or_falls_back_to_the_builtin_map() {
+        let controller_type = ControllerType::ModulineIV(HwVersion::new(3, 6));
+        let root = devicetree(&[("slot@1", SLOT1)]);
+        let slot_map = SlotMap::detect(&controller_type, root.path()).unwrap();
+        assert_eq!(slot_map.slots.len(), 1);
+        assert_eq!(slot_map.slots[0].spi_mode, 3);
+
+        let missing = tempfile::tempdir().unwrap();
+        assert_eq!(
+            SlotMap::detect(&controller_type, missing.path()).unwrap(),
+            SlotMap::builtin(&controller_type).unwrap()
+        );
+        assert!(matches!(
+            SlotMap::detect(
+                &ControllerType::ModulineMini(HwVersion::new(1, 6)),
+                missing.path()
+            ),
+            Err(SlotMapError::NoBuiltinMap)
+        ));
+
+        //a node that is there is used as it is, even when it is wrong
+        let empty = devicetree(&[]);
+        assert!(SlotMap::detect(&controller_type, empty.path())
+            .unwrap()
+            .slots
+            .is_empty());
+        let mut properties = SLOT1.to_vec();
+        properties.retain(|(name, _)| *name != "spi-mode");
+        properties.push(("spi-mode", &[1, 0, 0, 0]));
+        let malformed = devicetree(&[("slot@1", &properties)]);
+        assert!(matches!(
+            SlotMap::detect(&controller_type, malformed.path()),
+            Err(SlotMapError::InvalidSpiMode { slot: 1, .. })
+        ));
+    }
+}
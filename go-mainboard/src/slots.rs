@@ -0,0 +1,139 @@
+use crate::ControllerType;
+
+///Where the spi device, reset line and interrupt line of a module slot are found
+pub(crate) struct SlotMapping {
+    pub spidev: &'static str,
+    pub reset: (&'static str, u32),
+    pub interrupt: (&'static str, u32),
+}
+
+///The wiring of the original `GoModule::get_modules`
+const MODULINEIVSLOTS: [SlotMapping; 8] = [
+    SlotMapping {
+        spidev: "/dev/spidev1.0",
+        reset: ("gpiochip0", 7),
+        interrupt: ("gpiochip0", 6),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev1.1",
+        reset: ("gpiochip2", 10),
+        interrupt: ("gpiochip4", 20),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev2.0",
+        reset: ("gpiochip0", 4),
+        interrupt: ("gpiochip0", 7),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev2.1",
+        reset: ("gpiochip0", 2),
+        interrupt: ("gpiochip4", 21),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev2.2",
+        reset: ("gpiochip3", 24),
+        interrupt: ("gpiochip4", 1),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev2.3",
+        reset: ("gpiochip3", 27),
+        interrupt: ("gpiochip3", 26),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev0.0",
+        reset: ("gpiochip2", 24),
+        interrupt: ("gpiochip2", 19),
+    },
+    SlotMapping {
+        spidev: "/dev/spidev0.1",
+        reset: ("gpiochip2", 20),
+        interrupt: ("gpiochip2", 22),
+    },
+];
+
+///The slot table built into this crate, only the wiring of the Moduline IV is known. The other
+///controllers describe their slots in the device tree or a TOML file, see [crate::slot_map::SlotMap]
+pub(crate) fn slot_table(controller_type: &ControllerType) -> Option<&'static [SlotMapping]> {
+    match controller_type {
+        ControllerType::ModulineIV(_) => Some(&MODULINEIVSLOTS),
+        ControllerType::ModulineIII(_)
+        | ControllerType::ModulineMini(_)
+        | ControllerType::ModulineDisplay(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HwVersion;
+
+    fn wiring(controller_type: ControllerType) -> Option<Vec<(&'static str, u32, u32)>> {
+        slot_table(&controller_type).map(|table| {
+            table
+                .iter()
+                .map(|mapping| (mapping.spidev, mapping.reset.1, mapping.interrupt.1))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn only_the_moduline_iv_is_built_in() {
+        for hw in [
+            HwVersion::new(1, 5),
+            HwVersion::new(1, 6),
+            HwVersion::new(3, 6),
+        ] {
+            let table = slot_table(&ControllerType::ModulineIV(hw)).unwrap();
+            assert_eq!(table.len(), 8);
+            assert_eq!(
+                wiring(ControllerType::ModulineIV(hw)).unwrap(),
+                [
+                    ("/dev/spidev1.0", 7, 6),
+                    ("/dev/spidev1.1", 10, 20),
+                    ("/dev/spidev2.0", 4, 7),
+                    ("/dev/spidev2.1", 2, 21),
+                    ("/dev/spidev2.2", 24, 1),
+                    ("/dev/spidev2.3", 27, 26),
+                    ("/dev/spidev0.0", 24, 19),
+                    ("/dev/spidev0.1", 20, 22),
+                ]
+            );
+            assert_eq!(
+                table
+                    .iter()
+                    .map(|mapping| mapping.reset.0)
+                    .collect::<Vec<_>>(),
+                [
+                    "gpiochip0",
+                    "gpiochip2",
+                    "gpiochip0",
+                    "gpiochip0",
+                    "gpiochip3",
+                    "gpiochip3",
+                    "gpiochip2",
+                    "gpiochip2"
+                ]
+            );
+            assert_eq!(
+                table
+                    .iter()
+                    .map(|mapping| mapping.interrupt.0)
+                    .collect::<Vec<_>>(),
+                [
+                    "gpiochip0",
+                    "gpiochip4",
+                    "gpiochip0",
+                    "gpiochip4",
+                    "gpiochip4",
+                    "gpiochip3",
+                    "gpiochip2",
+                    "gpiochip2"
+                ]
+            );
+
+            assert!(wiring(ControllerType::ModulineIII(hw)).is_none());
+            assert!(wiring(ControllerType::ModulineMini(hw)).is_none());
+            assert!(wiring(ControllerType::ModulineDisplay(hw)).is_none());
+        }
+    }
+}
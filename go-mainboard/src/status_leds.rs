@@ -0,0 +1,342 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const OFF: Color = Color::new(0, 0, 0);
+    pub const RED: Color = Color::new(255, 0, 0);
+    pub const GREEN: Color = Color::new(0, 255, 0);
+    pub const BLUE: Color = Color::new(0, 0, 255);
+    pub const YELLOW: Color = Color::new(255, 255, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Color { red, green, blue }
+    }
+}
+
+///What a status LED is doing, the kernel LED triggers take care of blinking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedMode {
+    Solid,
+    Heartbeat,
+    Blink { on: Duration, off: Duration },
+}
+
+///Patterns that tell what state the controller is in, shown on all status LEDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Off,
+    Booting,
+    Running,
+    Warning,
+    Fault,
+}
+
+impl Pattern {
+    pub fn color(&self) -> Color {
+        match self {
+            Pattern::Off => Color::OFF,
+            Pattern::Booting => Color::BLUE,
+            Pattern::Running => Color::GREEN,
+            Pattern::Warning => Color::YELLOW,
+            Pattern::Fault => Color::RED,
+        }
+    }
+
+    pub fn mode(&self) -> LedMode {
+        match self {
+            Pattern::Off | Pattern::Running => LedMode::Solid,
+            Pattern::Booting => LedMode::Heartbeat,
+            Pattern::Warning => LedMode::Blink {
+                on: Duration::from_millis(500),
+                off: Duration::from_millis(500),
+            },
+            Pattern::Fault => LedMode::Blink {
+                on: Duration::from_millis(100),
+                off: Duration::from_millis(100),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPattern(pub String);
+
+///Patterns are named in lower case, e.g. `fault` or `booting`
+impl FromStr for Pattern {
+    type Err = UnknownPattern;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "off" => Ok(Pattern::Off),
+            "booting" => Ok(Pattern::Booting),
+            "running" => Ok(Pattern::Running),
+            "warning" => Ok(Pattern::Warning),
+            "fault" => Ok(Pattern::Fault),
+            _ => Err(UnknownPattern(name.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StatusLedError {
+    Io(PathBuf, std::io::Error),
+    ///The LED index is not one of the status LEDs found
+    NoSuchLed(usize),
+}
+
+///One colour of an RGB status LED
+struct LedChannel {
+    path: PathBuf,
+    max_brightness: u32,
+}
+
+struct StatusLed {
+    channels: [LedChannel; 3],
+    color: Color,
+    brightness: u8,
+    mode: LedMode,
+}
+
+///The RGB status LEDs of the controller, found in the sysfs LED class as `Status<N>-r`,
+///`Status<N>-g` and `Status<N>-b`. LEDs are indexed from 0 in the order of N.
+pub struct StatusLeds {
+    leds: Vec<StatusLed>,
+}
+
+impl StatusLeds {
+    ///Find the status LEDs below `/sys`
+    pub fn new() -> Result<Self, StatusLedError> {
+        Self::with_root("/sys")
+    }
+
+    ///Find the status LEDs below a sysfs root, normally `/sys`
+    pub fn with_root(root: impl AsRef<Path>) -> Result<Self, StatusLedError> {
+        let dir = root.as_ref().join("class/leds");
+        let entries = fs::read_dir(&dir).map_err(|err| StatusLedError::Io(dir.clone(), err))?;
+        let mut numbers: Vec<u32> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("Status")?
+                    .strip_suffix("-r")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        numbers.sort_unstable();
+        let mut leds = Vec::with_capacity(numbers.len());
+        for number in numbers {
+            let channel = |color: &str| -> Result<LedChannel, StatusLedError> {
+                let path = dir.join(format!("Status{}-{}", number, color));
+                let max_brightness =
+                    read(&path.join("max_brightness"))?
+                        .trim()
+                        .parse()
+                        .map_err(|_| {
+                            StatusLedError::Io(
+                                path.join("max_brightness"),
+                                std::io::ErrorKind::InvalidData.into(),
+                            )
+                        })?;
+                Ok(LedChannel {
+                    path,
+                    max_brightness,
+                })
+            };
+            leds.push(StatusLed {
+                channels: [channel("r")?, channel("g")?, channel("b")?],
+                color: Color::OFF,
+                brightness: 255,
+                mode: LedMode::Solid,
+            });
+        }
+        Ok(StatusLeds { leds })
+    }
+
+    ///Number of status LEDs found
+    pub fn len(&self) -> usize {
+        self.leds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leds.is_empty()
+    }
+
+    pub fn set_color(&mut self, led: usize, color: Color) -> Result<(), StatusLedError> {
+        self.led_mut(led)?.color = color;
+        self.apply(led)
+    }
+
+    ///Scale the colour of the LED, 255 is full brightness
+    pub fn set_brightness(&mut self, led: usize, brightness: u8) -> Result<(), StatusLedError> {
+        self.led_mut(led)?.brightness = brightness;
+        self.apply(led)
+    }
+
+    pub fn set_mode(&mut self, led: usize, mode: LedMode) -> Result<(), StatusLedError> {
+        self.led_mut(led)?.mode = mode;
+        self.apply(led)
+    }
+
+    ///Pulse the LED with the kernel heartbeat trigger
+    pub fn heartbeat(&mut self, led: usize, color: Color) -> Result<(), StatusLedError> {
+        self.led_mut(led)?.color = color;
+        self.set_mode(led, LedMode::Heartbeat)
+    }
+
+    ///Blink the LED with the kernel timer trigger
+    pub fn blink(
+        &mut self,
+        led: usize,
+        color: Color,
+        on: Duration,
+        off: Duration,
+    ) -> Result<(), StatusLedError> {
+        self.led_mut(led)?.color = color;
+        self.set_mode(led, LedMode::Blink { on, off })
+    }
+
+    ///Show a pattern on all status LEDs
+    pub fn show(&mut self, pattern: Pattern) -> Result<(), StatusLedError> {
+        for led in 0..self.leds.len() {
+            self.leds[led].color = pattern.color();
+            self.leds[led].mode = pattern.mode();
+            self.apply(led)?;
+        }
+        Ok(())
+    }
+
+    fn led_mut(&mut self, led: usize) -> Result<&mut StatusLed, StatusLedError> {
+        self.leds.get_mut(led).ok_or(StatusLedError::NoSuchLed(led))
+    }
+
+    fn apply(&self, led: usize) -> Result<(), StatusLedError> {
+        let led = self.leds.get(led).ok_or(StatusLedError::NoSuchLed(led))?;
+        let Color { red, green, blue } = led.color;
+        for (channel, value) in led.channels.iter().zip([red, green, blue]) {
+            let brightness =
+                value as u32 * led.brightness as u32 * channel.max_brightness / (255 * 255);
+            //the trigger is reset first because writing the brightness stops a running trigger
+            write(&channel.path.join("trigger"), "none")?;
+            write(&channel.path.join("brightness"), &brightness.to_string())?;
+            if brightness == 0 {
+                continue;
+            }
+            match led.mode {
+                LedMode::Solid => {}
+                LedMode::Heartbeat => write(&channel.path.join("trigger"), "heartbeat")?,
+                LedMode::Blink { on, off } => {
+                    write(&channel.path.join("trigger"), "timer")?;
+                    write(&channel.path.join("delay_on"), &on.as_millis().to_string())?;
+                    write(
+                        &channel.path.join("delay_off"),
+                        &off.as_millis().to_string(),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<String, StatusLedError> {
+    fs::read_to_string(path).map_err(|err| StatusLedError::Io(path.to_path_buf(), err))
+}
+
+fn write(path: &Path, value: &str) -> Result<(), StatusLedError> {
+    fs::write(path, value).map_err(|err| StatusLedError::Io(path.to_path_buf(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_leds(count: u32) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for number in 1..=count {
+            for color in ["r", "g", "b"] {
+                let led = root
+                    .path()
+                    .join(format!("class/leds/Status{}-{}", number, color));
+                fs::create_dir_all(&led).unwrap();
+                fs::write(led.join("max_brightness"), "255\n").unwrap();
+                fs::write(led.join("brightness"), "0\n").unwrap();
+                fs::write(led.join("trigger"), "[none] timer heartbeat\n").unwrap();
+            }
+        }
+        //LEDs that are not status LEDs are left alone
+        fs::create_dir_all(root.path().join("class/leds/ResetM-1")).unwrap();
+        root
+    }
+
+    fn attribute(root: &Path, led: &str, attribute: &str) -> String {
+        fs::read_to_string(root.join("class/leds").join(led).join(attribute)).unwrap()
+    }
+
+    #[test]
+    fn finds_status_leds() {
+        let root = fake_leds(2);
+        assert_eq!(StatusLeds::with_root(root.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sets_color_and_brightness() {
+        let root = fake_leds(1);
+        let mut leds = StatusLeds::with_root(root.path()).unwrap();
+        leds.set_color(0, Color::new(255, 128, 0)).unwrap();
+        assert_eq!(attribute(root.path(), "Status1-r", "brightness"), "255");
+        assert_eq!(attribute(root.path(), "Status1-g", "brightness"), "128");
+        assert_eq!(attribute(root.path(), "Status1-b", "brightness"), "0");
+        leds.set_brightness(0, 128).unwrap();
+        assert_eq!(attribute(root.path(), "Status1-r", "brightness"), "128");
+        assert_eq!(attribute(root.path(), "Status1-g", "brightness"), "64");
+        assert!(matches!(
+            leds.set_color(1, Color::RED),
+            Err(StatusLedError::NoSuchLed(1))
+        ));
+    }
+
+    #[test]
+    fn uses_kernel_triggers() {
+        let root = fake_leds(1);
+        let mut leds = StatusLeds::with_root(root.path()).unwrap();
+        leds.blink(
+            0,
+            Color::RED,
+            Duration::from_millis(200),
+            Duration::from_millis(800),
+        )
+        .unwrap();
+        assert_eq!(attribute(root.path(), "Status1-r", "trigger"), "timer");
+        assert_eq!(attribute(root.path(), "Status1-r", "delay_on"), "200");
+        assert_eq!(attribute(root.path(), "Status1-r", "delay_off"), "800");
+        assert_eq!(attribute(root.path(), "Status1-g", "trigger"), "none");
+        leds.heartbeat(0, Color::GREEN).unwrap();
+        assert_eq!(attribute(root.path(), "Status1-r", "trigger"), "none");
+        assert_eq!(attribute(root.path(), "Status1-g", "trigger"), "heartbeat");
+    }
+
+    #[test]
+    fn shows_named_patterns() {
+        let root = fake_leds(2);
+        let mut leds = StatusLeds::with_root(root.path()).unwrap();
+        leds.show("fault".parse().unwrap()).unwrap();
+        for led in ["Status1-r", "Status2-r"] {
+            assert_eq!(attribute(root.path(), led, "trigger"), "timer");
+            assert_eq!(attribute(root.path(), led, "delay_on"), "100");
+        }
+        assert_eq!(attribute(root.path(), "Status2-g", "brightness"), "0");
+        assert!("blinking".parse::<Pattern>().is_err());
+    }
+}
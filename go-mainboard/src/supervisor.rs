@@ -0,0 +1,575 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+use go_module_base::{
+    CommunicationError, GoModuleError, GoModuleUnknown, ModuleDriver, ModuleIdentity,
+    ModuleSetupError, ModuleStatus,
+};
+use go_modules::any_module::{
+    AnyModule, AnyModuleConfiguration, AnyModuleSetpoint, AnyModuleValues,
+};
+
+use crate::ControllerSlot;
+
+///Where a supervised module is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleState {
+    ///The last exchange succeeded
+    Online,
+    ///Exchanges fail, but not yet enough of them in a row to consider the module lost
+    Degraded,
+    ///The module was lost and is being reset and configured again
+    Recovering,
+    ///Recovery gave up after the configured number of attempts
+    Failed,
+}
+
+///A supervised module changed state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleEvent {
+    pub slot: ControllerSlot,
+    pub previous: ModuleState,
+    pub state: ModuleState,
+    ///Recovery attempts made since the module was lost
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupervisorConfig {
+    ///Failed exchanges in a row after which the module is considered lost,
+    ///a module that answers with its boot message is lost right away
+    pub error_threshold: u32,
+    ///Wait before the second recovery attempt, doubled after every failed attempt
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    ///Attempts after which the module is marked failed, None keeps trying
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            error_threshold: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+impl SupervisorConfig {
+    ///Time to wait after the given number of failed attempts
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        match attempts {
+            0 => Duration::ZERO,
+            attempts => self
+                .initial_backoff
+                .saturating_mul(1 << (attempts - 1).min(31))
+                .min(self.max_backoff),
+        }
+    }
+}
+
+pub type EventHandler = Arc<dyn Fn(ModuleEvent) + Send + Sync>;
+
+enum Supervised<SPI, ResetPin, InterruptPin, Delay> {
+    Running(AnyModule<SPI, ResetPin, InterruptPin, Delay>),
+    Lost {
+        module: GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+        configuration: AnyModuleConfiguration,
+        attempts: u32,
+        next_attempt: Instant,
+    },
+}
+
+///A module driver that resets and configures its module again when it gets lost, for example
+///after a brown out or being swapped. It is a [ModuleDriver] itself so it can be run by the
+///[Scheduler](crate::scheduler::Scheduler) like any other driver.
+///
+///Recovery runs inside [exchange](ModuleDriver::exchange) and takes as long as a module reset
+///and configuration, the cycle it runs in overruns. While the module is lost exchanges fail with
+///[CommunicationError::ModuleUnavailable].
+pub struct SupervisedModule<SPI, ResetPin, InterruptPin, Delay> {
+    slot: ControllerSlot,
+    module: Option<Supervised<SPI, ResetPin, InterruptPin, Delay>>,
+    state: ModuleState,
+    identity: ModuleIdentity,
+    consecutive_errors: u32,
+    config: SupervisorConfig,
+    on_event: EventHandler,
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> SupervisedModule<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    ///Supervise a running module, `on_event` is called on every state change
+    ///and can be shared between the modules of a controller
+    pub fn new(
+        slot: ControllerSlot,
+        module: AnyModule<SPI, ResetPin, InterruptPin, Delay>,
+        config: SupervisorConfig,
+        on_event: EventHandler,
+    ) -> Self {
+        SupervisedModule {
+            slot,
+            identity: module.identity(),
+            module: Some(Supervised::Running(module)),
+            state: ModuleState::Online,
+            consecutive_errors: 0,
+            config,
+            on_event,
+        }
+    }
+
+    pub fn slot(&self) -> ControllerSlot {
+        self.slot
+    }
+
+    pub fn state(&self) -> ModuleState {
+        self.state
+    }
+
+    ///The driver, None while the module is lost
+    pub fn module_mut(&mut self) -> Option<&mut AnyModule<SPI, ResetPin, InterruptPin, Delay>> {
+        match &mut self.module {
+            Some(Supervised::Running(module)) => Some(module),
+            _ => None,
+        }
+    }
+
+    ///Start recovering a failed module again, with the backoff starting over
+    pub fn retry(&mut self) {
+        if let Some(Supervised::Lost {
+            attempts,
+            next_attempt,
+            ..
+        }) = &mut self.module
+        {
+            *attempts = 0;
+            *next_attempt = Instant::now();
+            self.set_state(ModuleState::Recovering, 0);
+        }
+    }
+
+    fn set_state(&mut self, state: ModuleState, attempts: u32) {
+        if state != self.state {
+            let previous = self.state;
+            self.state = state;
+            (self.on_event)(ModuleEvent {
+                slot: self.slot,
+                previous,
+                state,
+                attempts,
+            });
+        }
+    }
+
+    ///Reset a lost module right away, so it sits in its bootloader with its outputs off until
+    ///it is recovered
+    fn lose(&mut self, module: AnyModule<SPI, ResetPin, InterruptPin, Delay>) {
+        let (module, configuration) = module.reconfigure();
+        //a failed reset is tried again by the recovery and by safe_state
+        let (module, _) = reset(module);
+        self.module = Some(Supervised::Lost {
+            module,
+            configuration,
+            attempts: 0,
+            next_attempt: Instant::now(),
+        });
+        self.set_state(ModuleState::Recovering, 0);
+    }
+
+    fn recover(
+        &mut self,
+        module: GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+        configuration: AnyModuleConfiguration,
+        attempts: u32,
+    ) {
+        let attempts = attempts + 1;
+        let (module, configuration) = match module.module_reset() {
+            Ok(module) => match AnyModule::build(module, configuration) {
+                Ok(module) => {
+                    self.identity = module.identity();
+                    self.consecutive_errors = 0;
+                    self.module = Some(Supervised::Running(module));
+                    self.set_state(ModuleState::Online, attempts);
+                    return;
+                }
+                Err((module, configuration, _)) => (module, configuration),
+            },
+            Err(module) => (module, configuration),
+        };
+        self.module = Some(Supervised::Lost {
+            module,
+            configuration,
+            attempts,
+            next_attempt: Instant::now() + self.config.backoff(attempts),
+        });
+        if self
+            .config
+            .max_attempts
+            .is_some_and(|max_attempts| attempts >= max_attempts)
+        {
+            self.set_state(ModuleState::Failed, attempts);
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for SupervisedModule<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = AnyModuleConfiguration;
+    type Setpoint = AnyModuleSetpoint;
+    type Values = AnyModuleValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    ///Identity the module reported when it was last built
+    fn identity(&self) -> ModuleIdentity {
+        self.identity
+    }
+
+    fn degrade(mut self) -> Self::Module {
+        match self.module.take().unwrap() {
+            Supervised::Running(module) => module.degrade(),
+            Supervised::Lost { module, .. } => module,
+        }
+    }
+
+    fn reconfigure(mut self) -> (Self::Module, Self::Configuration) {
+        match self.module.take().unwrap() {
+            Supervised::Running(module) => module.reconfigure(),
+            Supervised::Lost {
+                module,
+                configuration,
+                ..
+            } => (module, configuration),
+        }
+    }
+
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        //the module is only taken out while its state is updated, it is always put back
+        match self.module.take().unwrap() {
+            Supervised::Running(mut module) => {
+                let result = module.exchange(setpoint);
+                match &result {
+                    Ok(_) => {
+                        self.consecutive_errors = 0;
+                        self.module = Some(Supervised::Running(module));
+                        self.set_state(ModuleState::Online, 0);
+                    }
+                    Err(err) => {
+                        self.consecutive_errors += 1;
+                        let in_bootloader = matches!(
+                            err,
+                            GoModuleError::CommunicationError(CommunicationError::InBootloader)
+                        );
+                        if in_bootloader || self.consecutive_errors >= self.config.error_threshold {
+                            self.lose(module);
+                        } else {
+                            self.module = Some(Supervised::Running(module));
+                            self.set_state(ModuleState::Degraded, 0);
+                        }
+                    }
+                }
+                result
+            }
+            Supervised::Lost {
+                module,
+                configuration,
+                attempts,
+                next_attempt,
+            } => {
+                if self.state == ModuleState::Recovering && Instant::now() >= next_attempt {
+                    self.recover(module, configuration, attempts);
+                } else {
+                    self.module = Some(Supervised::Lost {
+                        module,
+                        configuration,
+                        attempts,
+                        next_attempt,
+                    });
+                }
+                //the setpoint is only sent again from the next cycle on, when the module is back
+                Err(GoModuleError::CommunicationError(
+                    CommunicationError::ModuleUnavailable,
+                ))
+            }
+        }
+    }
+
+    ///Statistics of the running driver, they start over when the module is recovered
+    fn status(&self) -> ModuleStatus {
+        match &self.module {
+            Some(Supervised::Running(module)) => module.status(),
+            _ => ModuleStatus::default(),
+        }
+    }
+
+    ///A lost module is reset again, which leaves it in its bootloader with its outputs off,
+    ///fails when the reset pin can't be driven
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        match self.module.take().unwrap() {
+            Supervised::Running(mut module) => {
+                let result = module.safe_state();
+                self.module = Some(Supervised::Running(module));
+                result
+            }
+            Supervised::Lost {
+                module,
+                configuration,
+                attempts,
+                next_attempt,
+            } => {
+                let (module, was_reset) = reset(module);
+                self.module = Some(Supervised::Lost {
+                    module,
+                    configuration,
+                    attempts,
+                    next_attempt,
+                });
+                if was_reset {
+                    Ok(())
+                } else {
+                    Err(GoModuleError::ModuleSetupError(ModuleSetupError::ResetPin))
+                }
+            }
+        }
+    }
+}
+
+///Pulse the reset of a module, returns whether the reset pin could be driven
+fn reset<SPI, ResetPin, InterruptPin, Delay>(
+    module: GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+) -> (GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>, bool)
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    match module.module_reset() {
+        Ok(module) => (module.degrade(), true),
+        Err(module) => (module, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use go_module_base::{
+        mock::{bootmessage, module_frame, NoDelay, ScriptedModule, ScriptedPin, ScriptedSpi},
+        ModuleCommunicationType,
+    };
+    use go_modules::any_module::ModuleKind;
+
+    use super::*;
+
+    type Supervised = SupervisedModule<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>;
+
+    const OUTPUT6: [u8; 3] = [20, 20, 2];
+
+    fn feedback() -> Vec<u8> {
+        module_frame(22, ModuleCommunicationType::Feedback, 1, &[], 44)
+    }
+
+    ///Script the answers of a module that is configured successfully
+    fn accept_configuration(spi: &ScriptedSpi) {
+        spi.reply(bootmessage(OUTPUT6, 1, [1, 0, 0]));
+    }
+
+    ///An online output module on scripted hardware and the events it reports
+    fn supervised(
+        config: SupervisorConfig,
+    ) -> (
+        Supervised,
+        ScriptedSpi,
+        ScriptedPin,
+        Arc<Mutex<Vec<ModuleEvent>>>,
+    ) {
+        let (module, spi, reset) = ScriptedModule::scripted(1);
+        accept_configuration(&spi);
+        let module = AnyModule::build(
+            module.module_reset().ok().unwrap(),
+            AnyModuleConfiguration::default_for(ModuleKind::Output6Channel),
+        )
+        .ok()
+        .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_event = {
+            let events = events.clone();
+            Arc::new(move |event| events.lock().unwrap().push(event))
+        };
+        let supervised = SupervisedModule::new(ControllerSlot::Slot1, module, config, on_event);
+        (supervised, spi, reset, events)
+    }
+
+    fn exchange(supervised: &mut Supervised) -> bool {
+        supervised
+            .exchange(&AnyModuleSetpoint::off(ModuleKind::Output6Channel))
+            .is_ok()
+    }
+
+    fn states(events: &Mutex<Vec<ModuleEvent>>) -> Vec<(ModuleState, ModuleState, u32)> {
+        events
+            .lock()
+            .unwrap()
+            .drain(..)
+            .map(|event| {
+                assert_eq!(event.slot, ControllerSlot::Slot1);
+                (event.previous, event.state, event.attempts)
+            })
+            .collect()
+    }
+
+    fn immediate_retries() -> SupervisorConfig {
+        SupervisorConfig {
+            error_threshold: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            max_attempts: None,
+        }
+    }
+
+    #[test]
+    fn degrades_loses_and_recovers_a_module() {
+        use ModuleState::*;
+        let (mut supervised, spi, reset, events) = supervised(immediate_retries());
+        let resets = reset.pulses();
+
+        spi.reply(feedback());
+        assert!(exchange(&mut supervised));
+        assert_eq!(supervised.state(), Online);
+
+        //a single failed exchange only degrades the module
+        assert!(!exchange(&mut supervised));
+        spi.reply(feedback());
+        assert!(exchange(&mut supervised));
+        assert_eq!(
+            states(&events),
+            [(Online, Degraded, 0), (Degraded, Online, 0)]
+        );
+
+        //the threshold of failures in a row loses it and resets it right away
+        for _ in 0..3 {
+            assert!(!exchange(&mut supervised));
+        }
+        assert_eq!(supervised.state(), Recovering);
+        assert!(supervised.module_mut().is_none());
+        assert_eq!(reset.pulses(), resets + 1);
+        assert_eq!(
+            states(&events),
+            [(Online, Degraded, 0), (Degraded, Recovering, 0)]
+        );
+
+        //the next cycle resets and configures it again, the cycle itself still fails
+        accept_configuration(&spi);
+        assert!(!exchange(&mut supervised));
+        assert_eq!(spi.pending(), 0);
+        assert_eq!(reset.pulses(), resets + 2);
+        assert_eq!(states(&events), [(Recovering, Online, 1)]);
+        spi.reply(feedback());
+        assert!(exchange(&mut supervised));
+    }
+
+    #[test]
+    fn loses_a_module_in_its_bootloader_right_away() {
+        let (mut supervised, spi, _, events) = supervised(immediate_retries());
+        spi.reply(bootmessage(OUTPUT6, 1, [1, 0, 0]));
+        assert!(!exchange(&mut supervised));
+        assert_eq!(
+            states(&events),
+            [(ModuleState::Online, ModuleState::Recovering, 0)]
+        );
+    }
+
+    #[test]
+    fn fails_after_the_maximum_attempts_until_retried() {
+        use ModuleState::*;
+        let (mut supervised, spi, _, events) = supervised(SupervisorConfig {
+            max_attempts: Some(2),
+            ..immediate_retries()
+        });
+        spi.reply(bootmessage(OUTPUT6, 1, [1, 0, 0]));
+        assert!(!exchange(&mut supervised));
+
+        //nothing answers the two attempts
+        assert!(!exchange(&mut supervised));
+        assert_eq!(supervised.state(), Recovering);
+        assert!(!exchange(&mut supervised));
+        assert_eq!(
+            states(&events),
+            [(Online, Recovering, 0), (Recovering, Failed, 2)]
+        );
+
+        //a failed module is left alone
+        let written = spi.written().len();
+        assert!(!exchange(&mut supervised));
+        assert_eq!(spi.written().len(), written);
+
+        supervised.retry();
+        accept_configuration(&spi);
+        assert!(!exchange(&mut supervised));
+        assert_eq!(
+            states(&events),
+            [(Failed, Recovering, 0), (Recovering, Online, 1)]
+        );
+    }
+
+    #[test]
+    fn resets_a_lost_module_for_the_safe_state() {
+        let (mut supervised, spi, reset, _) = supervised(SupervisorConfig {
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+            ..immediate_retries()
+        });
+        spi.reply(feedback());
+        assert!(supervised.safe_state().is_ok());
+
+        //the reset when the module is lost fails, the safe state has to report it
+        reset.set_failing(true);
+        spi.reply(bootmessage(OUTPUT6, 1, [1, 0, 0]));
+        assert!(!exchange(&mut supervised));
+        assert!(matches!(
+            supervised.safe_state(),
+            Err(GoModuleError::ModuleSetupError(ModuleSetupError::ResetPin))
+        ));
+
+        reset.set_failing(false);
+        let resets = reset.pulses();
+        assert!(supervised.safe_state().is_ok());
+        assert_eq!(reset.pulses(), resets + 1);
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_maximum() {
+        let config = SupervisorConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let backoff: Vec<_> = (0..7).map(|attempts| config.backoff(attempts)).collect();
+        assert_eq!(
+            backoff,
+            [0, 100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(config.backoff(u32::MAX), Duration::from_secs(1));
+    }
+}
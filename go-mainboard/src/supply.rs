@@ -0,0 +1,401 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+///Supply inputs of the controller, K30 is the battery and K15 the ignition key inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SupplyInput {
+    K30,
+    K15A,
+    K15B,
+    K15C,
+}
+
+impl SupplyInput {
+    pub fn is_ignition(&self) -> bool {
+        !matches!(self, SupplyInput::K30)
+    }
+}
+
+#[derive(Debug)]
+pub enum SupplyError {
+    Io(PathBuf, std::io::Error),
+    ///No IIO device with the expected name was found
+    NoAdc,
+    ///The controller does not have this supply input
+    NoSuchInput(SupplyInput),
+    ///A value read from the ADC could not be parsed
+    InvalidValue(PathBuf),
+}
+
+///Where the supply voltages come from, the values are in mV at the supply input
+pub trait SupplyBackend {
+    fn read_millivolts(&mut self, input: SupplyInput) -> Result<u32, SupplyError>;
+}
+
+///Where a supply input is measured, the wiring differs between controllers and is not
+///documented so it has to come from the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupplyChannel {
+    pub input: SupplyInput,
+    ///The `N` of `in_voltageN_raw`
+    pub channel: u32,
+    ///How the input is divided down in front of the ADC,
+    ///supply voltage = ADC voltage * divider.0 / divider.1
+    pub divider: (u32, u32),
+}
+
+///Reads the supply voltages from an ADC through the IIO subsystem, the ADC voltage is scaled with
+///the `in_voltageN_scale` the driver reports
+pub struct IioSupply {
+    device: PathBuf,
+    channels: Vec<SupplyChannel>,
+}
+
+impl IioSupply {
+    ///Find the IIO device called `name` below `/sys`
+    pub fn new(name: &str, channels: Vec<SupplyChannel>) -> Result<Self, SupplyError> {
+        Self::with_root("/sys", name, channels)
+    }
+
+    ///Find the IIO device called `name` below a sysfs root, normally `/sys`
+    pub fn with_root(
+        root: impl AsRef<Path>,
+        name: &str,
+        channels: Vec<SupplyChannel>,
+    ) -> Result<Self, SupplyError> {
+        let dir = root.as_ref().join("bus/iio/devices");
+        let entries = fs::read_dir(&dir).map_err(|err| SupplyError::Io(dir.clone(), err))?;
+        let device = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|device| {
+                fs::read_to_string(device.join("name"))
+                    .is_ok_and(|device_name| device_name.trim() == name)
+            })
+            .ok_or(SupplyError::NoAdc)?;
+        Ok(IioSupply { device, channels })
+    }
+
+    ///Supply inputs that have a channel, to pass to [SupplyMonitor::with_inputs]
+    pub fn inputs(&self) -> impl Iterator<Item = SupplyInput> + '_ {
+        self.channels.iter().map(|channel| channel.input)
+    }
+
+    fn read_value(&self, attribute: &str) -> Result<Option<f64>, SupplyError> {
+        let path = self.device.join(attribute);
+        match fs::read_to_string(&path) {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| SupplyError::InvalidValue(path)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(SupplyError::Io(path, err)),
+        }
+    }
+}
+
+impl SupplyBackend for IioSupply {
+    fn read_millivolts(&mut self, input: SupplyInput) -> Result<u32, SupplyError> {
+        let SupplyChannel {
+            channel, divider, ..
+        } = *self
+            .channels
+            .iter()
+            .find(|channel| channel.input == input)
+            .ok_or(SupplyError::NoSuchInput(input))?;
+        let raw_attribute = format!("in_voltage{}_raw", channel);
+        let raw = self
+            .read_value(&raw_attribute)?
+            .ok_or_else(|| SupplyError::InvalidValue(self.device.join(&raw_attribute)))?;
+        //the scale is in mV per LSB and is either per channel or shared by all channels
+        let scale = match self.read_value(&format!("in_voltage{}_scale", channel))? {
+            Some(scale) => scale,
+            None => self
+                .read_value("in_voltage_scale")?
+                .ok_or_else(|| SupplyError::InvalidValue(self.device.join("in_voltage_scale")))?,
+        };
+        let (numerator, denominator) = divider;
+        Ok((raw * scale * numerator as f64 / denominator as f64).round() as u32)
+    }
+}
+
+///Supply voltages set by hand, for testing applications without a controller
+#[derive(Debug, Clone, Default)]
+pub struct FakeSupply {
+    millivolts: BTreeMap<SupplyInput, u32>,
+}
+
+impl FakeSupply {
+    pub fn set(&mut self, input: SupplyInput, millivolts: u32) {
+        self.millivolts.insert(input, millivolts);
+    }
+}
+
+impl SupplyBackend for FakeSupply {
+    fn read_millivolts(&mut self, input: SupplyInput) -> Result<u32, SupplyError> {
+        self.millivolts
+            .get(&input)
+            .copied()
+            .ok_or(SupplyError::NoSuchInput(input))
+    }
+}
+
+///Voltage limits in mV, a limit is only cleared again once the voltage is `hysteresis` back inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupplyThresholds {
+    pub under_voltage: u32,
+    pub over_voltage: u32,
+    ///K15 counts as switched on above this voltage
+    pub ignition_on: u32,
+    pub hysteresis: u32,
+}
+
+impl Default for SupplyThresholds {
+    ///Limits that fit both 12V and 24V systems
+    fn default() -> Self {
+        SupplyThresholds {
+            under_voltage: 8_000,
+            over_voltage: 32_000,
+            ignition_on: 6_000,
+            hysteresis: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupplyEvent {
+    UnderVoltage {
+        input: SupplyInput,
+        millivolts: u32,
+    },
+    OverVoltage {
+        input: SupplyInput,
+        millivolts: u32,
+    },
+    ///The voltage is back between the limits after an under- or over-voltage
+    VoltageNormal {
+        input: SupplyInput,
+        millivolts: u32,
+    },
+    IgnitionOn(SupplyInput),
+    IgnitionOff(SupplyInput),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Level {
+    #[default]
+    Normal,
+    Under,
+    Over,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct InputState {
+    millivolts: u32,
+    level: Level,
+    ignition: bool,
+}
+
+///Keeps track of the supply voltages and reports when they cross their limits
+pub struct SupplyMonitor<Backend> {
+    backend: Backend,
+    thresholds: SupplyThresholds,
+    inputs: BTreeMap<SupplyInput, InputState>,
+}
+
+impl<Backend: SupplyBackend> SupplyMonitor<Backend> {
+    pub fn with_inputs(
+        backend: Backend,
+        inputs: impl IntoIterator<Item = SupplyInput>,
+        thresholds: SupplyThresholds,
+    ) -> Self {
+        SupplyMonitor {
+            backend,
+            thresholds,
+            inputs: inputs
+                .into_iter()
+                .map(|input| (input, InputState::default()))
+                .collect(),
+        }
+    }
+
+    ///Read all supply inputs and return what changed since the last poll.
+    ///Ignition inputs only report ignition events, the battery input reports the voltage limits
+    pub fn poll(&mut self) -> Result<Vec<SupplyEvent>, SupplyError> {
+        let SupplyThresholds {
+            under_voltage,
+            over_voltage,
+            ignition_on,
+            hysteresis,
+        } = self.thresholds;
+        let mut events = Vec::new();
+        for (input, state) in self.inputs.iter_mut() {
+            let input = *input;
+            let millivolts = self.backend.read_millivolts(input)?;
+            state.millivolts = millivolts;
+            if input.is_ignition() {
+                let ignition = if state.ignition {
+                    millivolts + hysteresis > ignition_on
+                } else {
+                    millivolts > ignition_on
+                };
+                if ignition != state.ignition {
+                    state.ignition = ignition;
+                    events.push(if ignition {
+                        SupplyEvent::IgnitionOn(input)
+                    } else {
+                        SupplyEvent::IgnitionOff(input)
+                    });
+                }
+                continue;
+            }
+            let level = match state.level {
+                _ if millivolts < under_voltage => Level::Under,
+                _ if millivolts > over_voltage => Level::Over,
+                Level::Under if millivolts < under_voltage + hysteresis => Level::Under,
+                Level::Over if millivolts + hysteresis > over_voltage => Level::Over,
+                _ => Level::Normal,
+            };
+            if level != state.level {
+                state.level = level;
+                events.push(match level {
+                    Level::Under => SupplyEvent::UnderVoltage { input, millivolts },
+                    Level::Over => SupplyEvent::OverVoltage { input, millivolts },
+                    Level::Normal => SupplyEvent::VoltageNormal { input, millivolts },
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    ///The voltage of an input at the last poll
+    pub fn millivolts(&self, input: SupplyInput) -> Option<u32> {
+        self.inputs.get(&input).map(|state| state.millivolts)
+    }
+
+    ///Any of the ignition inputs was on at the last poll
+    pub fn ignition(&self) -> bool {
+        self.inputs.values().any(|state| state.ignition)
+    }
+
+    ///The battery voltage was between the limits at the last poll
+    pub fn supply_ok(&self) -> bool {
+        self.inputs
+            .get(&SupplyInput::K30)
+            .is_none_or(|state| state.level == Level::Normal)
+    }
+
+    pub fn backend_mut(&mut self) -> &mut Backend {
+        &mut self.backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_scales_iio_channels() {
+        let root = tempfile::tempdir().unwrap();
+        let device = root.path().join("bus/iio/devices/iio:device0");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("name"), "adc\n").unwrap();
+        fs::write(device.join("in_voltage_scale"), "0.805664062\n").unwrap();
+        fs::write(device.join("in_voltage0_raw"), "1354\n").unwrap();
+        fs::write(device.join("in_voltage1_raw"), "0\n").unwrap();
+        fs::write(device.join("in_voltage2_raw"), "1000\n").unwrap();
+        fs::write(device.join("in_voltage2_scale"), "2\n").unwrap();
+        let channels = vec![
+            SupplyChannel {
+                input: SupplyInput::K30,
+                channel: 0,
+                divider: (11, 1),
+            },
+            SupplyChannel {
+                input: SupplyInput::K15A,
+                channel: 1,
+                divider: (11, 1),
+            },
+            SupplyChannel {
+                input: SupplyInput::K15B,
+                channel: 2,
+                divider: (3, 2),
+            },
+        ];
+        let mut adc = IioSupply::with_root(root.path(), "adc", channels).unwrap();
+        assert_eq!(
+            adc.inputs().collect::<Vec<_>>(),
+            [SupplyInput::K30, SupplyInput::K15A, SupplyInput::K15B]
+        );
+        assert_eq!(adc.read_millivolts(SupplyInput::K30).unwrap(), 12_000);
+        assert_eq!(adc.read_millivolts(SupplyInput::K15A).unwrap(), 0);
+        //a scale of the channel itself wins over the shared one
+        assert_eq!(adc.read_millivolts(SupplyInput::K15B).unwrap(), 3_000);
+        assert!(matches!(
+            adc.read_millivolts(SupplyInput::K15C),
+            Err(SupplyError::NoSuchInput(SupplyInput::K15C))
+        ));
+        assert!(matches!(
+            IioSupply::with_root(root.path(), "other", Vec::new()),
+            Err(SupplyError::NoAdc)
+        ));
+    }
+
+    #[test]
+    fn reports_voltage_and_ignition_events() {
+        let mut monitor = SupplyMonitor::with_inputs(
+            FakeSupply::default(),
+            [SupplyInput::K30, SupplyInput::K15A],
+            SupplyThresholds::default(),
+        );
+        monitor.backend_mut().set(SupplyInput::K30, 24_000);
+        monitor.backend_mut().set(SupplyInput::K15A, 0);
+        assert_eq!(monitor.poll().unwrap(), []);
+
+        monitor.backend_mut().set(SupplyInput::K15A, 24_000);
+        assert_eq!(
+            monitor.poll().unwrap(),
+            [SupplyEvent::IgnitionOn(SupplyInput::K15A)]
+        );
+        assert!(monitor.ignition());
+
+        monitor.backend_mut().set(SupplyInput::K30, 7_000);
+        assert_eq!(
+            monitor.poll().unwrap(),
+            [SupplyEvent::UnderVoltage {
+                input: SupplyInput::K30,
+                millivolts: 7_000
+            }]
+        );
+        assert!(!monitor.supply_ok());
+
+        //inside the hysteresis nothing changes
+        monitor.backend_mut().set(SupplyInput::K30, 8_200);
+        assert_eq!(monitor.poll().unwrap(), []);
+
+        monitor.backend_mut().set(SupplyInput::K30, 12_000);
+        monitor.backend_mut().set(SupplyInput::K15A, 1_000);
+        assert_eq!(
+            monitor.poll().unwrap(),
+            [
+                SupplyEvent::VoltageNormal {
+                    input: SupplyInput::K30,
+                    millivolts: 12_000
+                },
+                SupplyEvent::IgnitionOff(SupplyInput::K15A)
+            ]
+        );
+
+        monitor.backend_mut().set(SupplyInput::K30, 33_000);
+        assert_eq!(
+            monitor.poll().unwrap(),
+            [SupplyEvent::OverVoltage {
+                input: SupplyInput::K30,
+                millivolts: 33_000
+            }]
+        );
+    }
+}
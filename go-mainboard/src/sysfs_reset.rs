@@ -1,32 +1,60 @@
 use crate::ControllerSlot;
-use embedded_hal::digital::OutputPin;
-use std::{fs::File, os::unix::fs::FileExt};
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+use std::{fs::File, os::unix::fs::FileExt, path::Path};
 
 pub struct SysfsOutput {
     fd: File,
 }
 
+#[derive(Debug)]
+pub struct SysfsOutputError(pub std::io::Error);
+
+impl embedded_hal::digital::Error for SysfsOutputError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
 impl SysfsOutput {
     pub fn new(slot: ControllerSlot) -> Result<Self, std::io::Error> {
+        Self::from_led(&format!("ResetM-{}", slot as u8 + 1))
+    }
+
+    ///Open the LED with the given name in `/sys/class/leds`
+    pub fn from_led(name: &str) -> Result<Self, std::io::Error> {
+        Self::from_led_with_root("/sys", name)
+    }
+
+    ///Open the LED with the given name below a sysfs root, normally `/sys`
+    pub fn from_led_with_root(root: impl AsRef<Path>, name: &str) -> Result<Self, std::io::Error> {
         Ok(SysfsOutput {
-            fd: std::fs::File::options()
-                .read(true)
-                .write(true)
-                .open(format!(
-                    "/sys/class/leds/ResetM-{}/brightness",
-                    slot as u8 + 1
-                ))?,
+            fd: std::fs::File::options().read(true).write(true).open(
+                root.as_ref()
+                    .join("class/leds")
+                    .join(name)
+                    .join("brightness"),
+            )?,
         })
     }
 }
 
+impl ErrorType for SysfsOutput {
+    type Error = SysfsOutputError;
+}
+
 impl OutputPin for SysfsOutput {
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        self.fd.write_at(b"0", 0)
+        self.fd
+            .write_at(b"0", 0)
+            .map(|_| ())
+            .map_err(SysfsOutputError)
     }
 
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        self.fd.write_at(b"1", 0)
+        self.fd
+            .write_at(b"1", 0)
+            .map(|_| ())
+            .map_err(SysfsOutputError)
     }
 
     fn set_state(&mut self, state: embedded_hal::digital::PinState) -> Result<(), Self::Error> {
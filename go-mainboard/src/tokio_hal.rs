@@ -0,0 +1,227 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use embedded_hal::spi::{self as blocking, ErrorKind, ErrorType, Operation};
+use embedded_hal_async::{delay::DelayNs, spi::SpiDevice};
+use linux_embedded_hal::{SPIError, SpidevDevice};
+
+///A spidev device, or another blocking SPI device, for async code. The blocking transfers run on
+///the tokio blocking pool so other tasks keep running while the kernel handles the transfer
+pub struct AsyncSpidev<SPI = SpidevDevice> {
+    spi: Arc<Mutex<SPI>>,
+}
+
+#[derive(Debug)]
+pub enum AsyncSpidevError<E = SPIError> {
+    Spi(E),
+    ///The blocking task doing the transfer panicked or was cancelled
+    Task,
+}
+
+impl<E: blocking::Error> blocking::Error for AsyncSpidevError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            AsyncSpidevError::Spi(err) => err.kind(),
+            AsyncSpidevError::Task => ErrorKind::Other,
+        }
+    }
+}
+
+impl<SPI> AsyncSpidev<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        AsyncSpidev {
+            spi: Arc::new(Mutex::new(spi)),
+        }
+    }
+}
+
+///Clones share the device, their transactions take turns
+impl<SPI> Clone for AsyncSpidev<SPI> {
+    fn clone(&self) -> Self {
+        AsyncSpidev {
+            spi: self.spi.clone(),
+        }
+    }
+}
+
+///An operation with its own buffers so it can be moved to the blocking pool
+enum OwnedOperation {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+    Transfer(Vec<u8>, Vec<u8>),
+    TransferInPlace(Vec<u8>),
+    DelayNs(u32),
+}
+
+impl<SPI: blocking::SpiDevice> ErrorType for AsyncSpidev<SPI> {
+    type Error = AsyncSpidevError<SPI::Error>;
+}
+
+impl<SPI> SpiDevice for AsyncSpidev<SPI>
+where
+    SPI: blocking::SpiDevice + Send + 'static,
+    SPI::Error: Send,
+{
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        let mut owned: Vec<OwnedOperation> = operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Read(read) => OwnedOperation::Read(vec![0; read.len()]),
+                Operation::Write(write) => OwnedOperation::Write(write.to_vec()),
+                Operation::Transfer(read, write) => {
+                    OwnedOperation::Transfer(vec![0; read.len()], write.to_vec())
+                }
+                Operation::TransferInPlace(buf) => OwnedOperation::TransferInPlace(buf.to_vec()),
+                Operation::DelayNs(ns) => OwnedOperation::DelayNs(*ns),
+            })
+            .collect();
+        let spi = self.spi.clone();
+        owned = tokio::task::spawn_blocking(move || {
+            let mut spi = spi.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let mut borrowed: Vec<Operation<'_, u8>> = owned
+                .iter_mut()
+                .map(|operation| match operation {
+                    OwnedOperation::Read(read) => Operation::Read(read),
+                    OwnedOperation::Write(write) => Operation::Write(write),
+                    OwnedOperation::Transfer(read, write) => Operation::Transfer(read, write),
+                    OwnedOperation::TransferInPlace(buf) => Operation::TransferInPlace(buf),
+                    OwnedOperation::DelayNs(ns) => Operation::DelayNs(*ns),
+                })
+                .collect();
+            let result = spi.transaction(&mut borrowed);
+            drop(borrowed);
+            result.map(|_| owned)
+        })
+        .await
+        .map_err(|_| AsyncSpidevError::Task)?
+        .map_err(AsyncSpidevError::Spi)?;
+        for (operation, owned) in operations.iter_mut().zip(owned) {
+            match (operation, owned) {
+                (Operation::Read(read), OwnedOperation::Read(data))
+                | (Operation::Transfer(read, _), OwnedOperation::Transfer(data, _))
+                | (Operation::TransferInPlace(read), OwnedOperation::TransferInPlace(data)) => {
+                    read.copy_from_slice(&data)
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+///Delay on the tokio timer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioDelay;
+
+impl DelayNs for TokioDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        tokio::time::sleep(Duration::from_nanos(ns as u64)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use embedded_hal::spi::ErrorKind;
+    use go_module_base::{
+        mock::{bootmessage, module_frame, Answer, ScriptedPin, ScriptedSpi},
+        GoModuleUnknown, ModuleCommunicationDirection, ModuleCommunicationType,
+    };
+
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn runs_transactions_on_the_blocking_device() {
+        let spi = ScriptedSpi::new();
+        spi.reply(vec![1, 2, 3]).answer(Answer::Fail);
+        let mut device = AsyncSpidev::new(spi.clone());
+        let mut rx = [0xff; 3];
+        let mut read = [0xff; 2];
+        let mut in_place = [4, 5];
+        block_on(device.transaction(&mut [
+            Operation::Transfer(&mut rx, &[9, 8, 7]),
+            Operation::Write(&[6]),
+            Operation::Read(&mut read),
+            Operation::TransferInPlace(&mut in_place),
+        ]))
+        .unwrap();
+        assert_eq!(rx, [1, 2, 3]);
+        assert_eq!(read, [0, 0]);
+        assert_eq!(in_place, [0, 0]);
+        assert_eq!(spi.written(), [vec![9, 8, 7], vec![6], vec![4, 5]]);
+
+        //a failing transfer leaves the buffers of the caller alone
+        let mut rx = [0xff; 3];
+        assert!(matches!(
+            block_on(device.transaction(&mut [Operation::Transfer(&mut rx, &[1, 2, 3])])),
+            Err(AsyncSpidevError::Spi(ErrorKind::Other))
+        ));
+        assert_eq!(rx, [0xff; 3]);
+    }
+
+    #[test]
+    fn drives_a_module_with_the_async_api() {
+        let spi = ScriptedSpi::new();
+        let reset = ScriptedPin::new();
+        let module = GoModuleUnknown::new(
+            AsyncSpidev::new(spi.clone()),
+            reset.clone(),
+            ScriptedPin::new(),
+            TokioDelay,
+            1,
+        );
+        let data = module_frame(11, ModuleCommunicationType::Data, 1, &[42], 8);
+        spi.reply(bootmessage([20, 10, 3], 1, [1, 0, 0]))
+            .reply(data.clone());
+        block_on(async {
+            let Ok(mut module) = module.module_reset().await else {
+                panic!("the reset failed");
+            };
+            assert_eq!(reset.pulses(), 1);
+            let boot = module.escape_module_bootloader().await.unwrap();
+            assert_eq!(boot[6..9], [20, 10, 3]);
+            let mut tx = [0; 8];
+            let mut rx = [0; 8];
+            module
+                .send_receive_spi(
+                    ModuleCommunicationDirection::ToModule,
+                    11,
+                    ModuleCommunicationType::Data,
+                    1,
+                    &mut tx,
+                    &mut rx,
+                    8,
+                    0,
+                )
+                .await
+                .unwrap();
+            assert_eq!(rx.to_vec(), data);
+        });
+    }
+
+    #[test]
+    fn delays_on_the_tokio_timer() {
+        block_on(async {
+            let start = Instant::now();
+            TokioDelay.delay_ms(20).await;
+            assert!(start.elapsed() >= Duration::from_millis(20));
+            let start = Instant::now();
+            TokioDelay.delay_us(500).await;
+            assert!(start.elapsed() >= Duration::from_micros(500));
+        });
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "modules")]
+use crate::io_config::ControllerIo;
+use crate::ControllerSlot;
+
+pub const WATCHDOG_DEVICE: &str = "/dev/watchdog";
+
+#[derive(Debug)]
+pub enum WatchdogError {
+    Io(PathBuf, std::io::Error),
+}
+
+enum WatchdogOutput {
+    Device {
+        path: PathBuf,
+        file: File,
+    },
+    ///Feeds are only counted, for tests and for running without resetting the controller
+    DryRun,
+}
+
+///A watchdog device, once opened the controller is reset when it is not fed within the timeout of the device
+pub struct Watchdog {
+    output: WatchdogOutput,
+    feeds: u64,
+    last_feed: Option<Instant>,
+}
+
+impl Watchdog {
+    ///Open and arm the watchdog device, usually [WATCHDOG_DEVICE]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WatchdogError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .map_err(|err| WatchdogError::Io(path.clone(), err))?;
+        Ok(Watchdog {
+            output: WatchdogOutput::Device { path, file },
+            feeds: 0,
+            last_feed: None,
+        })
+    }
+
+    ///A watchdog that only counts the feeds
+    pub fn dry_run() -> Self {
+        Watchdog {
+            output: WatchdogOutput::DryRun,
+            feeds: 0,
+            last_feed: None,
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self.output, WatchdogOutput::DryRun)
+    }
+
+    pub fn feed(&mut self) -> Result<(), WatchdogError> {
+        if let WatchdogOutput::Device { path, file } = &mut self.output {
+            file.write_all(b"\0")
+                .map_err(|err| WatchdogError::Io(path.clone(), err))?;
+        }
+        self.feeds += 1;
+        self.last_feed = Some(Instant::now());
+        Ok(())
+    }
+
+    ///Number of times the watchdog was fed
+    pub fn feeds(&self) -> u64 {
+        self.feeds
+    }
+
+    pub fn last_feed(&self) -> Option<Instant> {
+        self.last_feed
+    }
+
+    ///Stop the watchdog on an orderly exit, writing the magic close character
+    ///drivers with nowayout set keep running and reset the controller anyway
+    pub fn disarm(mut self) -> Result<(), WatchdogError> {
+        if let WatchdogOutput::Device { path, file } = &mut self.output {
+            file.write_all(b"V")
+                .map_err(|err| WatchdogError::Io(path.clone(), err))?;
+        }
+        Ok(())
+    }
+}
+
+///Communication health of a watched module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleHealth {
+    ///Exchanges that failed since the last successful one
+    pub consecutive_failures: u32,
+    ///Time since the last successful exchange, or since watching started when there was none
+    pub since_success: Duration,
+    ///No successful exchange within the deadline of the module
+    pub late: bool,
+    pub critical: bool,
+}
+
+struct WatchedModule {
+    slot: ControllerSlot,
+    deadline: Duration,
+    critical: bool,
+    last_success: Instant,
+    consecutive_failures: u32,
+}
+
+///Feeds a watchdog only while the I/O cycle is healthy, a critical module without a successful
+///exchange within its deadline stops the feeding so the controller resets out of a stuck state,
+///a late module that is not critical is reported but keeps the watchdog fed
+pub struct IoWatchdog {
+    watchdog: Watchdog,
+    modules: Vec<WatchedModule>,
+}
+
+impl IoWatchdog {
+    pub fn new(watchdog: Watchdog) -> Self {
+        IoWatchdog {
+            watchdog,
+            modules: Vec::new(),
+        }
+    }
+
+    ///Watch the module in a slot, it gets its deadline from now for the first successful exchange
+    pub fn watch(&mut self, slot: ControllerSlot, deadline: Duration, critical: bool) {
+        self.modules.retain(|module| module.slot != slot);
+        self.modules.push(WatchedModule {
+            slot,
+            deadline,
+            critical,
+            last_success: Instant::now(),
+            consecutive_failures: 0,
+        });
+    }
+
+    ///Watch every module of the configured I/O as critical
+    #[cfg(feature = "modules")]
+    pub fn watch_io(&mut self, io: &ControllerIo, deadline: Duration) {
+        for slot in io.slots() {
+            self.watch(slot, deadline, true);
+        }
+    }
+
+    ///Record the result of an exchange with the module in a slot, slots that are not watched are ignored
+    pub fn record<T, E>(&mut self, slot: ControllerSlot, result: &Result<T, E>) {
+        if let Some(module) = self.modules.iter_mut().find(|module| module.slot == slot) {
+            if result.is_ok() {
+                module.last_success = Instant::now();
+                module.consecutive_failures = 0;
+            } else {
+                module.consecutive_failures += 1;
+            }
+        }
+    }
+
+    pub fn health(&self, slot: ControllerSlot) -> Option<ModuleHealth> {
+        self.modules
+            .iter()
+            .find(|module| module.slot == slot)
+            .map(Self::module_health)
+    }
+
+    ///Health of all watched modules, in the order they were added
+    pub fn modules(&self) -> impl Iterator<Item = (ControllerSlot, ModuleHealth)> + '_ {
+        self.modules
+            .iter()
+            .map(|module| (module.slot, Self::module_health(module)))
+    }
+
+    ///True when no critical module is late
+    pub fn is_healthy(&self) -> bool {
+        self.modules()
+            .all(|(_, health)| !(health.critical && health.late))
+    }
+
+    ///Feed the watchdog when the I/O is healthy, returns whether it was fed
+    pub fn service(&mut self) -> Result<bool, WatchdogError> {
+        if !self.is_healthy() {
+            return Ok(false);
+        }
+        self.watchdog.feed()?;
+        Ok(true)
+    }
+
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    pub fn into_watchdog(self) -> Watchdog {
+        self.watchdog
+    }
+
+    fn module_health(module: &WatchedModule) -> ModuleHealth {
+        let since_success = module.last_success.elapsed();
+        ModuleHealth {
+            consecutive_failures: module.consecutive_failures,
+            since_success,
+            late: since_success > module.deadline,
+            critical: module.critical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const OK: Result<(), ()> = Ok(());
+    const FAILED: Result<(), ()> = Err(());
+
+    #[test]
+    fn feeds_while_every_module_communicates() {
+        let mut io = IoWatchdog::new(Watchdog::dry_run());
+        io.watch(ControllerSlot::Slot1, Duration::from_millis(50), true);
+        io.watch(ControllerSlot::Slot2, Duration::from_millis(50), true);
+        for _ in 0..3 {
+            io.record(ControllerSlot::Slot1, &OK);
+            io.record(ControllerSlot::Slot2, &FAILED);
+            io.record(ControllerSlot::Slot2, &OK);
+            assert!(io.service().unwrap());
+        }
+        assert_eq!(io.watchdog().feeds(), 3);
+        assert_eq!(
+            io.health(ControllerSlot::Slot2)
+                .unwrap()
+                .consecutive_failures,
+            0
+        );
+    }
+
+    #[test]
+    fn stops_feeding_when_a_critical_module_is_unreachable() {
+        let mut io = IoWatchdog::new(Watchdog::dry_run());
+        io.watch(ControllerSlot::Slot1, Duration::from_millis(30), true);
+        io.watch(ControllerSlot::Slot2, Duration::from_millis(30), false);
+        thread::sleep(Duration::from_millis(40));
+        io.record(ControllerSlot::Slot1, &OK);
+        io.record(ControllerSlot::Slot2, &FAILED);
+        //a late module that is not critical keeps the watchdog fed
+        assert!(io.health(ControllerSlot::Slot2).unwrap().late);
+        assert!(io.service().unwrap());
+        thread::sleep(Duration::from_millis(40));
+        io.record(ControllerSlot::Slot1, &FAILED);
+        let health = io.health(ControllerSlot::Slot1).unwrap();
+        assert!(health.late);
+        assert_eq!(health.consecutive_failures, 1);
+        assert!(!io.service().unwrap());
+        assert_eq!(io.watchdog().feeds(), 1);
+    }
+}
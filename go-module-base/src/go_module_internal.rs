@@ -13,9 +13,76 @@ pub struct GoModule<SPI, ResetPin, InterruptPin, Delay> {
     interrupt: InterruptPin,
     pub delay: Delay,
     slot: u8,
+    status: ModuleStatus,
 }
 
-const BOOTMESSAGELENGTH: usize = 46;
+impl<SPI, ResetPin, InterruptPin, Delay> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
+    pub fn new(
+        spi: SPI,
+        reset: ResetPin,
+        interrupt: InterruptPin,
+        delay: Delay,
+        slot: u8,
+    ) -> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
+        debug_assert!(slot > 0, "slot needs to be larger than 0");
+        GoModuleUnknown {
+            spi,
+            reset,
+            interrupt,
+            delay,
+            slot,
+        }
+    }
+}
+
+pub const BOOTMESSAGELENGTH: usize = 46;
+
+///Identification of a module as reported in its boot message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModuleIdentity {
+    pub id: [u8; 3],
+    pub hardware_version: u8,
+    pub firmware_version: [u8; 3],
+}
+
+impl ModuleIdentity {
+    pub fn from_bootmessage(bootmessage: &[u8; BOOTMESSAGELENGTH]) -> Self {
+        ModuleIdentity {
+            id: [bootmessage[6], bootmessage[7], bootmessage[8]],
+            hardware_version: bootmessage[9],
+            firmware_version: [bootmessage[10], bootmessage[11], bootmessage[12]],
+        }
+    }
+}
+
+///Communication statistics of a module, updated on every exchange with the module
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModuleStatus {
+    pub exchanges: u32,
+    pub failures: u32,
+    pub consecutive_failures: u32,
+}
+
+impl ModuleStatus {
+    ///The last exchange with the module succeeded
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+
+    fn record_failure(&mut self) {
+        self.failures = self.failures.wrapping_add(1);
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    }
+
+    fn record<T, E>(&mut self, result: &Result<T, E>) {
+        self.exchanges = self.exchanges.wrapping_add(1);
+        if result.is_ok() {
+            self.consecutive_failures = 0;
+        } else {
+            self.record_failure();
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub enum GoModuleError<SPI, ResetPin, InterruptPin> {
@@ -26,11 +93,29 @@ pub enum GoModuleError<SPI, ResetPin, InterruptPin> {
     CommunicationError(CommunicationError),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModuleSetupError {
     InterruptPin,
     ResetPin,
     Spi,
+    ///The controller does not have the requested slot
+    NoSuchSlot,
+    ///There is no slot map built in for the controller, its slots have to be described
+    NoSlotMap,
+}
+
+///Reason why a module driver could not be built, returned together with the module and the
+///configuration so the build can be retried
+#[derive(Debug, Clone, Copy)]
+pub enum ConfigurationError {
+    ///The module did not answer the bootloader escape
+    NoBootMessage,
+    ///The boot message belongs to a different kind of module
+    WrongModule(ModuleIdentity),
+    ///The configuration could not be sent to the module
+    NotSent,
+    ///The module did not answer with a valid data frame after it was configured
+    NoResponse,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -38,6 +123,8 @@ pub enum CommunicationError {
     ModuleUnavailable,
     ChecksumIncorrect,
     UnableToSerDe,
+    ///The module answered with its boot message, it restarted and waits in the bootloader
+    InBootloader,
 }
 
 #[repr(u8)]
@@ -57,13 +144,11 @@ pub enum ModuleCommunicationType {
 #[cfg(not(feature = "async"))]
 pub mod go_module {
 
-    use core::usize;
-
     use crate::GoModuleUnknown;
 
     use super::{
-        module_checksum, CommunicationError, GoModule, GoModuleError, ModuleCommunicationDirection,
-        ModuleCommunicationType, BOOTMESSAGELENGTH,
+        is_bootmessage, module_checksum, CommunicationError, GoModule, GoModuleError,
+        ModuleCommunicationDirection, ModuleCommunicationType, ModuleStatus, BOOTMESSAGELENGTH,
     };
     use embedded_hal::delay::DelayNs;
     use embedded_hal::digital::{InputPin, OutputPin, PinState};
@@ -76,23 +161,7 @@ pub mod go_module {
         InterruptPin: InputPin,
         Delay: DelayNs,
     {
-        pub fn new(
-            spi: SPI,
-            reset: ResetPin,
-            interrupt: InterruptPin,
-            delay: Delay,
-            slot: u8,
-        ) -> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
-            debug_assert!(slot > 0, "slot needs to be larger than 0");
-            GoModuleUnknown {
-                spi,
-                reset,
-                interrupt,
-                delay,
-                slot,
-            }
-        }
-
+        #[allow(clippy::type_complexity)]
         pub fn module_reset(
             mut self,
         ) -> Result<
@@ -113,6 +182,7 @@ pub mod go_module {
                 interrupt: self.interrupt,
                 delay: self.delay,
                 slot: self.slot,
+                status: ModuleStatus::default(),
             })
         }
     }
@@ -124,6 +194,7 @@ pub mod go_module {
         InterruptPin: InputPin,
         Delay: DelayNs,
     {
+        #[allow(clippy::type_complexity)]
         pub fn escape_module_bootloader(
             &mut self,
         ) -> Result<
@@ -142,6 +213,7 @@ pub mod go_module {
             Ok(rx)
         }
 
+        #[allow(clippy::too_many_arguments, clippy::type_complexity)]
         pub fn send_spi(
             &mut self,
             direction: ModuleCommunicationDirection,
@@ -171,10 +243,12 @@ pub mod go_module {
             //          .map_err(GoModuleError::InterruptPin)?
             //    {
             self.delay.delay_us(delay_us);
-            self.spi
+            let result = self
+                .spi
                 .transaction(&mut transactions)
-                .map_err(GoModuleError::SPI)?;
-            Ok(())
+                .map_err(GoModuleError::SPI);
+            self.status.record(&result);
+            result
             //            } else {
             //              Err(GoModuleError::CommunicationError(
             //                CommunicationError::ModuleUnavailable,
@@ -182,6 +256,7 @@ pub mod go_module {
             //    }
         }
 
+        #[allow(clippy::too_many_arguments, clippy::type_complexity)]
         pub fn send_receive_spi(
             &mut self,
             direction: ModuleCommunicationDirection,
@@ -216,16 +291,20 @@ pub mod go_module {
             //          .map_err(GoModuleError::InterruptPin)?
             //    {
             self.delay.delay_us(delay_us);
-            self.spi
-                .transaction(&mut transactions)
-                .map_err(GoModuleError::SPI)?;
-            if module_checksum(&rx, len) == rx[len - 1] && rx[1] as usize == len {
-                Ok(())
-            } else {
-                Err(GoModuleError::CommunicationError(
+            let result = match self.spi.transaction(&mut transactions) {
+                Err(err) => Err(GoModuleError::SPI(err)),
+                Ok(()) if module_checksum(rx, len) == rx[len - 1] && rx[1] as usize == len => {
+                    Ok(())
+                }
+                Ok(()) if is_bootmessage(rx) => Err(GoModuleError::CommunicationError(
+                    CommunicationError::InBootloader,
+                )),
+                Ok(()) => Err(GoModuleError::CommunicationError(
                     CommunicationError::ChecksumIncorrect,
-                ))
-            }
+                )),
+            };
+            self.status.record(&result);
+            result
             //            } else {
             //              Err(GoModuleError::CommunicationError(
             //                CommunicationError::ModuleUnavailable,
@@ -233,6 +312,7 @@ pub mod go_module {
             //    }
         }
 
+        #[allow(clippy::type_complexity)]
         pub fn get_module_interrupt_state(
             &mut self,
         ) -> Result<PinState, GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>>
@@ -248,6 +328,20 @@ pub mod go_module {
             }
         }
 
+        ///Communication statistics since the last module reset
+        pub fn status(&self) -> ModuleStatus {
+            self.status
+        }
+
+        ///Build the error for a response that passed the checksum but is not what was expected,
+        ///the exchange is counted as failed
+        pub fn invalid_response(
+            &mut self,
+        ) -> GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error> {
+            self.status.record_failure();
+            GoModuleError::CommunicationError(CommunicationError::UnableToSerDe)
+        }
+
         pub fn degrade(self) -> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
             GoModuleUnknown {
                 spi: self.spi,
@@ -265,13 +359,12 @@ pub mod go_module_async {
     use crate::GoModuleUnknown;
 
     use super::{
-        module_checksum, CommunicationError, GoModule, GoModuleError, ModuleCommunicationDirection,
-        ModuleCommunicationType, BOOTMESSAGELENGTH,
+        is_bootmessage, module_checksum, CommunicationError, GoModule, GoModuleError,
+        ModuleCommunicationDirection, ModuleCommunicationType, ModuleStatus, BOOTMESSAGELENGTH,
     };
     use embedded_hal::digital::{InputPin, OutputPin, PinState};
 
     use embedded_hal_async::delay::DelayNs;
-    #[cfg(feature = "async")]
     use embedded_hal_async::{
         digital::Wait,
         spi::{Operation, SpiDevice},
@@ -280,26 +373,10 @@ pub mod go_module_async {
     impl<SPI, ResetPin, InterruptPin, Delay> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>
     where
         SPI: SpiDevice,
-        ResetPin: OutputPin + Wait,
+        ResetPin: OutputPin,
         InterruptPin: InputPin + Wait,
         Delay: DelayNs,
     {
-        pub fn new(
-            spi: SPI,
-            reset: ResetPin,
-            interrupt: InterruptPin,
-            delay: Delay,
-            slot: u8,
-        ) -> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
-            GoModuleUnknown {
-                spi,
-                reset,
-                interrupt,
-                delay,
-                slot,
-            }
-        }
-
         pub async fn module_reset(
             mut self,
         ) -> Result<
@@ -320,6 +397,7 @@ pub mod go_module_async {
                 interrupt: self.interrupt,
                 delay: self.delay,
                 slot: self.slot,
+                status: ModuleStatus::default(),
             })
         }
     }
@@ -327,7 +405,7 @@ pub mod go_module_async {
     impl<SPI, ResetPin, InterruptPin, Delay> GoModule<SPI, ResetPin, InterruptPin, Delay>
     where
         SPI: SpiDevice,
-        ResetPin: OutputPin + Wait,
+        ResetPin: OutputPin,
         InterruptPin: InputPin + Wait,
         Delay: DelayNs,
     {
@@ -342,6 +420,7 @@ pub mod go_module_async {
             tx[0] = 19;
             tx[1] = (BOOTMESSAGELENGTH - 1) as u8;
             tx[2] = 19;
+            tx[BOOTMESSAGELENGTH - 1] = module_checksum(&tx, BOOTMESSAGELENGTH);
             self.spi
                 .transaction(&mut [Operation::Transfer(&mut rx, &tx)])
                 .await
@@ -349,6 +428,7 @@ pub mod go_module_async {
             Ok(rx)
         }
 
+        #[allow(clippy::too_many_arguments)]
         pub async fn send_spi(
             &mut self,
             direction: ModuleCommunicationDirection,
@@ -356,32 +436,37 @@ pub mod go_module_async {
             message_type: ModuleCommunicationType,
             message_index: u8,
             tx: &mut [u8],
-            delay_us: u16,
+            len: usize,
+            delay_us: u32,
         ) -> Result<(), GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>> {
+            debug_assert!(
+                len <= tx.len(),
+                "len cannot be longer than the actual buffer length"
+            );
             tx[0] = self.slot;
-            tx[1] = tx.len() as u8 - 1;
+            tx[1] = len as u8 - 1;
             tx[2] = direction as u8;
             tx[3] = module_id;
             tx[4] = message_type as u8;
             tx[5] = message_index;
-            tx[tx.len() - 1] = module_checksum(tx);
+            tx[len - 1] = module_checksum(tx, len);
 
-            let mut transactions = [
-                Operation::DelayNs(delay_us as u32 * 1000),
-                Operation::Write(tx),
-            ];
+            let mut transactions = [Operation::Write(tx)];
             self.interrupt
                 .wait_for_high()
                 .await
                 .map_err(GoModuleError::InterruptPin)?;
-
-            self.spi
+            self.delay.delay_us(delay_us).await;
+            let result = self
+                .spi
                 .transaction(&mut transactions)
                 .await
-                .map_err(GoModuleError::SPI)?;
-            Ok(())
+                .map_err(GoModuleError::SPI);
+            self.status.record(&result);
+            result
         }
 
+        #[allow(clippy::too_many_arguments)]
         pub async fn send_receive_spi(
             &mut self,
             direction: ModuleCommunicationDirection,
@@ -390,37 +475,48 @@ pub mod go_module_async {
             message_index: u8,
             tx: &mut [u8],
             rx: &mut [u8],
-            delay_us: u16,
+            len: usize,
+            delay_us: u32,
         ) -> Result<(), GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>> {
+            debug_assert!(
+                tx.len() == rx.len(),
+                "receive and transmit buffer must have equal length"
+            );
+            debug_assert!(
+                len <= tx.len(),
+                "len cannot be longer than the actual buffer length"
+            );
             tx[0] = self.slot;
-            tx[1] = tx.len() as u8 - 1;
+            tx[1] = len as u8 - 1;
             tx[2] = direction as u8;
             tx[3] = module_id;
             tx[4] = message_type as u8;
             tx[5] = message_index;
-            tx[tx.len() - 1] = module_checksum(tx);
+            tx[len - 1] = module_checksum(tx, len);
 
-            let mut transactions = [
-                Operation::DelayNs(delay_us as u32 * 100),
-                Operation::Transfer(rx, tx),
-            ];
+            let mut transactions = [Operation::Transfer(rx, tx)];
             self.interrupt
                 .wait_for_high()
                 .await
                 .map_err(GoModuleError::InterruptPin)?;
-            self.spi
-                .transaction(&mut transactions)
-                .await
-                .map_err(GoModuleError::SPI)?;
-            if module_checksum(&rx) == rx[rx.len() - 1] && rx[1] == rx.len() as u8 - 1 {
-                Ok(())
-            } else {
-                Err(GoModuleError::CommunicationError(
+            self.delay.delay_us(delay_us).await;
+            let result = match self.spi.transaction(&mut transactions).await {
+                Err(err) => Err(GoModuleError::SPI(err)),
+                Ok(()) if module_checksum(rx, len) == rx[len - 1] && rx[1] as usize == len => {
+                    Ok(())
+                }
+                Ok(()) if is_bootmessage(rx) => Err(GoModuleError::CommunicationError(
+                    CommunicationError::InBootloader,
+                )),
+                Ok(()) => Err(GoModuleError::CommunicationError(
                     CommunicationError::ChecksumIncorrect,
-                ))
-            }
+                )),
+            };
+            self.status.record(&result);
+            result
         }
 
+        #[allow(clippy::type_complexity)]
         pub fn get_module_interrupt_state(
             &mut self,
         ) -> Result<PinState, GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>>
@@ -436,6 +532,20 @@ pub mod go_module_async {
             }
         }
 
+        ///Communication statistics since the last module reset
+        pub fn status(&self) -> ModuleStatus {
+            self.status
+        }
+
+        ///Build the error for a response that passed the checksum but is not what was expected,
+        ///the exchange is counted as failed
+        pub fn invalid_response(
+            &mut self,
+        ) -> GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error> {
+            self.status.record_failure();
+            GoModuleError::CommunicationError(CommunicationError::UnableToSerDe)
+        }
+
         pub fn degrade(self) -> GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay> {
             GoModuleUnknown {
                 spi: self.spi,
@@ -448,11 +558,52 @@ pub mod go_module_async {
     }
 }
 
+///A module in the bootloader answers every frame with its boot message
+fn is_bootmessage(rx: &[u8]) -> bool {
+    rx.len() >= BOOTMESSAGELENGTH
+        && rx[1] as usize == BOOTMESSAGELENGTH
+        && module_checksum(rx, BOOTMESSAGELENGTH) == rx[BOOTMESSAGELENGTH - 1]
+}
+
 pub fn module_checksum(data: &[u8], len: usize) -> u8 {
     debug_assert!(len <= data.len());
-    let mut checksum: u8 = 0;
-    for i in 0..(len - 1) {
-        checksum = checksum.wrapping_add(data[i]);
+    data[..len - 1]
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_bootmessage, module_checksum, BOOTMESSAGELENGTH};
+
+    fn bootmessage() -> [u8; BOOTMESSAGELENGTH + 5] {
+        let mut rx = [0u8; BOOTMESSAGELENGTH + 5];
+        rx[1] = BOOTMESSAGELENGTH as u8;
+        rx[6..9].copy_from_slice(&[20, 20, 2]);
+        rx[BOOTMESSAGELENGTH - 1] = module_checksum(&rx, BOOTMESSAGELENGTH);
+        rx
+    }
+
+    #[test]
+    fn recognises_a_bootmessage() {
+        assert!(is_bootmessage(&bootmessage()));
+        assert!(is_bootmessage(&bootmessage()[..BOOTMESSAGELENGTH]));
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        //too short to hold one
+        assert!(!is_bootmessage(&bootmessage()[..BOOTMESSAGELENGTH - 1]));
+        //a different length in the header
+        let mut rx = bootmessage();
+        rx[1] = 44;
+        rx[BOOTMESSAGELENGTH - 1] = module_checksum(&rx, BOOTMESSAGELENGTH);
+        assert!(!is_bootmessage(&rx));
+        //a broken checksum
+        let mut rx = bootmessage();
+        rx[7] ^= 0xff;
+        assert!(!is_bootmessage(&rx));
+        //nothing on the bus
+        assert!(!is_bootmessage(&[0u8; BOOTMESSAGELENGTH]));
     }
-    checksum
 }
@@ -1,8 +1,12 @@
 #![no_std]
 mod go_module_internal;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod module_driver;
 #[cfg(not(feature = "async"))]
 pub use go_module_internal::go_module;
 pub use go_module_internal::*;
+pub use module_driver::*;
 
 #[cfg(feature = "async")]
 pub use go_module_internal::go_module_async as go_module;
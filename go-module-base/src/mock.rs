@@ -0,0 +1,280 @@
+//!Scripted stand-ins for the hardware of a module, to exercise drivers without a controller
+extern crate std;
+
+use std::{
+    collections::VecDeque,
+    string::String,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    vec::Vec,
+};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{self, InputPin, OutputPin},
+    spi::{self, Operation, SpiDevice},
+};
+
+use crate::{
+    module_checksum, GoModule, GoModuleUnknown, ModuleCommunicationDirection,
+    ModuleCommunicationType, BOOTMESSAGELENGTH,
+};
+
+///What the module answers to the next transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    ///Clock in these bytes, the rest of the transfer reads zero
+    Frame(Vec<u8>),
+    ///Nothing is driving the bus, the whole transfer reads zero
+    Silence,
+    ///The bus itself fails
+    Fail,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    answers: VecDeque<Answer>,
+    written: Vec<Vec<u8>>,
+}
+
+///A SPI device that answers transfers from a script and records everything written to it,
+///clones share the same script so a test can keep a handle after handing the device to a driver
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedSpi {
+    script: Arc<Mutex<Script>>,
+}
+
+impl ScriptedSpi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Queue the answer to the next transfer, transfers with nothing queued read [Answer::Silence]
+    pub fn answer(&self, answer: Answer) -> &Self {
+        self.script.lock().unwrap().answers.push_back(answer);
+        self
+    }
+
+    ///Queue a frame as the answer to the next transfer
+    pub fn reply(&self, frame: Vec<u8>) -> &Self {
+        self.answer(Answer::Frame(frame))
+    }
+
+    ///Every write and the transmitted half of every transfer so far, in order
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.script.lock().unwrap().written.clone()
+    }
+
+    ///Answers that were queued but not used yet
+    pub fn pending(&self) -> usize {
+        self.script.lock().unwrap().answers.len()
+    }
+}
+
+impl spi::ErrorType for ScriptedSpi {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice for ScriptedSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut script = self.script.lock().unwrap();
+        for operation in operations {
+            match operation {
+                Operation::Write(tx) => script.written.push(tx.to_vec()),
+                Operation::Transfer(rx, tx) => {
+                    script.written.push(tx.to_vec());
+                    rx.fill(0);
+                    match script.answers.pop_front().unwrap_or(Answer::Silence) {
+                        Answer::Frame(frame) => {
+                            let len = frame.len().min(rx.len());
+                            rx[..len].copy_from_slice(&frame[..len]);
+                        }
+                        Answer::Silence => {}
+                        Answer::Fail => return Err(spi::ErrorKind::Other),
+                    }
+                }
+                Operation::TransferInPlace(buffer) => {
+                    script.written.push(buffer.to_vec());
+                    buffer.fill(0);
+                }
+                Operation::Read(rx) => rx.fill(0),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+///A pin that reads low and can be made to fail, clones share the same state
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedPin {
+    failing: Arc<AtomicBool>,
+    pulses: Arc<AtomicUsize>,
+}
+
+impl ScriptedPin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Make every following access fail, or succeed again
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    ///How often the pin was driven low, for a reset pin the number of resets
+    pub fn pulses(&self) -> usize {
+        self.pulses.load(Ordering::SeqCst)
+    }
+
+    fn check(&self) -> Result<(), digital::ErrorKind> {
+        if self.failing.load(Ordering::SeqCst) {
+            Err(digital::ErrorKind::Other)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl digital::ErrorType for ScriptedPin {
+    type Error = digital::ErrorKind;
+}
+
+impl OutputPin for ScriptedPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.check()?;
+        self.pulses.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+}
+
+impl InputPin for ScriptedPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.check().map(|()| false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.check().map(|()| true)
+    }
+}
+
+///Waiting returns right away, a scripted module is always ready for the next exchange
+#[cfg(feature = "async")]
+impl embedded_hal_async::digital::Wait for ScriptedPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+}
+
+///A delay that returns immediately
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+pub type ScriptedModule = GoModuleUnknown<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>;
+
+impl ScriptedModule {
+    ///A module in `slot` on scripted hardware, returns handles to the SPI script and the reset pin
+    pub fn scripted(slot: u8) -> (Self, ScriptedSpi, ScriptedPin) {
+        let spi = ScriptedSpi::new();
+        let reset = ScriptedPin::new();
+        let module = GoModuleUnknown::new(
+            spi.clone(),
+            reset.clone(),
+            ScriptedPin::new(),
+            NoDelay,
+            slot,
+        );
+        (module, spi, reset)
+    }
+
+    ///A module in `slot` that was reset and answers the bootloader escape with the boot message
+    ///of a module with `id`, ready to be handed to the builder of a driver
+    #[cfg(not(feature = "async"))]
+    pub fn identifying_as(slot: u8, id: [u8; 3]) -> (ScriptedGoModule, ScriptedSpi) {
+        let (module, spi, _) = Self::scripted(slot);
+        spi.reply(bootmessage(id, 1, [1, 0, 0]));
+        let Ok(module) = module.module_reset() else {
+            unreachable!("a scripted reset pin only fails when asked to");
+        };
+        (module, spi)
+    }
+}
+
+pub type ScriptedGoModule = GoModule<ScriptedSpi, ScriptedPin, ScriptedPin, NoDelay>;
+
+///The boot message a module with this identification sends while in its bootloader
+pub fn bootmessage(id: [u8; 3], hardware_version: u8, firmware_version: [u8; 3]) -> Vec<u8> {
+    let mut frame = std::vec![0u8; BOOTMESSAGELENGTH];
+    frame[1] = BOOTMESSAGELENGTH as u8;
+    frame[6..9].copy_from_slice(&id);
+    frame[9] = hardware_version;
+    frame[10..13].copy_from_slice(&firmware_version);
+    frame[BOOTMESSAGELENGTH - 1] = module_checksum(&frame, BOOTMESSAGELENGTH);
+    frame
+}
+
+///A valid frame of `len` bytes from the module, `payload` starts right after the header
+pub fn module_frame(
+    module_id: u8,
+    message_type: ModuleCommunicationType,
+    message_index: u8,
+    payload: &[u8],
+    len: usize,
+) -> Vec<u8> {
+    let mut frame = std::vec![0u8; len];
+    frame[1] = len as u8;
+    frame[2] = ModuleCommunicationDirection::FromModule as u8;
+    frame[3] = module_id;
+    frame[4] = message_type as u8;
+    frame[5] = message_index;
+    frame[6..6 + payload.len()].copy_from_slice(payload);
+    frame[len - 1] = module_checksum(&frame, len);
+    frame
+}
+
+///The first code block in `language` in the doc comments of `source`, empty when there is none,
+///to check the examples in the documentation against the code that reads them
+pub fn doc_example(source: &str, language: &str) -> String {
+    let fence = std::format!("```{language}");
+    let mut lines = source
+        .lines()
+        .map(|line| line.trim_start())
+        .filter_map(|line| {
+            line.strip_prefix("///")
+                .or_else(|| line.strip_prefix("//!"))
+        });
+    let mut example = String::new();
+    if lines.any(|line| line.trim() == fence) {
+        for line in lines.take_while(|line| line.trim() != "```") {
+            example.push_str(line);
+            example.push('\n');
+        }
+    }
+    example
+}
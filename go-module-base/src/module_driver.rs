@@ -0,0 +1,37 @@
+use crate::{ModuleIdentity, ModuleStatus};
+
+///Common interface of the module drivers, so supervisory code can manage modules without
+///knowing which kind of module sits in a slot
+pub trait ModuleDriver {
+    ///The configuration that is sent to the module when the driver is built
+    type Configuration;
+    ///What is sent to the module every cycle
+    type Setpoint;
+    ///What the module answers every cycle
+    type Values;
+    ///The module that is handed back when the driver is taken apart
+    type Module;
+    type Error;
+
+    ///Identity of the module as reported in its boot message
+    fn identity(&self) -> ModuleIdentity;
+
+    ///Take the driver apart, dropping its configuration
+    fn degrade(self) -> Self::Module
+    where
+        Self: Sized;
+
+    ///Take the driver apart, keeping its configuration so it can be changed and built again
+    fn reconfigure(self) -> (Self::Module, Self::Configuration)
+    where
+        Self: Sized;
+
+    ///Perform one cycle, sending the setpoint and returning what the module answered
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error>;
+
+    ///Communication statistics of the module
+    fn status(&self) -> ModuleStatus;
+
+    ///Bring the module in a state where it does not drive any of its outputs
+    fn safe_state(&mut self) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,509 @@
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+use go_module_base::{
+    CommunicationError, ConfigurationError, GoModule, GoModuleError, GoModuleUnknown, ModuleDriver,
+    ModuleIdentity, ModuleStatus,
+};
+
+use crate::{
+    bridge_2_channel::{
+        BridgeModule2Channel, BridgeModule2ChannelBuilder, BridgeModule2ChannelConfiguration,
+        BridgeModule2ChannelSetpoint, BridgeModule2ChannelValues, BRIDGEMODULE2CHANNELID,
+    },
+    input_6_channel::{
+        InputModule6Channel, InputModule6ChannelBuilder, InputModule6ChannelConfiguration,
+        InputModule6ChannelValues, INPUTMODULE6CHANNELID,
+    },
+    output_10_channel::{
+        OutputModule10Channel, OutputModule10ChannelBuilder, OutputModule10ChannelConfiguration,
+        OutputModule10ChannelSetpoint, OutputModule10ChannelValues, OUTPUTMODULE10CHANNELID,
+    },
+    output_6_channel::{
+        OutputModule6Channel, OutputModule6ChannelBuilder, OutputModule6ChannelConfiguration,
+        OutputModule6ChannelSetpoint, OutputModule6ChannelValues, OUTPUTMODULE6CHANNELID,
+    },
+};
+
+///The kinds of modules there is a driver for in this crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModuleKind {
+    Input6Channel,
+    Output6Channel,
+    ///Experimental, see [output_10_channel](crate::output_10_channel)
+    Output10Channel,
+    ///Experimental, see [bridge_2_channel](crate::bridge_2_channel)
+    Bridge2Channel,
+}
+
+impl ModuleKind {
+    pub const ALL: [ModuleKind; 4] = [
+        ModuleKind::Input6Channel,
+        ModuleKind::Output6Channel,
+        ModuleKind::Output10Channel,
+        ModuleKind::Bridge2Channel,
+    ];
+
+    ///The id a module of this kind reports in its boot message
+    pub fn id(&self) -> [u8; 3] {
+        match self {
+            ModuleKind::Input6Channel => INPUTMODULE6CHANNELID,
+            ModuleKind::Output6Channel => OUTPUTMODULE6CHANNELID,
+            ModuleKind::Output10Channel => OUTPUTMODULE10CHANNELID,
+            ModuleKind::Bridge2Channel => BRIDGEMODULE2CHANNELID,
+        }
+    }
+
+    ///Number of channels on a module of this kind, channels are numbered from 1
+    pub fn channels(&self) -> u8 {
+        match self {
+            ModuleKind::Input6Channel => 6,
+            ModuleKind::Output6Channel => 6,
+            ModuleKind::Output10Channel => 10,
+            ModuleKind::Bridge2Channel => 2,
+        }
+    }
+
+    ///The kind of module that identified itself, None when there is no driver for it
+    pub fn from_identity(identity: &ModuleIdentity) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.id() == identity.id)
+    }
+}
+
+///Any of the module drivers in this crate, so modules of different kinds can be kept in one list
+pub enum AnyModule<SPI, ResetPin, InterruptPin, Delay> {
+    Input6Channel(InputModule6Channel<SPI, ResetPin, InterruptPin, Delay>),
+    Output6Channel(OutputModule6Channel<SPI, ResetPin, InterruptPin, Delay>),
+    Output10Channel(OutputModule10Channel<SPI, ResetPin, InterruptPin, Delay>),
+    Bridge2Channel(BridgeModule2Channel<SPI, ResetPin, InterruptPin, Delay>),
+}
+
+///Configuration of any module, stored as a table named after the kind of module that holds
+///the configuration of that module:
+///```toml
+///[Bridge2Channel]
+///channels = [
+///    { func = "DutyCoast", frequency = "Hz1_000", max_current = 2000 },
+///    { func = "Disabled", frequency = "Hz1_000", max_current = 0 },
+///]
+///```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnyModuleConfiguration {
+    Input6Channel(InputModule6ChannelConfiguration),
+    Output6Channel(OutputModule6ChannelConfiguration),
+    Output10Channel(OutputModule10ChannelConfiguration),
+    Bridge2Channel(BridgeModule2ChannelConfiguration),
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> AnyModule<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    ///Build the driver the configuration is for, on failure the module and configuration are
+    ///returned just like the builders of the separate drivers do
+    #[allow(clippy::type_complexity)]
+    pub fn build(
+        module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+        configuration: AnyModuleConfiguration,
+    ) -> Result<
+        Self,
+        (
+            GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+            AnyModuleConfiguration,
+            ConfigurationError,
+        ),
+    > {
+        match configuration {
+            AnyModuleConfiguration::Input6Channel(configuration) => {
+                InputModule6ChannelBuilder::from_configuration(module, configuration)
+                    .build()
+                    .map(AnyModule::Input6Channel)
+                    .map_err(|(module, configuration, err)| {
+                        (
+                            module,
+                            AnyModuleConfiguration::Input6Channel(configuration),
+                            err,
+                        )
+                    })
+            }
+            AnyModuleConfiguration::Output6Channel(configuration) => {
+                OutputModule6ChannelBuilder::from_configuration(module, configuration)
+                    .build()
+                    .map(AnyModule::Output6Channel)
+                    .map_err(|(module, configuration, err)| {
+                        (
+                            module,
+                            AnyModuleConfiguration::Output6Channel(configuration),
+                            err,
+                        )
+                    })
+            }
+            AnyModuleConfiguration::Output10Channel(configuration) => {
+                OutputModule10ChannelBuilder::from_configuration(module, configuration)
+                    .build()
+                    .map(AnyModule::Output10Channel)
+                    .map_err(|(module, configuration, err)| {
+                        (
+                            module,
+                            AnyModuleConfiguration::Output10Channel(configuration),
+                            err,
+                        )
+                    })
+            }
+            AnyModuleConfiguration::Bridge2Channel(configuration) => {
+                BridgeModule2ChannelBuilder::from_configuration(module, configuration)
+                    .build()
+                    .map(AnyModule::Bridge2Channel)
+                    .map_err(|(module, configuration, err)| {
+                        (
+                            module,
+                            AnyModuleConfiguration::Bridge2Channel(configuration),
+                            err,
+                        )
+                    })
+            }
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> AnyModule<SPI, ResetPin, InterruptPin, Delay> {
+    pub fn kind(&self) -> ModuleKind {
+        match self {
+            AnyModule::Input6Channel(_) => ModuleKind::Input6Channel,
+            AnyModule::Output6Channel(_) => ModuleKind::Output6Channel,
+            AnyModule::Output10Channel(_) => ModuleKind::Output10Channel,
+            AnyModule::Bridge2Channel(_) => ModuleKind::Bridge2Channel,
+        }
+    }
+}
+
+impl AnyModuleConfiguration {
+    ///The default configuration of the driver for a kind of module
+    pub fn default_for(kind: ModuleKind) -> Self {
+        match kind {
+            ModuleKind::Input6Channel => AnyModuleConfiguration::Input6Channel(Default::default()),
+            ModuleKind::Output6Channel => {
+                AnyModuleConfiguration::Output6Channel(Default::default())
+            }
+            ModuleKind::Output10Channel => {
+                AnyModuleConfiguration::Output10Channel(Default::default())
+            }
+            ModuleKind::Bridge2Channel => {
+                AnyModuleConfiguration::Bridge2Channel(Default::default())
+            }
+        }
+    }
+
+    ///The kind of module this configuration is for
+    pub fn kind(&self) -> ModuleKind {
+        match self {
+            AnyModuleConfiguration::Input6Channel(_) => ModuleKind::Input6Channel,
+            AnyModuleConfiguration::Output6Channel(_) => ModuleKind::Output6Channel,
+            AnyModuleConfiguration::Output10Channel(_) => ModuleKind::Output10Channel,
+            AnyModuleConfiguration::Bridge2Channel(_) => ModuleKind::Bridge2Channel,
+        }
+    }
+}
+
+///Setpoint for an [AnyModule], the variant has to match the kind of module
+#[derive(Clone, Copy)]
+pub enum AnyModuleSetpoint {
+    Input6Channel,
+    Output6Channel(OutputModule6ChannelSetpoint),
+    Output10Channel(OutputModule10ChannelSetpoint),
+    Bridge2Channel(BridgeModule2ChannelSetpoint),
+}
+
+#[derive(Clone, Copy)]
+pub enum AnyModuleValues {
+    Input6Channel(InputModule6ChannelValues),
+    Output6Channel(OutputModule6ChannelValues),
+    Output10Channel(OutputModule10ChannelValues),
+    Bridge2Channel(BridgeModule2ChannelValues),
+}
+
+impl AnyModuleSetpoint {
+    ///Setpoint with every output of a module of this kind switched off
+    pub fn off(kind: ModuleKind) -> Self {
+        match kind {
+            ModuleKind::Input6Channel => AnyModuleSetpoint::Input6Channel,
+            ModuleKind::Output6Channel => AnyModuleSetpoint::Output6Channel(Default::default()),
+            ModuleKind::Output10Channel => AnyModuleSetpoint::Output10Channel(Default::default()),
+            ModuleKind::Bridge2Channel => AnyModuleSetpoint::Bridge2Channel(Default::default()),
+        }
+    }
+
+    ///Setpoint of a channel, numbered from 1, None for input modules and channels the module does not have
+    pub fn channel(&self, channel: u8) -> Option<i32> {
+        let index = (channel as usize).checked_sub(1)?;
+        match self {
+            AnyModuleSetpoint::Input6Channel => None,
+            AnyModuleSetpoint::Output6Channel(setpoint) => [
+                setpoint.channel1,
+                setpoint.channel2,
+                setpoint.channel3,
+                setpoint.channel4,
+                setpoint.channel5,
+                setpoint.channel6,
+            ]
+            .get(index)
+            .map(|&value| value as i32),
+            AnyModuleSetpoint::Output10Channel(setpoint) => [
+                setpoint.channel1,
+                setpoint.channel2,
+                setpoint.channel3,
+                setpoint.channel4,
+                setpoint.channel5,
+                setpoint.channel6,
+                setpoint.channel7,
+                setpoint.channel8,
+                setpoint.channel9,
+                setpoint.channel10,
+            ]
+            .get(index)
+            .map(|&value| value as i32),
+            AnyModuleSetpoint::Bridge2Channel(setpoint) => [setpoint.channel1, setpoint.channel2]
+                .get(index)
+                .map(|&value| value as i32),
+        }
+    }
+
+    ///Change the setpoint of a channel, numbered from 1. Returns false when the module has no such
+    ///output or the value does not fit the setpoint, which is a u16 for outputs and an i16 for bridges
+    pub fn set_channel(&mut self, channel: u8, value: i32) -> bool {
+        let Some(index) = (channel as usize).checked_sub(1) else {
+            return false;
+        };
+        match self {
+            AnyModuleSetpoint::Input6Channel => false,
+            AnyModuleSetpoint::Output6Channel(setpoint) => set(
+                [
+                    &mut setpoint.channel1,
+                    &mut setpoint.channel2,
+                    &mut setpoint.channel3,
+                    &mut setpoint.channel4,
+                    &mut setpoint.channel5,
+                    &mut setpoint.channel6,
+                ],
+                index,
+                value,
+            ),
+            AnyModuleSetpoint::Output10Channel(setpoint) => set(
+                [
+                    &mut setpoint.channel1,
+                    &mut setpoint.channel2,
+                    &mut setpoint.channel3,
+                    &mut setpoint.channel4,
+                    &mut setpoint.channel5,
+                    &mut setpoint.channel6,
+                    &mut setpoint.channel7,
+                    &mut setpoint.channel8,
+                    &mut setpoint.channel9,
+                    &mut setpoint.channel10,
+                ],
+                index,
+                value,
+            ),
+            AnyModuleSetpoint::Bridge2Channel(setpoint) => set(
+                [&mut setpoint.channel1, &mut setpoint.channel2],
+                index,
+                value,
+            ),
+        }
+    }
+}
+
+fn set<T: TryFrom<i32>, const N: usize>(channels: [&mut T; N], index: usize, value: i32) -> bool {
+    match (channels.into_iter().nth(index), T::try_from(value)) {
+        (Some(channel), Ok(value)) => {
+            *channel = value;
+            true
+        }
+        _ => false,
+    }
+}
+
+///What a module reported for one of its channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelValue {
+    ///Reading of an input, its meaning depends on the function the channel is configured for
+    Input(u32),
+    ///Measured current and duty cycle of an output
+    Output { current: i16, duty: u16 },
+    ///Measured current and duty cycle of a bridge, a negative duty cycle is reversed polarity
+    Bridge { current: i16, duty: i16 },
+}
+
+impl AnyModuleValues {
+    ///Error code an output or bridge module reported, None for input modules
+    pub fn error_code(&self) -> Option<u32> {
+        match self {
+            AnyModuleValues::Input6Channel(_) => None,
+            AnyModuleValues::Output6Channel(values) => Some(values.error_code),
+            AnyModuleValues::Output10Channel(values) => Some(values.error_code),
+            AnyModuleValues::Bridge2Channel(values) => Some(values.error_code),
+        }
+    }
+
+    ///Value of a channel, numbered from 1, None when the module does not have the channel
+    pub fn channel(&self, channel: u8) -> Option<ChannelValue> {
+        let index = (channel as usize).checked_sub(1)?;
+        match self {
+            AnyModuleValues::Input6Channel(values) => [
+                values.channel1,
+                values.channel2,
+                values.channel3,
+                values.channel4,
+                values.channel5,
+                values.channel6,
+            ]
+            .get(index)
+            .map(|&value| ChannelValue::Input(value)),
+            AnyModuleValues::Output6Channel(values) => [
+                (values.channel1_cur, values.channel1_duty),
+                (values.channel2_cur, values.channel2_duty),
+                (values.channel3_cur, values.channel3_duty),
+                (values.channel4_cur, values.channel4_duty),
+                (values.channel5_cur, values.channel5_duty),
+                (values.channel6_cur, values.channel6_duty),
+            ]
+            .get(index)
+            .map(|&(current, duty)| ChannelValue::Output { current, duty }),
+            AnyModuleValues::Output10Channel(values) => [
+                (values.channel1_cur, values.channel1_duty),
+                (values.channel2_cur, values.channel2_duty),
+                (values.channel3_cur, values.channel3_duty),
+                (values.channel4_cur, values.channel4_duty),
+                (values.channel5_cur, values.channel5_duty),
+                (values.channel6_cur, values.channel6_duty),
+                (values.channel7_cur, values.channel7_duty),
+                (values.channel8_cur, values.channel8_duty),
+                (values.channel9_cur, values.channel9_duty),
+                (values.channel10_cur, values.channel10_duty),
+            ]
+            .get(index)
+            .map(|&(current, duty)| ChannelValue::Output { current, duty }),
+            AnyModuleValues::Bridge2Channel(values) => [
+                (values.channel1_cur, values.channel1_duty),
+                (values.channel2_cur, values.channel2_duty),
+            ]
+            .get(index)
+            .map(|&(current, duty)| ChannelValue::Bridge { current, duty }),
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for AnyModule<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = AnyModuleConfiguration;
+    type Setpoint = AnyModuleSetpoint;
+    type Values = AnyModuleValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    fn identity(&self) -> ModuleIdentity {
+        match self {
+            AnyModule::Input6Channel(module) => module.identity(),
+            AnyModule::Output6Channel(module) => module.identity(),
+            AnyModule::Output10Channel(module) => module.identity(),
+            AnyModule::Bridge2Channel(module) => module.identity(),
+        }
+    }
+
+    fn degrade(self) -> Self::Module {
+        match self {
+            AnyModule::Input6Channel(module) => ModuleDriver::degrade(module),
+            AnyModule::Output6Channel(module) => ModuleDriver::degrade(module),
+            AnyModule::Output10Channel(module) => ModuleDriver::degrade(module),
+            AnyModule::Bridge2Channel(module) => ModuleDriver::degrade(module),
+        }
+    }
+
+    fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+        match self {
+            AnyModule::Input6Channel(module) => {
+                let (module, configuration) = module.reconfigure();
+                (module, AnyModuleConfiguration::Input6Channel(configuration))
+            }
+            AnyModule::Output6Channel(module) => {
+                let (module, configuration) = module.reconfigure();
+                (
+                    module,
+                    AnyModuleConfiguration::Output6Channel(configuration),
+                )
+            }
+            AnyModule::Output10Channel(module) => {
+                let (module, configuration) = module.reconfigure();
+                (
+                    module,
+                    AnyModuleConfiguration::Output10Channel(configuration),
+                )
+            }
+            AnyModule::Bridge2Channel(module) => {
+                let (module, configuration) = module.reconfigure();
+                (
+                    module,
+                    AnyModuleConfiguration::Bridge2Channel(configuration),
+                )
+            }
+        }
+    }
+
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        match (self, setpoint) {
+            (AnyModule::Input6Channel(module), AnyModuleSetpoint::Input6Channel) => {
+                module.exchange(&()).map(AnyModuleValues::Input6Channel)
+            }
+            (AnyModule::Output6Channel(module), AnyModuleSetpoint::Output6Channel(setpoint)) => {
+                module
+                    .exchange(setpoint)
+                    .map(AnyModuleValues::Output6Channel)
+            }
+            (AnyModule::Output10Channel(module), AnyModuleSetpoint::Output10Channel(setpoint)) => {
+                module
+                    .exchange(setpoint)
+                    .map(AnyModuleValues::Output10Channel)
+            }
+            (AnyModule::Bridge2Channel(module), AnyModuleSetpoint::Bridge2Channel(setpoint)) => {
+                module
+                    .exchange(setpoint)
+                    .map(AnyModuleValues::Bridge2Channel)
+            }
+            //the setpoint can't be serialized for this kind of module
+            _ => Err(GoModuleError::CommunicationError(
+                CommunicationError::UnableToSerDe,
+            )),
+        }
+    }
+
+    fn status(&self) -> ModuleStatus {
+        match self {
+            AnyModule::Input6Channel(module) => module.status(),
+            AnyModule::Output6Channel(module) => module.status(),
+            AnyModule::Output10Channel(module) => module.status(),
+            AnyModule::Bridge2Channel(module) => module.status(),
+        }
+    }
+
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        match self {
+            AnyModule::Input6Channel(module) => module.safe_state(),
+            AnyModule::Output6Channel(module) => module.safe_state(),
+            AnyModule::Output10Channel(module) => module.safe_state(),
+            AnyModule::Bridge2Channel(module) => module.safe_state(),
+        }
+    }
+}
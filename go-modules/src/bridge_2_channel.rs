@@ -0,0 +1,421 @@
+//!Driver for the 2 channel bridge module
+//!
+//!Experimental: no documentation of this module's protocol was available when the driver was
+//!written. The module id `[20, 30, 3]`, message id 33, the message length of 44 bytes, the codes
+//!of the bridge functions and the layout of the configuration, data and feedback frames are not
+//!verified against the module firmware. Check the driver against a module on the bench before it
+//!drives a motor or valve.
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+use go_module_base::{
+    ConfigurationError, GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection,
+    ModuleCommunicationType, ModuleDriver, ModuleIdentity, ModuleStatus,
+};
+
+use crate::output_6_channel::OutputModule6ChannelFrequency;
+
+const BRIDGEMODULE2CHANNELMESSAGELENGTH: usize = 44;
+pub(crate) const BRIDGEMODULE2CHANNELID: [u8; 3] = [20, 30, 3];
+
+#[repr(u8)]
+#[derive(Clone, Copy, Default)]
+///Selects how a bridge drives its load
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BridgeModule2ChannelFunc {
+    #[default]
+    Disabled = 1,
+    ///Signed duty cycle, the load is shorted through the low sides during the off time
+    DutyBrake,
+    ///Signed duty cycle, all switches are opened during the off time
+    DutyCoast,
+}
+
+#[repr(usize)]
+pub enum BridgeModule2ChannelNum {
+    One = 1,
+    Two,
+}
+
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BridgeModule2ChannelChannel {
+    func: BridgeModule2ChannelFunc,
+    frequency: OutputModule6ChannelFrequency,
+    max_current: u16,
+}
+
+///Duty cycle setpoints of both bridges, negative values reverse the polarity of the output
+#[derive(Default, Clone, Copy)]
+pub struct BridgeModule2ChannelSetpoint {
+    pub channel1: i16,
+    pub channel2: i16,
+}
+
+#[derive(Clone, Copy)]
+pub struct BridgeModule2ChannelValues {
+    pub temperature: i16,
+    pub supply_volt: u16,
+    pub error_code: u32,
+    pub channel1_cur: i16,
+    pub channel1_duty: i16,
+    pub channel2_cur: i16,
+    pub channel2_duty: i16,
+}
+
+///Configuration of a bridge module
+///
+///With the `serde` feature this can be stored in a file, `channels` has to list both bridges.
+///`max_current` is in mA. In TOML:
+///```toml
+///[[channels]]
+///func = "DutyBrake"
+///frequency = "Hz10_000"
+///max_current = 4000
+///
+///[[channels]]
+///func = "Disabled"
+///frequency = "Hz1_000"
+///max_current = 0
+///```
+///Or in JSON:
+///```json
+///{
+///  "channels": [
+///    { "func": "DutyBrake", "frequency": "Hz10_000", "max_current": 4000 },
+///    { "func": "Disabled", "frequency": "Hz1_000", "max_current": 0 }
+///  ]
+///}
+///```
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BridgeModule2ChannelConfiguration {
+    channels: [BridgeModule2ChannelChannel; 2],
+}
+
+pub struct BridgeModule2Channel<SPI, ResetPin, InterruptPin, Delay> {
+    module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+    configuration: BridgeModule2ChannelConfiguration,
+    identity: ModuleIdentity,
+}
+
+pub struct BridgeModule2ChannelBuilder<SPI, ResetPin, InterruptPin, Delay> {
+    module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+    configuration: BridgeModule2ChannelConfiguration,
+}
+
+impl BridgeModule2ChannelSetpoint {
+    fn serialize(&self, tx: &mut [u8]) {
+        tx[6..8].copy_from_slice(&self.channel1.to_le_bytes());
+        tx[8..10].copy_from_slice(&self.channel2.to_le_bytes());
+    }
+}
+
+impl BridgeModule2ChannelChannel {
+    pub fn func(&self) -> BridgeModule2ChannelFunc {
+        self.func
+    }
+
+    pub fn frequency(&self) -> OutputModule6ChannelFrequency {
+        self.frequency
+    }
+
+    pub fn max_current(&self) -> u16 {
+        self.max_current
+    }
+}
+
+impl BridgeModule2ChannelConfiguration {
+    pub fn channel(&self, channel: BridgeModule2ChannelNum) -> &BridgeModule2ChannelChannel {
+        &self.channels[channel as usize - 1]
+    }
+
+    pub fn channels(&self) -> &[BridgeModule2ChannelChannel; 2] {
+        &self.channels
+    }
+
+    fn serialize(&self, tx: &mut [u8]) {
+        for (i, channel) in self.channels.iter().enumerate() {
+            tx[6 + i] = (channel.func as u8) << 4 | channel.frequency as u8;
+            tx[8 + i * 2..10 + i * 2].copy_from_slice(&channel.max_current.to_le_bytes());
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> BridgeModule2Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    pub fn reconfigure(
+        self,
+    ) -> (
+        GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+        BridgeModule2ChannelConfiguration,
+    ) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn set_and_read_channels(
+        &mut self,
+        setpoint: &BridgeModule2ChannelSetpoint,
+    ) -> Result<
+        BridgeModule2ChannelValues,
+        GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>,
+    > {
+        let mut tx = [0u8; BRIDGEMODULE2CHANNELMESSAGELENGTH + 5];
+        let mut rx = [0u8; BRIDGEMODULE2CHANNELMESSAGELENGTH + 5];
+        setpoint.serialize(&mut tx);
+        self.module.send_receive_spi(
+            ModuleCommunicationDirection::ToModule,
+            33,
+            ModuleCommunicationType::Data,
+            1,
+            &mut tx,
+            &mut rx,
+            BRIDGEMODULE2CHANNELMESSAGELENGTH,
+            0,
+        )?;
+        if rx[2] != ModuleCommunicationDirection::FromModule as u8
+            || rx[3] != 33
+            || rx[4] != ModuleCommunicationType::Feedback as u8
+            || rx[5] != 1
+        {
+            return Err(self.module.invalid_response());
+        }
+        Ok(BridgeModule2ChannelValues {
+            temperature: i16::from_le_bytes(rx[6..8].try_into().unwrap()),
+            supply_volt: u16::from_le_bytes(rx[8..10].try_into().unwrap()),
+            channel1_cur: i16::from_le_bytes(rx[10..12].try_into().unwrap()),
+            channel2_cur: i16::from_le_bytes(rx[12..14].try_into().unwrap()),
+            channel1_duty: i16::from_le_bytes(rx[14..16].try_into().unwrap()),
+            channel2_duty: i16::from_le_bytes(rx[16..18].try_into().unwrap()),
+            error_code: u32::from_le_bytes(rx[18..22].try_into().unwrap()),
+        })
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay>
+    BridgeModule2ChannelBuilder<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    pub fn new(module: GoModule<SPI, ResetPin, InterruptPin, Delay>) -> Self {
+        BridgeModule2ChannelBuilder {
+            module,
+            configuration: BridgeModule2ChannelConfiguration::default(),
+        }
+    }
+
+    pub fn from_configuration(
+        module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+        configuration: BridgeModule2ChannelConfiguration,
+    ) -> Self {
+        BridgeModule2ChannelBuilder {
+            module,
+            configuration,
+        }
+    }
+
+    pub fn configure_channel(
+        self,
+        channel: BridgeModule2ChannelNum,
+        func: BridgeModule2ChannelFunc,
+        frequency: OutputModule6ChannelFrequency,
+        max_current: u16,
+    ) -> Self {
+        let mut configuration = self.configuration;
+        configuration.channels[channel as usize - 1] = BridgeModule2ChannelChannel {
+            func,
+            frequency,
+            max_current,
+        };
+        BridgeModule2ChannelBuilder {
+            module: self.module,
+            configuration,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn build(
+        self,
+    ) -> Result<
+        BridgeModule2Channel<SPI, ResetPin, InterruptPin, Delay>,
+        (
+            GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+            BridgeModule2ChannelConfiguration,
+            ConfigurationError,
+        ),
+    > {
+        let mut module = BridgeModule2Channel {
+            module: self.module,
+            configuration: self.configuration,
+            identity: ModuleIdentity::default(),
+        };
+        let Ok(bootmessage) = module.module.escape_module_bootloader() else {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NoBootMessage,
+            ));
+        };
+
+        let identity = ModuleIdentity::from_bootmessage(&bootmessage);
+        if BRIDGEMODULE2CHANNELID != identity.id {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::WrongModule(identity),
+            ));
+        }
+        module.identity = identity;
+
+        let mut tx = [0u8; BRIDGEMODULE2CHANNELMESSAGELENGTH + 5];
+        module.configuration.serialize(&mut tx);
+        if module
+            .module
+            .send_spi(
+                ModuleCommunicationDirection::ToModule,
+                33,
+                ModuleCommunicationType::Configuration,
+                1,
+                &mut tx,
+                BRIDGEMODULE2CHANNELMESSAGELENGTH,
+                500_000,
+            )
+            .is_err()
+        {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
+        }
+        //whether the module runs the configuration shows on the first exchange, which checks the
+        //message id, message type and length the module echoes in its feedback
+        Ok(module)
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for BridgeModule2Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = BridgeModule2ChannelConfiguration;
+    type Setpoint = BridgeModule2ChannelSetpoint;
+    type Values = BridgeModule2ChannelValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    fn identity(&self) -> ModuleIdentity {
+        self.identity
+    }
+
+    fn degrade(self) -> Self::Module {
+        self.module.degrade()
+    }
+
+    fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        self.set_and_read_channels(setpoint)
+    }
+
+    fn status(&self) -> ModuleStatus {
+        self.module.status()
+    }
+
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        self.set_and_read_channels(&BridgeModule2ChannelSetpoint::default())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::{
+        mock::{module_frame, ScriptedModule},
+        CommunicationError, GoModuleError, ModuleCommunicationType,
+    };
+
+    use super::{
+        BridgeModule2ChannelBuilder, BridgeModule2ChannelSetpoint, BRIDGEMODULE2CHANNELID,
+        BRIDGEMODULE2CHANNELMESSAGELENGTH,
+    };
+
+    #[test]
+    fn only_sends_the_configuration_frame() {
+        let (module, spi) = ScriptedModule::identifying_as(2, BRIDGEMODULE2CHANNELID);
+        assert!(BridgeModule2ChannelBuilder::new(module).build().is_ok());
+        //the escape and the configuration frame, no setpoint until the application sends one
+        let written = spi.written();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[1][4], ModuleCommunicationType::Configuration as u8);
+        assert_eq!(written[1][5], 1);
+    }
+
+    #[test]
+    fn checks_the_header_the_module_echoes() {
+        let (module, spi) = ScriptedModule::identifying_as(2, BRIDGEMODULE2CHANNELID);
+        let Ok(mut module) = BridgeModule2ChannelBuilder::new(module).build() else {
+            panic!("the module is configured");
+        };
+        let setpoint = BridgeModule2ChannelSetpoint::default();
+
+        spi.reply(module_frame(
+            33,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            BRIDGEMODULE2CHANNELMESSAGELENGTH,
+        ));
+        assert!(module.set_and_read_channels(&setpoint).is_ok());
+
+        //the feedback of another kind of module
+        spi.reply(module_frame(
+            34,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            BRIDGEMODULE2CHANNELMESSAGELENGTH,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::UnableToSerDe
+            ))
+        ));
+
+        //a frame of the wrong length
+        spi.reply(module_frame(
+            33,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            BRIDGEMODULE2CHANNELMESSAGELENGTH - 1,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::ChecksumIncorrect
+            ))
+        ));
+        assert_eq!(spi.pending(), 0);
+    }
+}
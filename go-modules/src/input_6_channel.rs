@@ -4,16 +4,18 @@ use embedded_hal::{
     spi::SpiDevice,
 };
 use go_module_base::{
-    GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection, ModuleCommunicationType,
+    ConfigurationError, GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection,
+    ModuleCommunicationType, ModuleDriver, ModuleIdentity, ModuleStatus,
 };
 
 const INPUTMODULE6CHANNELMESSAGELENGTH: usize = 55;
-const INPUTMODULE6CHANNELID: [u8; 3] = [20, 10, 1];
+pub(crate) const INPUTMODULE6CHANNELID: [u8; 3] = [20, 10, 1];
 const RESISTORMATRIX: [u8; 4] = [0, 3, 1, 2];
 
 #[repr(u8)]
 #[derive(Clone, Copy)]
 /// Selects the funcion of a given input channel, some functions require two channels
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InputModule6ChannelFunc {
     /// Get the raw adc value, u16 is the number of samples
     AnalogRaw(u16) = 1,
@@ -42,6 +44,7 @@ impl InputModule6ChannelFunc {
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InputModule6ChannelPullUp {
     None,
     PU3_3k,
@@ -50,6 +53,7 @@ pub enum InputModule6ChannelPullUp {
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InputModule6ChannelPullDown {
     None,
     PD3_3k,
@@ -59,6 +63,7 @@ pub enum InputModule6ChannelPullDown {
 
 #[repr(u8)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InputModule6ChannelVoltage {
     Voltage5V,
     Voltage12V,
@@ -75,6 +80,7 @@ pub enum InputModule6ChannelNum {
     Six,
 }
 
+#[derive(Clone, Copy)]
 pub struct InputModule6ChannelValues {
     pub channel1: u32,
     pub channel2: u32,
@@ -86,13 +92,46 @@ pub struct InputModule6ChannelValues {
 
 #[repr(u8)]
 #[derive(Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InputModule6ChannelSupply {
     #[default]
     On = 1,
     Off,
 }
 
+/// Configuration of an input module
+///
+/// With the `serde` feature this can be stored in a file, fields that are left out keep their
+/// default value. `channels` has to list all 6 channels and `supplies` all 3 sensor supplies.
+/// In TOML:
+/// ```toml
+/// supplies = ["On", "On", "Off"]
+/// channels = [
+///     { func = { AnalogmV = 100 }, pu = "None", pd = "PD10k", volt = "Voltage24V" },
+///     { func = "Frequency", pu = "PU4_7k", pd = "None", volt = "Voltage12V" },
+///     { func = "Digital", pu = "PU10k", pd = "None", volt = "Voltage24V" },
+///     { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+///     { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+///     { func = { AnalogmV = 1000 }, pu = "None", pd = "None", volt = "Voltage5V" },
+/// ]
+/// ```
+/// Or in JSON:
+/// ```json
+/// {
+///   "channels": [
+///     { "func": { "AnalogmV": 100 }, "pu": "None", "pd": "PD10k", "volt": "Voltage24V" },
+///     { "func": "Frequency", "pu": "PU4_7k", "pd": "None", "volt": "Voltage12V" },
+///     { "func": "Digital", "pu": "PU10k", "pd": "None", "volt": "Voltage24V" },
+///     { "func": { "AnalogmV": 1000 }, "pu": "None", "pd": "None", "volt": "Voltage5V" },
+///     { "func": { "AnalogmV": 1000 }, "pu": "None", "pd": "None", "volt": "Voltage5V" },
+///     { "func": { "AnalogmV": 1000 }, "pu": "None", "pd": "None", "volt": "Voltage5V" }
+///   ],
+///   "supplies": ["On", "On", "Off"]
+/// }
+/// ```
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct InputModule6ChannelConfiguration {
     channels: [InputModule6ChannelChannel; 6],
     supplies: [InputModule6ChannelSupply; 3],
@@ -101,6 +140,7 @@ pub struct InputModule6ChannelConfiguration {
 pub struct InputModule6Channel<SPI, ResetPin, InterruptPin, Delay> {
     module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
     configuration: InputModule6ChannelConfiguration,
+    identity: ModuleIdentity,
 }
 
 impl<SPI, ResetPin, InterruptPin, Delay> InputModule6Channel<SPI, ResetPin, InterruptPin, Delay>
@@ -119,6 +159,7 @@ where
         (self.module.degrade(), self.configuration)
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn read_channels(
         &mut self,
     ) -> Result<
@@ -142,9 +183,7 @@ where
             || rx[4] != ModuleCommunicationType::Data as u8
             || rx[5] != 1
         {
-            return Err(GoModuleError::CommunicationError(
-                go_module_base::CommunicationError::UnableToSerDe,
-            ));
+            return Err(self.module.invalid_response());
         }
         Ok(InputModule6ChannelValues {
             channel1: u32::from_le_bytes(rx[6..10].try_into().unwrap()), //These can't fail aslong as the slice is correctly sized
@@ -156,6 +195,7 @@ where
         })
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn reset_counter(
         &mut self,
         channel: InputModule6ChannelNum,
@@ -177,6 +217,7 @@ where
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputModule6ChannelChannel {
     func: InputModule6ChannelFunc,
     pu: InputModule6ChannelPullUp,
@@ -195,7 +236,37 @@ impl Default for InputModule6ChannelChannel {
     }
 }
 
+impl InputModule6ChannelChannel {
+    pub fn func(&self) -> InputModule6ChannelFunc {
+        self.func
+    }
+
+    pub fn pull_up(&self) -> InputModule6ChannelPullUp {
+        self.pu
+    }
+
+    pub fn pull_down(&self) -> InputModule6ChannelPullDown {
+        self.pd
+    }
+
+    pub fn voltage(&self) -> InputModule6ChannelVoltage {
+        self.volt
+    }
+}
+
 impl InputModule6ChannelConfiguration {
+    pub fn channel(&self, channel: InputModule6ChannelNum) -> &InputModule6ChannelChannel {
+        &self.channels[channel as usize - 1]
+    }
+
+    pub fn channels(&self) -> &[InputModule6ChannelChannel; 6] {
+        &self.channels
+    }
+
+    pub fn supplies(&self) -> &[InputModule6ChannelSupply; 3] {
+        &self.supplies
+    }
+
     fn serialize(&self, tx: &mut [u8]) {
         for (i, channel) in self.channels.iter().enumerate() {
             let samples = match channel.func {
@@ -276,6 +347,7 @@ where
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn build(
         self,
     ) -> Result<
@@ -283,19 +355,31 @@ where
         (
             GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
             InputModule6ChannelConfiguration,
+            ConfigurationError,
         ),
     > {
         let mut module = InputModule6Channel {
             module: self.module,
             configuration: self.config,
+            identity: ModuleIdentity::default(),
         };
         let Ok(bootmessage) = module.module.escape_module_bootloader() else {
-            return Err((module.module.degrade(), module.configuration));
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NoBootMessage,
+            ));
         };
 
-        if INPUTMODULE6CHANNELID != bootmessage[6..9] {
-            return Err((module.module.degrade(), module.configuration));
+        let identity = ModuleIdentity::from_bootmessage(&bootmessage);
+        if INPUTMODULE6CHANNELID != identity.id {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::WrongModule(identity),
+            ));
         }
+        module.identity = identity;
         let mut tx = [0u8; INPUTMODULE6CHANNELMESSAGELENGTH + 5];
         module.configuration.serialize(&mut tx);
         if module
@@ -311,8 +395,106 @@ where
             )
             .is_err()
         {
-            return Err((module.module.degrade(), module.configuration));
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
+        }
+        //the input module reports no error code, the only check is that it answers with a valid data frame
+        module.module.delay.delay_ms(1);
+        if module.read_channels().is_err() {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NoResponse,
+            ));
         }
         Ok(module)
     }
 }
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for InputModule6Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = InputModule6ChannelConfiguration;
+    type Setpoint = ();
+    type Values = InputModule6ChannelValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    fn identity(&self) -> ModuleIdentity {
+        self.identity
+    }
+
+    fn degrade(self) -> Self::Module {
+        self.module.degrade()
+    }
+
+    fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    fn exchange(&mut self, _setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        self.read_channels()
+    }
+
+    fn status(&self) -> ModuleStatus {
+        self.module.status()
+    }
+
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        //an input module has nothing to switch off
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::{
+        mock::{module_frame, ScriptedModule},
+        ConfigurationError, ModuleCommunicationType,
+    };
+
+    use super::{
+        InputModule6ChannelBuilder, INPUTMODULE6CHANNELID, INPUTMODULE6CHANNELMESSAGELENGTH,
+    };
+
+    #[test]
+    fn only_checks_that_a_data_frame_comes_back() {
+        let (module, spi) = ScriptedModule::identifying_as(3, INPUTMODULE6CHANNELID);
+        spi.reply(module_frame(
+            11,
+            ModuleCommunicationType::Data,
+            1,
+            &[0xff; 48],
+            INPUTMODULE6CHANNELMESSAGELENGTH,
+        ));
+        assert!(InputModule6ChannelBuilder::new(module).build().is_ok());
+        assert_eq!(spi.pending(), 0);
+
+        let (module, spi) = ScriptedModule::identifying_as(3, INPUTMODULE6CHANNELID);
+        spi.reply(module_frame(
+            11,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            INPUTMODULE6CHANNELMESSAGELENGTH,
+        ));
+        assert!(matches!(
+            InputModule6ChannelBuilder::new(module).build(),
+            Err((_, _, ConfigurationError::NoResponse))
+        ));
+
+        let (module, _) = ScriptedModule::identifying_as(3, INPUTMODULE6CHANNELID);
+        assert!(matches!(
+            InputModule6ChannelBuilder::new(module).build(),
+            Err((_, _, ConfigurationError::NoResponse))
+        ));
+    }
+}
@@ -1,3 +1,9 @@
 #![no_std]
+pub mod any_module;
+pub mod bridge_2_channel;
 pub mod input_6_channel;
+pub mod output_10_channel;
 pub mod output_6_channel;
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests;
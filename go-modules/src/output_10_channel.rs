@@ -0,0 +1,482 @@
+//!Driver for the 10 channel output module
+//!
+//!Experimental: no documentation of this module's protocol was available when the driver was
+//!written. The module id `[20, 20, 3]`, message id 23, the message length of 57 bytes, the
+//!setpoints sent as 10 contiguous values in bytes 6..26 and the layout of the configuration and
+//!feedback frames are not verified against the module firmware. Check the driver against a
+//!module on the bench before it drives a machine.
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+use go_module_base::{
+    ConfigurationError, GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection,
+    ModuleCommunicationType, ModuleDriver, ModuleIdentity, ModuleStatus,
+};
+
+use crate::output_6_channel::{
+    OutputModule6ChannelChannel, OutputModule6ChannelFrequency, OutputModule6ChannelFunc,
+};
+
+const OUTPUTMODULE10CHANNELMESSAGELENGTH: usize = 57;
+pub(crate) const OUTPUTMODULE10CHANNELID: [u8; 3] = [20, 20, 3];
+
+#[repr(usize)]
+pub enum OutputModule10ChannelNum {
+    One = 1,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+}
+
+///The 10 channel module shares one pwm frequency between every two adjacent channels
+#[repr(usize)]
+pub enum OutputModule10ChannelFrequencyNum {
+    OneTwo,
+    ThreeFour,
+    FiveSix,
+    SevenEight,
+    NineTen,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct OutputModule10ChannelSetpoint {
+    pub channel1: u16,
+    pub channel2: u16,
+    pub channel3: u16,
+    pub channel4: u16,
+    pub channel5: u16,
+    pub channel6: u16,
+    pub channel7: u16,
+    pub channel8: u16,
+    pub channel9: u16,
+    pub channel10: u16,
+}
+
+#[derive(Clone, Copy)]
+pub struct OutputModule10ChannelValues {
+    pub temperature: i16,
+    pub ground_shift: u16,
+    pub error_code: u32,
+    pub supply_volt: u16,
+    pub channel1_cur: i16,
+    pub channel1_duty: u16,
+    pub channel2_cur: i16,
+    pub channel2_duty: u16,
+    pub channel3_cur: i16,
+    pub channel3_duty: u16,
+    pub channel4_cur: i16,
+    pub channel4_duty: u16,
+    pub channel5_cur: i16,
+    pub channel5_duty: u16,
+    pub channel6_cur: i16,
+    pub channel6_duty: u16,
+    pub channel7_cur: i16,
+    pub channel7_duty: u16,
+    pub channel8_cur: i16,
+    pub channel8_duty: u16,
+    pub channel9_cur: i16,
+    pub channel9_duty: u16,
+    pub channel10_cur: i16,
+    pub channel10_duty: u16,
+}
+
+///Configuration of a 10 channel output module
+///
+///Stored in the same format as [OutputModule6ChannelConfiguration](crate::output_6_channel::OutputModule6ChannelConfiguration),
+///with 10 `channels` and 5 `frequencies`.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct OutputModule10ChannelConfiguration {
+    channels: [OutputModule6ChannelChannel; 10],
+    frequencies: [OutputModule6ChannelFrequency; 5],
+}
+
+pub struct OutputModule10Channel<SPI, ResetPin, InterruptPin, Delay> {
+    module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+    configuration: OutputModule10ChannelConfiguration,
+    identity: ModuleIdentity,
+}
+
+pub struct OutputModule10ChannelBuilder<SPI, ResetPin, InterruptPin, Delay> {
+    module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+    configuration: OutputModule10ChannelConfiguration,
+}
+
+impl OutputModule10ChannelSetpoint {
+    fn serialize(&self, tx: &mut [u8]) {
+        tx[6..8].copy_from_slice(&self.channel1.to_le_bytes());
+        tx[8..10].copy_from_slice(&self.channel2.to_le_bytes());
+        tx[10..12].copy_from_slice(&self.channel3.to_le_bytes());
+        tx[12..14].copy_from_slice(&self.channel4.to_le_bytes());
+        tx[14..16].copy_from_slice(&self.channel5.to_le_bytes());
+        tx[16..18].copy_from_slice(&self.channel6.to_le_bytes());
+        tx[18..20].copy_from_slice(&self.channel7.to_le_bytes());
+        tx[20..22].copy_from_slice(&self.channel8.to_le_bytes());
+        tx[22..24].copy_from_slice(&self.channel9.to_le_bytes());
+        tx[24..26].copy_from_slice(&self.channel10.to_le_bytes());
+    }
+}
+
+impl OutputModule10ChannelConfiguration {
+    pub fn channel(&self, channel: OutputModule10ChannelNum) -> &OutputModule6ChannelChannel {
+        &self.channels[channel as usize - 1]
+    }
+
+    pub fn channels(&self) -> &[OutputModule6ChannelChannel; 10] {
+        &self.channels
+    }
+
+    pub fn frequencies(&self) -> &[OutputModule6ChannelFrequency; 5] {
+        &self.frequencies
+    }
+
+    fn serialize1(&self, tx: &mut [u8]) {
+        for (i, channel) in self.channels.iter().enumerate() {
+            let func_byte = channel.func.discriminant() << 4 | self.frequencies[i / 2] as u8;
+            tx[6 + i] = func_byte;
+            tx[16 + i * 2..18 + i * 2].copy_from_slice(&channel.max_current.to_le_bytes())
+        }
+    }
+
+    fn serialize2(&self, tx: &mut [u8]) {
+        for (i, channel) in self.channels.iter().enumerate() {
+            if let OutputModule6ChannelFunc::PeakAndHold(settings) = channel.func {
+                tx[6 + i * 2..8 + i * 2].copy_from_slice(&settings.peak_current.to_le_bytes());
+                tx[26 + i * 2..28 + i * 2].copy_from_slice(&settings.peak_time.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> OutputModule10Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    pub fn reconfigure(
+        self,
+    ) -> (
+        GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+        OutputModule10ChannelConfiguration,
+    ) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn set_and_read_channels(
+        &mut self,
+        setpoint: &OutputModule10ChannelSetpoint,
+    ) -> Result<
+        OutputModule10ChannelValues,
+        GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>,
+    > {
+        let mut tx = [0u8; OUTPUTMODULE10CHANNELMESSAGELENGTH + 5];
+        let mut rx = [0u8; OUTPUTMODULE10CHANNELMESSAGELENGTH + 5];
+        setpoint.serialize(&mut tx);
+        self.module.send_receive_spi(
+            ModuleCommunicationDirection::ToModule,
+            23,
+            ModuleCommunicationType::Data,
+            1,
+            &mut tx,
+            &mut rx,
+            OUTPUTMODULE10CHANNELMESSAGELENGTH,
+            0,
+        )?;
+        if rx[2] != ModuleCommunicationDirection::FromModule as u8
+            || rx[3] != 23
+            || rx[4] != ModuleCommunicationType::Feedback as u8
+            || rx[5] != 1
+        {
+            return Err(self.module.invalid_response());
+        }
+        Ok(OutputModule10ChannelValues {
+            temperature: i16::from_le_bytes(rx[6..8].try_into().unwrap()),
+            ground_shift: u16::from_le_bytes(rx[8..10].try_into().unwrap()),
+            channel1_cur: i16::from_le_bytes(rx[10..12].try_into().unwrap()),
+            channel2_cur: i16::from_le_bytes(rx[12..14].try_into().unwrap()),
+            channel3_cur: i16::from_le_bytes(rx[14..16].try_into().unwrap()),
+            channel4_cur: i16::from_le_bytes(rx[16..18].try_into().unwrap()),
+            channel5_cur: i16::from_le_bytes(rx[18..20].try_into().unwrap()),
+            channel6_cur: i16::from_le_bytes(rx[20..22].try_into().unwrap()),
+            channel7_cur: i16::from_le_bytes(rx[22..24].try_into().unwrap()),
+            channel8_cur: i16::from_le_bytes(rx[24..26].try_into().unwrap()),
+            channel9_cur: i16::from_le_bytes(rx[26..28].try_into().unwrap()),
+            channel10_cur: i16::from_le_bytes(rx[28..30].try_into().unwrap()),
+            error_code: u32::from_le_bytes(rx[30..34].try_into().unwrap()),
+            channel1_duty: u16::from_le_bytes(rx[34..36].try_into().unwrap()),
+            channel2_duty: u16::from_le_bytes(rx[36..38].try_into().unwrap()),
+            channel3_duty: u16::from_le_bytes(rx[38..40].try_into().unwrap()),
+            channel4_duty: u16::from_le_bytes(rx[40..42].try_into().unwrap()),
+            channel5_duty: u16::from_le_bytes(rx[42..44].try_into().unwrap()),
+            channel6_duty: u16::from_le_bytes(rx[44..46].try_into().unwrap()),
+            channel7_duty: u16::from_le_bytes(rx[46..48].try_into().unwrap()),
+            channel8_duty: u16::from_le_bytes(rx[48..50].try_into().unwrap()),
+            channel9_duty: u16::from_le_bytes(rx[50..52].try_into().unwrap()),
+            channel10_duty: u16::from_le_bytes(rx[52..54].try_into().unwrap()),
+            supply_volt: u16::from_le_bytes(rx[54..56].try_into().unwrap()),
+        })
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay>
+    OutputModule10ChannelBuilder<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    pub fn new(module: GoModule<SPI, ResetPin, InterruptPin, Delay>) -> Self {
+        OutputModule10ChannelBuilder {
+            module,
+            configuration: OutputModule10ChannelConfiguration::default(),
+        }
+    }
+
+    pub fn from_configuration(
+        module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
+        configuration: OutputModule10ChannelConfiguration,
+    ) -> Self {
+        OutputModule10ChannelBuilder {
+            module,
+            configuration,
+        }
+    }
+
+    pub fn configure_channel(
+        self,
+        channel: OutputModule10ChannelNum,
+        func: OutputModule6ChannelFunc,
+        max_current: u16,
+    ) -> Self {
+        let mut configuration = self.configuration;
+        configuration.channels[channel as usize - 1] =
+            OutputModule6ChannelChannel { func, max_current };
+        OutputModule10ChannelBuilder {
+            module: self.module,
+            configuration,
+        }
+    }
+
+    pub fn configure_frequency(
+        self,
+        channel: OutputModule10ChannelFrequencyNum,
+        freq: OutputModule6ChannelFrequency,
+    ) -> Self {
+        let mut configuration = self.configuration;
+        configuration.frequencies[channel as usize] = freq;
+        OutputModule10ChannelBuilder {
+            module: self.module,
+            configuration,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn build(
+        self,
+    ) -> Result<
+        OutputModule10Channel<SPI, ResetPin, InterruptPin, Delay>,
+        (
+            GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
+            OutputModule10ChannelConfiguration,
+            ConfigurationError,
+        ),
+    > {
+        let mut module = OutputModule10Channel {
+            module: self.module,
+            configuration: self.configuration,
+            identity: ModuleIdentity::default(),
+        };
+        let Ok(bootmessage) = module.module.escape_module_bootloader() else {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NoBootMessage,
+            ));
+        };
+
+        let identity = ModuleIdentity::from_bootmessage(&bootmessage);
+        if OUTPUTMODULE10CHANNELID != identity.id {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::WrongModule(identity),
+            ));
+        }
+        module.identity = identity;
+
+        let mut tx = [0u8; OUTPUTMODULE10CHANNELMESSAGELENGTH + 5];
+        module.configuration.serialize1(&mut tx);
+        if module
+            .module
+            .send_spi(
+                ModuleCommunicationDirection::ToModule,
+                23,
+                ModuleCommunicationType::Configuration,
+                1,
+                &mut tx,
+                OUTPUTMODULE10CHANNELMESSAGELENGTH,
+                500_000,
+            )
+            .is_err()
+        {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
+        }
+        let mut tx = [0u8; OUTPUTMODULE10CHANNELMESSAGELENGTH + 5];
+        module.configuration.serialize2(&mut tx);
+        if module
+            .module
+            .send_spi(
+                ModuleCommunicationDirection::ToModule,
+                23,
+                ModuleCommunicationType::Configuration,
+                2,
+                &mut tx,
+                OUTPUTMODULE10CHANNELMESSAGELENGTH,
+                500,
+            )
+            .is_err()
+        {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
+        }
+        //whether the module runs the configuration shows on the first exchange, which checks the
+        //message id, message type and length the module echoes in its feedback
+        Ok(module)
+    }
+}
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for OutputModule10Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = OutputModule10ChannelConfiguration;
+    type Setpoint = OutputModule10ChannelSetpoint;
+    type Values = OutputModule10ChannelValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    fn identity(&self) -> ModuleIdentity {
+        self.identity
+    }
+
+    fn degrade(self) -> Self::Module {
+        self.module.degrade()
+    }
+
+    fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        self.set_and_read_channels(setpoint)
+    }
+
+    fn status(&self) -> ModuleStatus {
+        self.module.status()
+    }
+
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        self.set_and_read_channels(&OutputModule10ChannelSetpoint::default())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::{
+        mock::{module_frame, ScriptedModule},
+        CommunicationError, GoModuleError, ModuleCommunicationType,
+    };
+
+    use super::{
+        OutputModule10ChannelBuilder, OutputModule10ChannelSetpoint, OUTPUTMODULE10CHANNELID,
+        OUTPUTMODULE10CHANNELMESSAGELENGTH,
+    };
+
+    #[test]
+    fn only_sends_the_configuration_frames() {
+        let (module, spi) = ScriptedModule::identifying_as(2, OUTPUTMODULE10CHANNELID);
+        assert!(OutputModule10ChannelBuilder::new(module).build().is_ok());
+        //the escape and both configuration frames, no setpoint until the application sends one
+        let written = spi.written();
+        assert_eq!(written.len(), 3);
+        assert_eq!(written[1][4], ModuleCommunicationType::Configuration as u8);
+        assert_eq!(written[1][5], 1);
+        assert_eq!(written[2][4], ModuleCommunicationType::Configuration as u8);
+        assert_eq!(written[2][5], 2);
+    }
+
+    #[test]
+    fn checks_the_header_the_module_echoes() {
+        let (module, spi) = ScriptedModule::identifying_as(2, OUTPUTMODULE10CHANNELID);
+        let Ok(mut module) = OutputModule10ChannelBuilder::new(module).build() else {
+            panic!("the module is configured");
+        };
+        let setpoint = OutputModule10ChannelSetpoint::default();
+
+        spi.reply(module_frame(
+            23,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE10CHANNELMESSAGELENGTH,
+        ));
+        assert!(module.set_and_read_channels(&setpoint).is_ok());
+
+        //the feedback of another kind of module
+        spi.reply(module_frame(
+            24,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE10CHANNELMESSAGELENGTH,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::UnableToSerDe
+            ))
+        ));
+
+        //a frame of the wrong length
+        spi.reply(module_frame(
+            23,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE10CHANNELMESSAGELENGTH - 1,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::ChecksumIncorrect
+            ))
+        ));
+        assert_eq!(spi.pending(), 0);
+    }
+}
@@ -5,14 +5,16 @@ use embedded_hal::{
 };
 
 use go_module_base::{
-    GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection, ModuleCommunicationType,
+    ConfigurationError, GoModule, GoModuleError, GoModuleUnknown, ModuleCommunicationDirection,
+    ModuleCommunicationType, ModuleDriver, ModuleIdentity, ModuleStatus,
 };
 
 const OUTPUTMODULE6CHANNELMESSAGELENGTH: usize = 44;
-const OUTPUTMODULE6CHANNELID: [u8; 3] = [20, 20, 2];
+pub(crate) const OUTPUTMODULE6CHANNELID: [u8; 3] = [20, 20, 2];
 
 #[repr(u8)]
 #[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OutputModule6ChannelFunc {
     #[default]
     Disabled = 1,
@@ -26,7 +28,7 @@ pub enum OutputModule6ChannelFunc {
 }
 
 impl OutputModule6ChannelFunc {
-    fn discriminant(&self) -> u8 {
+    pub(crate) fn discriminant(&self) -> u8 {
         //This is only safe to do on enums with #[repr(u8)].
         //Sadly there seems to be no inherently safe method to do this.
         unsafe { *<*const _>::from(self).cast::<u8>() }
@@ -35,6 +37,7 @@ impl OutputModule6ChannelFunc {
 
 #[repr(u8)]
 #[derive(Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OutputModule6ChannelFrequency {
     Hz100 = 1,
     Hz200,
@@ -64,17 +67,20 @@ pub enum OutputModule6ChannelFrequencyNum {
 }
 
 #[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PeakAndHoldSettings {
     pub peak_time: u16,
     pub peak_current: u16,
 }
 
 #[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutputModule6ChannelChannel {
-    func: OutputModule6ChannelFunc,
-    max_current: u16,
+    pub(crate) func: OutputModule6ChannelFunc,
+    pub(crate) max_current: u16,
 }
 
+#[derive(Default, Clone, Copy)]
 pub struct OutputModule6ChannelSetpoint {
     pub channel1: u16,
     pub channel2: u16,
@@ -84,6 +90,7 @@ pub struct OutputModule6ChannelSetpoint {
     pub channel6: u16,
 }
 
+#[derive(Clone, Copy)]
 pub struct OutputModule6ChannelValues {
     pub temperature: i16,
     pub ground_shift: u16,
@@ -103,7 +110,39 @@ pub struct OutputModule6ChannelValues {
     pub channel6_duty: u16,
 }
 
+///Configuration of a 6 channel output module
+///
+///With the `serde` feature this can be stored in a file, fields that are left out keep their
+///default value. `channels` has to list all 6 channels and `frequencies` all 3 channel pairs.
+///`max_current` is in mA. In TOML:
+///```toml
+///frequencies = ["Hz1_000", "Hz100", "Hz1_000"]
+///channels = [
+///    { func = "HighSideDuty", max_current = 2500 },
+///    { func = { PeakAndHold = { peak_time = 200, peak_current = 3000 } }, max_current = 1500 },
+///    { func = "LowSideBool", max_current = 1000 },
+///    { func = "Disabled", max_current = 0 },
+///    { func = "Disabled", max_current = 0 },
+///    { func = "Disabled", max_current = 0 },
+///]
+///```
+///Or in JSON:
+///```json
+///{
+///  "channels": [
+///    { "func": "HighSideDuty", "max_current": 2500 },
+///    { "func": { "PeakAndHold": { "peak_time": 200, "peak_current": 3000 } }, "max_current": 1500 },
+///    { "func": "LowSideBool", "max_current": 1000 },
+///    { "func": "Disabled", "max_current": 0 },
+///    { "func": "Disabled", "max_current": 0 },
+///    { "func": "Disabled", "max_current": 0 }
+///  ],
+///  "frequencies": ["Hz1_000", "Hz100", "Hz1_000"]
+///}
+///```
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct OutputModule6ChannelConfiguration {
     channels: [OutputModule6ChannelChannel; 6],
     frequencies: [OutputModule6ChannelFrequency; 3],
@@ -112,6 +151,7 @@ pub struct OutputModule6ChannelConfiguration {
 pub struct OutputModule6Channel<SPI, ResetPin, InterruptPin, Delay> {
     module: GoModule<SPI, ResetPin, InterruptPin, Delay>,
     configuration: OutputModule6ChannelConfiguration,
+    identity: ModuleIdentity,
 }
 
 pub struct OutputModule6ChannelBuilder<SPI, ResetPin, InterruptPin, Delay> {
@@ -130,7 +170,29 @@ impl OutputModule6ChannelSetpoint {
     }
 }
 
+impl OutputModule6ChannelChannel {
+    pub fn func(&self) -> OutputModule6ChannelFunc {
+        self.func
+    }
+
+    pub fn max_current(&self) -> u16 {
+        self.max_current
+    }
+}
+
 impl OutputModule6ChannelConfiguration {
+    pub fn channel(&self, channel: OutputModule6ChannelNum) -> &OutputModule6ChannelChannel {
+        &self.channels[channel as usize - 1]
+    }
+
+    pub fn channels(&self) -> &[OutputModule6ChannelChannel; 6] {
+        &self.channels
+    }
+
+    pub fn frequencies(&self) -> &[OutputModule6ChannelFrequency; 3] {
+        &self.frequencies
+    }
+
     fn serialize1(&self, tx: &mut [u8]) {
         for (i, channel) in self.channels.iter().enumerate() {
             let func_byte = channel.func.discriminant() << 4 | self.frequencies[i / 2] as u8;
@@ -141,12 +203,9 @@ impl OutputModule6ChannelConfiguration {
 
     fn serialize2(&self, tx: &mut [u8]) {
         for (i, channel) in self.channels.iter().enumerate() {
-            match channel.func {
-                OutputModule6ChannelFunc::PeakAndHold(settings) => {
-                    tx[6 + i * 2..8 + i * 2].copy_from_slice(&settings.peak_current.to_le_bytes());
-                    tx[18 + i * 2..20 + i * 2].copy_from_slice(&settings.peak_time.to_le_bytes());
-                }
-                _ => {}
+            if let OutputModule6ChannelFunc::PeakAndHold(settings) = channel.func {
+                tx[6 + i * 2..8 + i * 2].copy_from_slice(&settings.peak_current.to_le_bytes());
+                tx[18 + i * 2..20 + i * 2].copy_from_slice(&settings.peak_time.to_le_bytes());
             }
         }
     }
@@ -168,6 +227,7 @@ where
         (self.module.degrade(), self.configuration)
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn set_and_read_channels(
         &mut self,
         setpoint: &OutputModule6ChannelSetpoint,
@@ -193,9 +253,7 @@ where
             || rx[4] != ModuleCommunicationType::Feedback as u8
             || rx[5] != 1
         {
-            return Err(GoModuleError::CommunicationError(
-                go_module_base::CommunicationError::UnableToSerDe,
-            ));
+            return Err(self.module.invalid_response());
         }
         Ok(OutputModule6ChannelValues {
             temperature: i16::from_le_bytes(rx[6..8].try_into().unwrap()),
@@ -271,6 +329,7 @@ where
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn build(
         self,
     ) -> Result<
@@ -278,19 +337,31 @@ where
         (
             GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>,
             OutputModule6ChannelConfiguration,
+            ConfigurationError,
         ),
     > {
         let mut module = OutputModule6Channel {
             module: self.module,
             configuration: self.configuration,
+            identity: ModuleIdentity::default(),
         };
         let Ok(bootmessage) = module.module.escape_module_bootloader() else {
-            return Err((module.module.degrade(), module.configuration));
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NoBootMessage,
+            ));
         };
 
-        if OUTPUTMODULE6CHANNELID != bootmessage[6..9] {
-            return Err((module.module.degrade(), module.configuration));
+        let identity = ModuleIdentity::from_bootmessage(&bootmessage);
+        if OUTPUTMODULE6CHANNELID != identity.id {
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::WrongModule(identity),
+            ));
         }
+        module.identity = identity;
 
         let mut tx = [0u8; OUTPUTMODULE6CHANNELMESSAGELENGTH + 5];
         module.configuration.serialize1(&mut tx);
@@ -307,7 +378,11 @@ where
             )
             .is_err()
         {
-            return Err((module.module.degrade(), module.configuration));
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
         }
         module.configuration.serialize2(&mut tx);
         if module
@@ -323,8 +398,139 @@ where
             )
             .is_err()
         {
-            return Err((module.module.degrade(), module.configuration));
+            return Err((
+                module.module.degrade(),
+                module.configuration,
+                ConfigurationError::NotSent,
+            ));
         }
+        //whether the module runs the configuration shows on the first exchange, which checks the
+        //message id, message type and length the module echoes in its feedback
         Ok(module)
     }
 }
+
+impl<SPI, ResetPin, InterruptPin, Delay> ModuleDriver
+    for OutputModule6Channel<SPI, ResetPin, InterruptPin, Delay>
+where
+    SPI: SpiDevice,
+    ResetPin: OutputPin,
+    InterruptPin: InputPin,
+    Delay: DelayNs,
+{
+    type Configuration = OutputModule6ChannelConfiguration;
+    type Setpoint = OutputModule6ChannelSetpoint;
+    type Values = OutputModule6ChannelValues;
+    type Module = GoModuleUnknown<SPI, ResetPin, InterruptPin, Delay>;
+    type Error = GoModuleError<SPI::Error, ResetPin::Error, InterruptPin::Error>;
+
+    fn identity(&self) -> ModuleIdentity {
+        self.identity
+    }
+
+    fn degrade(self) -> Self::Module {
+        self.module.degrade()
+    }
+
+    fn reconfigure(self) -> (Self::Module, Self::Configuration) {
+        (self.module.degrade(), self.configuration)
+    }
+
+    fn exchange(&mut self, setpoint: &Self::Setpoint) -> Result<Self::Values, Self::Error> {
+        self.set_and_read_channels(setpoint)
+    }
+
+    fn status(&self) -> ModuleStatus {
+        self.module.status()
+    }
+
+    fn safe_state(&mut self) -> Result<(), Self::Error> {
+        self.set_and_read_channels(&OutputModule6ChannelSetpoint::default())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use go_module_base::{
+        mock::{module_frame, ScriptedModule},
+        CommunicationError, ConfigurationError, GoModuleError, ModuleCommunicationType,
+    };
+
+    use super::{
+        OutputModule6ChannelBuilder, OutputModule6ChannelSetpoint, OUTPUTMODULE6CHANNELID,
+        OUTPUTMODULE6CHANNELMESSAGELENGTH,
+    };
+
+    #[test]
+    fn only_sends_the_configuration_frames() {
+        let (module, spi) = ScriptedModule::identifying_as(1, OUTPUTMODULE6CHANNELID);
+        assert!(OutputModule6ChannelBuilder::new(module).build().is_ok());
+        //the escape and both configuration frames, no setpoint until the application sends one
+        let written = spi.written();
+        assert_eq!(written.len(), 3);
+        assert_eq!(written[1][4], ModuleCommunicationType::Configuration as u8);
+        assert_eq!(written[1][5], 1);
+        assert_eq!(written[2][4], ModuleCommunicationType::Configuration as u8);
+        assert_eq!(written[2][5], 2);
+    }
+
+    #[test]
+    fn checks_the_header_the_module_echoes() {
+        let (module, spi) = ScriptedModule::identifying_as(1, OUTPUTMODULE6CHANNELID);
+        let Ok(mut module) = OutputModule6ChannelBuilder::new(module).build() else {
+            panic!("the module is configured");
+        };
+        let setpoint = OutputModule6ChannelSetpoint::default();
+
+        spi.reply(module_frame(
+            22,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE6CHANNELMESSAGELENGTH,
+        ));
+        assert!(module.set_and_read_channels(&setpoint).is_ok());
+
+        //the feedback of another kind of module
+        spi.reply(module_frame(
+            23,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE6CHANNELMESSAGELENGTH,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::UnableToSerDe
+            ))
+        ));
+
+        //a frame of the wrong length
+        spi.reply(module_frame(
+            22,
+            ModuleCommunicationType::Feedback,
+            1,
+            &[],
+            OUTPUTMODULE6CHANNELMESSAGELENGTH - 1,
+        ));
+        assert!(matches!(
+            module.set_and_read_channels(&setpoint),
+            Err(GoModuleError::CommunicationError(
+                CommunicationError::ChecksumIncorrect
+            ))
+        ));
+        assert_eq!(spi.pending(), 0);
+    }
+
+    #[test]
+    fn refuses_a_different_module() {
+        let (module, spi) = ScriptedModule::identifying_as(1, [20, 10, 1]);
+        assert!(matches!(
+            OutputModule6ChannelBuilder::new(module).build(),
+            Err((_, _, ConfigurationError::WrongModule(identity))) if identity.id == [20, 10, 1]
+        ));
+        assert_eq!(spi.written().len(), 1, "nothing is sent after the escape");
+    }
+}
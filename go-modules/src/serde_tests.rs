@@ -0,0 +1,111 @@
+//!The file format of the module configurations: every configuration has to come back unchanged
+//!from TOML and JSON, and the examples in the documentation have to read as documented
+extern crate std;
+
+use go_module_base::mock::doc_example;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    any_module::{AnyModuleConfiguration, ModuleKind},
+    bridge_2_channel::BridgeModule2ChannelConfiguration,
+    input_6_channel::InputModule6ChannelConfiguration,
+    output_10_channel::OutputModule10ChannelConfiguration,
+    output_6_channel::OutputModule6ChannelConfiguration,
+};
+
+///Check that `value` comes back unchanged from TOML and JSON, returns it as JSON to check its fields
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Value {
+    let json = serde_json::to_value(value).unwrap();
+    let from_json: T = serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(&from_json).unwrap(), json);
+    let from_toml: T = toml::from_str(&toml::to_string(value).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(&from_toml).unwrap(), json);
+    json
+}
+
+///Read the TOML example in the documentation in `source` and round trip it, when there is a JSON
+///example as well it has to describe the same configuration. Returns the example as JSON
+fn documented<T: Serialize + DeserializeOwned>(source: &str) -> Value {
+    let from_toml: T = toml::from_str(&doc_example(source, "toml")).unwrap();
+    let json = round_trip(&from_toml);
+    let example = doc_example(source, "json");
+    if !example.is_empty() {
+        let from_json: T = serde_json::from_str(&example).unwrap();
+        assert_eq!(serde_json::to_value(&from_json).unwrap(), json);
+    }
+    json
+}
+
+#[test]
+fn input_6_channel() {
+    let json = documented::<InputModule6ChannelConfiguration>(include_str!("input_6_channel.rs"));
+    assert_eq!(json["supplies"], json!(["On", "On", "Off"]));
+    assert_eq!(
+        json["channels"][0],
+        json!({ "func": { "AnalogmV": 100 }, "pu": "None", "pd": "PD10k", "volt": "Voltage24V" })
+    );
+    assert_eq!(json["channels"][1]["func"], json!("Frequency"));
+
+    //left out fields keep their default
+    let supplies: InputModule6ChannelConfiguration =
+        toml::from_str(r#"supplies = ["Off", "Off", "Off"]"#).unwrap();
+    assert_eq!(
+        round_trip(&supplies)["channels"],
+        serde_json::to_value(InputModule6ChannelConfiguration::default()).unwrap()["channels"]
+    );
+}
+
+#[test]
+fn output_6_channel() {
+    let json = documented::<OutputModule6ChannelConfiguration>(include_str!("output_6_channel.rs"));
+    assert_eq!(
+        json["channels"][1],
+        json!({
+            "func": { "PeakAndHold": { "peak_time": 200, "peak_current": 3000 } },
+            "max_current": 1500
+        })
+    );
+    assert_eq!(json["frequencies"], json!(["Hz1_000", "Hz100", "Hz1_000"]));
+}
+
+#[test]
+fn output_10_channel() {
+    let configuration: OutputModule10ChannelConfiguration =
+        toml::from_str(r#"frequencies = ["Hz1_000", "Hz1_000", "Hz1_000", "Hz1_000", "Hz200"]"#)
+            .unwrap();
+    let json = round_trip(&configuration);
+    assert_eq!(json["channels"].as_array().unwrap().len(), 10);
+    assert_eq!(
+        json["channels"][9],
+        json!({ "func": "Disabled", "max_current": 0 })
+    );
+    assert_eq!(json["frequencies"][4], json!("Hz200"));
+}
+
+#[test]
+fn bridge_2_channel() {
+    let json = documented::<BridgeModule2ChannelConfiguration>(include_str!("bridge_2_channel.rs"));
+    assert_eq!(
+        json["channels"][0],
+        json!({ "func": "DutyBrake", "frequency": "Hz10_000", "max_current": 4000 })
+    );
+}
+
+#[test]
+fn any_module() {
+    let json = documented::<AnyModuleConfiguration>(include_str!("any_module.rs"));
+    assert_eq!(
+        json["Bridge2Channel"]["channels"][0]["func"],
+        json!("DutyCoast")
+    );
+
+    //stored as a table named after the kind of module
+    for kind in ModuleKind::ALL {
+        let json = round_trip(&AnyModuleConfiguration::default_for(kind));
+        let (name, _) = json.as_object().unwrap().iter().next().unwrap();
+        assert_eq!(*name, std::format!("{kind:?}"));
+        let configuration: AnyModuleConfiguration = serde_json::from_value(json).unwrap();
+        assert_eq!(configuration.kind(), kind);
+    }
+}
@@ -0,0 +1,690 @@
+//!goctl inspects and exercises the modules of a Moduline controller, for commissioning and field service.
+//!Every command prints JSON instead of text with `--json`.
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use go_mainboard::{
+    controller::{Controller, DiscoverError, DiscoveredSlot, SlotFault, SlotState},
+    get_controller_type,
+    io_config::LinuxAnyModule,
+    slot_map::{SlotMap, SlotMapError},
+    ControllerSlot, ControllerType, DetectError,
+};
+use go_module_base::{
+    ConfigurationError, ModuleCommunicationDirection, ModuleCommunicationType, ModuleDriver,
+    ModuleIdentity, ModuleSetupError,
+};
+use go_modules::any_module::{
+    AnyModule, AnyModuleConfiguration, AnyModuleSetpoint, ChannelValue, ModuleKind,
+};
+use serde::Serialize;
+
+#[derive(Parser)]
+#[command(
+    name = "goctl",
+    about = "Inspect and exercise the modules of a Moduline controller"
+)]
+struct Cli {
+    ///Print JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    ///Show the controller type, hardware version, slot count and how modules are reset
+    Detect,
+    ///List the slots with the module in each of them
+    Slots,
+    ///Reset the module in a slot
+    Reset { slot: u8 },
+    ///Configure the module in a slot and read its channels
+    Read {
+        slot: u8,
+        ///Configuration of the module in TOML, the default configuration of the module when not given
+        #[arg(long)]
+        config: Option<PathBuf>,
+        ///Keep reading with this many ms in between until interrupted
+        #[arg(long)]
+        watch: Option<u64>,
+    },
+    ///Drive an output channel for a while and show the feedback, the module is put in its safe state after.
+    ///Ctrl-C ends the hold early
+    Set {
+        slot: u8,
+        channel: u8,
+        ///Duty cycle setpoint, a u16 for outputs and an i16 for bridges
+        #[arg(allow_negative_numbers = true)]
+        value: i32,
+        ///Configuration of the module in TOML, the default configuration of the module when not given
+        #[arg(long)]
+        config: Option<PathBuf>,
+        ///How long the output is driven
+        #[arg(long, default_value_t = 2000)]
+        hold_ms: u64,
+        ///Do not ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    ///Reset the module in a slot, send it one frame and print the answer
+    Raw {
+        slot: u8,
+        ///Escape the bootloader and print the boot message before sending the frame
+        #[arg(long)]
+        escape: bool,
+        #[arg(long, default_value_t = 11)]
+        module_id: u8,
+        #[arg(long, value_enum, default_value_t = MessageType::Data)]
+        message_type: MessageType,
+        #[arg(long, default_value_t = 1)]
+        index: u8,
+        ///Length of the frame including header and checksum, fits the payload when not given
+        #[arg(long)]
+        len: Option<usize>,
+        ///Payload bytes in hex that follow the 6 byte header
+        payload: Vec<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MessageType {
+    ModuleId,
+    Configuration,
+    Data,
+    Feedback,
+}
+
+impl From<MessageType> for ModuleCommunicationType {
+    fn from(message_type: MessageType) -> Self {
+        match message_type {
+            MessageType::ModuleId => ModuleCommunicationType::ModuleId,
+            MessageType::Configuration => ModuleCommunicationType::Configuration,
+            MessageType::Data => ModuleCommunicationType::Data,
+            MessageType::Feedback => ModuleCommunicationType::Feedback,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GoctlError {
+    Detect(DetectError),
+    SlotMap(SlotMapError),
+    NoSuchSlot(u8),
+    Open(ModuleSetupError),
+    Reset,
+    ///The slot does not hold a module goctl has a driver for
+    NoModule(SlotState),
+    Config(PathBuf, String),
+    WrongConfig(ModuleKind, ModuleKind),
+    Configuration(ConfigurationError),
+    InvalidOutput {
+        channel: u8,
+        value: i32,
+    },
+    InvalidHex(String),
+    ///The length byte of the header can't describe a frame this long
+    FrameTooLong(usize),
+    Aborted,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for GoctlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoctlError::Detect(err) => write!(f, "could not detect the controller: {err:?}"),
+            GoctlError::SlotMap(err) => write!(f, "could not read the slot map: {err:?}"),
+            GoctlError::NoSuchSlot(slot) => write!(f, "the controller has no slot {slot}"),
+            GoctlError::Open(err) => write!(f, "could not open the slot: {err:?}"),
+            GoctlError::Reset => write!(f, "could not reset the module"),
+            GoctlError::NoModule(state) => write!(f, "no usable module in the slot: {state:?}"),
+            GoctlError::Config(path, err) => write!(f, "{}: {err}", path.display()),
+            GoctlError::WrongConfig(config, module) => {
+                write!(
+                    f,
+                    "configuration for {config:?} but the slot holds {module:?}"
+                )
+            }
+            GoctlError::Configuration(err) => write!(f, "module was not configured: {err:?}"),
+            GoctlError::InvalidOutput { channel, value } => {
+                write!(f, "channel {channel} is not an output that takes {value}")
+            }
+            GoctlError::InvalidHex(byte) => write!(f, "{byte} is not a hex byte"),
+            GoctlError::FrameTooLong(len) => {
+                write!(f, "a frame is at most {MAXFRAMELENGTH} bytes, {len} needed")
+            }
+            GoctlError::Aborted => write!(f, "aborted"),
+            GoctlError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<io::Error> for GoctlError {
+    fn from(err: io::Error) -> Self {
+        GoctlError::Io(err)
+    }
+}
+
+#[derive(Serialize)]
+struct DetectOutput {
+    controller: &'static str,
+    hardware_version: String,
+    slots: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct SlotOutput {
+    slot: u8,
+    state: &'static str,
+    kind: Option<String>,
+    id: Option<[u8; 3]>,
+    hardware_version: Option<u8>,
+    firmware_version: Option<String>,
+    fault: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChannelOutput {
+    channel: u8,
+    input: Option<u32>,
+    current: Option<i16>,
+    duty: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct ReadingOutput {
+    slot: u8,
+    kind: String,
+    channels: Vec<ChannelOutput>,
+    error_code: Option<u32>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RawOutput {
+    bootmessage: Option<String>,
+    sent: String,
+    received: String,
+    error: Option<String>,
+}
+
+fn slot_number(slot: u8) -> Result<ControllerSlot, GoctlError> {
+    ControllerSlot::from_number(slot).ok_or(GoctlError::NoSuchSlot(slot))
+}
+
+fn slot_map() -> Result<SlotMap, GoctlError> {
+    SlotMap::detect(&get_controller_type().map_err(GoctlError::Detect)?, "/")
+        .map_err(GoctlError::SlotMap)
+}
+
+fn controller_name(controller_type: &ControllerType) -> &'static str {
+    match controller_type {
+        ControllerType::ModulineIV(_) => "Moduline IV",
+        ControllerType::ModulineIII(_) => "Moduline III",
+        ControllerType::ModulineMini(_) => "Moduline Mini",
+        ControllerType::ModulineDisplay(_) => "Moduline Display",
+    }
+}
+
+fn firmware(identity: &ModuleIdentity) -> String {
+    let [major, minor, patch] = identity.firmware_version;
+    format!("{major}.{minor}.{patch}")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn print<T: Serialize>(json: bool, output: &T, text: impl FnOnce(&T)) {
+    if json {
+        println!("{}", serde_json::to_string(output).unwrap());
+    } else {
+        text(output);
+    }
+}
+
+///Identify the module in a slot and build its driver with the configuration from the file or the default one
+fn build(slot: u8, config: Option<PathBuf>) -> Result<LinuxAnyModule, GoctlError> {
+    let slot_map = slot_map()?;
+    let mut discovered = DiscoveredSlot::discover(&slot_map, slot_number(slot)?);
+    let SlotState::Module {
+        kind: Some(kind), ..
+    } = discovered.state
+    else {
+        return Err(GoctlError::NoModule(discovered.state));
+    };
+    let configuration = match config {
+        Some(path) => {
+            let toml = fs::read_to_string(&path)
+                .map_err(|err| GoctlError::Config(path.clone(), err.to_string()))?;
+            let configuration: AnyModuleConfiguration =
+                toml::from_str(&toml).map_err(|err| GoctlError::Config(path, err.to_string()))?;
+            if configuration.kind() != kind {
+                return Err(GoctlError::WrongConfig(configuration.kind(), kind));
+            }
+            configuration
+        }
+        None => AnyModuleConfiguration::default_for(kind),
+    };
+    //discovery escaped the bootloader, the module has to be reset before it can be configured
+    let module = discovered
+        .take()
+        .ok_or(GoctlError::Reset)?
+        .module_reset()
+        .map_err(|_| GoctlError::Reset)?;
+    AnyModule::build(module, configuration).map_err(|(_, _, err)| GoctlError::Configuration(err))
+}
+
+fn reading(slot: u8, module: &mut LinuxAnyModule, setpoint: &AnyModuleSetpoint) -> ReadingOutput {
+    let kind = module.kind();
+    let mut output = ReadingOutput {
+        slot,
+        kind: format!("{kind:?}"),
+        channels: Vec::new(),
+        error_code: None,
+        error: None,
+    };
+    match module.exchange(setpoint) {
+        Ok(values) => {
+            output.channels = (1..=kind.channels())
+                .filter_map(|channel| {
+                    let value = values.channel(channel)?;
+                    let (input, current, duty) = match value {
+                        ChannelValue::Input(value) => (Some(value), None, None),
+                        ChannelValue::Output { current, duty } => {
+                            (None, Some(current), Some(duty as i32))
+                        }
+                        ChannelValue::Bridge { current, duty } => {
+                            (None, Some(current), Some(duty as i32))
+                        }
+                    };
+                    Some(ChannelOutput {
+                        channel,
+                        input,
+                        current,
+                        duty,
+                    })
+                })
+                .collect();
+            output.error_code = values.error_code();
+        }
+        Err(err) => output.error = Some(format!("{err:?}")),
+    }
+    output
+}
+
+fn print_reading(reading: &ReadingOutput) {
+    println!("slot {} {}", reading.slot, reading.kind);
+    if let Some(error) = &reading.error {
+        println!("  exchange failed: {error}");
+        return;
+    }
+    for channel in &reading.channels {
+        match (channel.input, channel.current, channel.duty) {
+            (Some(input), _, _) => println!("  channel {:2}: {input}", channel.channel),
+            (_, Some(current), Some(duty)) => println!(
+                "  channel {:2}: duty {duty} current {current}",
+                channel.channel
+            ),
+            _ => {}
+        }
+    }
+    if let Some(error_code) = reading.error_code {
+        println!("  error code {error_code:#010x}");
+    }
+}
+
+fn detect(json: bool) -> Result<(), GoctlError> {
+    let controller_type = get_controller_type().map_err(GoctlError::Detect)?;
+    let output = DetectOutput {
+        controller: controller_name(&controller_type),
+        hardware_version: controller_type.hw_version().to_string(),
+        slots: slot_map()?.slots.iter().map(|slot| slot.slot).collect(),
+    };
+    print(json, &output, |output| {
+        println!("{} {}", output.controller, output.hardware_version);
+        println!("  slots: {:?}", output.slots);
+    });
+    Ok(())
+}
+
+fn slots(json: bool) -> Result<(), GoctlError> {
+    let controller = Controller::discover().map_err(|err| match err {
+        DiscoverError::Detect(err) => GoctlError::Detect(err),
+        DiscoverError::SlotMap(err) => GoctlError::SlotMap(err),
+    })?;
+    let slots: Vec<_> = controller
+        .slots()
+        .iter()
+        .map(|discovered| {
+            let mut output = SlotOutput {
+                slot: discovered.slot.number(),
+                state: "empty",
+                kind: None,
+                id: None,
+                hardware_version: None,
+                firmware_version: None,
+                fault: None,
+            };
+            match discovered.state {
+                SlotState::Empty => {}
+                SlotState::Faulty(fault) => {
+                    output.state = "faulty";
+                    output.fault = Some(match fault {
+                        SlotFault::Open(err) => format!("could not open the slot: {err:?}"),
+                        SlotFault::Reset => "could not reset the module".to_owned(),
+                        SlotFault::NoBootMessage => "no boot message".to_owned(),
+                        SlotFault::InvalidBootMessage => "invalid boot message".to_owned(),
+                        SlotFault::Panicked => "discovery panicked".to_owned(),
+                    });
+                }
+                SlotState::Module { kind, identity } => {
+                    output.state = "module";
+                    output.kind = kind.map(|kind| format!("{kind:?}"));
+                    output.id = Some(identity.id);
+                    output.hardware_version = Some(identity.hardware_version);
+                    output.firmware_version = Some(firmware(&identity));
+                }
+            }
+            output
+        })
+        .collect();
+    print(json, &slots, |slots| {
+        for slot in slots {
+            match slot.state {
+                "module" => println!(
+                    "slot {}: {} id {:?} hardware {} firmware {}",
+                    slot.slot,
+                    slot.kind.as_deref().unwrap_or("unknown module"),
+                    slot.id.unwrap_or_default(),
+                    slot.hardware_version.unwrap_or_default(),
+                    slot.firmware_version.as_deref().unwrap_or_default(),
+                ),
+                "faulty" => println!(
+                    "slot {}: faulty, {}",
+                    slot.slot,
+                    slot.fault.as_deref().unwrap_or_default()
+                ),
+                state => println!("slot {}: {state}", slot.slot),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn reset(json: bool, slot: u8) -> Result<(), GoctlError> {
+    let module = slot_map()?
+        .open(slot_number(slot)?)
+        .map_err(GoctlError::Open)?;
+    module.module_reset().map_err(|_| GoctlError::Reset)?;
+    print(
+        json,
+        &serde_json::json!({ "slot": slot, "reset": true }),
+        |_| println!("slot {slot} reset"),
+    );
+    Ok(())
+}
+
+fn read(
+    json: bool,
+    slot: u8,
+    config: Option<PathBuf>,
+    watch: Option<u64>,
+) -> Result<(), GoctlError> {
+    let mut module = build(slot, config)?;
+    let setpoint = AnyModuleSetpoint::off(module.kind());
+    if watch.is_some() {
+        catch_interrupt();
+    }
+    loop {
+        let output = reading(slot, &mut module, &setpoint);
+        match watch {
+            //clear the screen so the view refreshes in place
+            Some(_) if !json => print!("\x1b[2J\x1b[H"),
+            _ => {}
+        }
+        print(json, &output, print_reading);
+        let Some(ms) = watch else { break };
+        //Ctrl-C ends the watch, the module is put in its safe state below
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(ms) && !INTERRUPTED.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    let _ = module.safe_state();
+    Ok(())
+}
+
+fn confirm(question: &str) -> Result<bool, GoctlError> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn set(
+    json: bool,
+    slot: u8,
+    channel: u8,
+    value: i32,
+    config: Option<PathBuf>,
+    hold: Duration,
+    yes: bool,
+) -> Result<(), GoctlError> {
+    let mut module = build(slot, config)?;
+    let mut setpoint = AnyModuleSetpoint::off(module.kind());
+    if !setpoint.set_channel(channel, value) {
+        let _ = module.safe_state();
+        return Err(GoctlError::InvalidOutput { channel, value });
+    }
+    if !yes
+        && !confirm(&format!(
+            "Drive channel {channel} of the {:?} in slot {slot} to {value} for {} ms?",
+            module.kind(),
+            hold.as_millis()
+        ))?
+    {
+        let _ = module.safe_state();
+        return Err(GoctlError::Aborted);
+    }
+    catch_interrupt();
+    let start = Instant::now();
+    let mut output = reading(slot, &mut module, &setpoint);
+    while start.elapsed() < hold && !INTERRUPTED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(10));
+        output = reading(slot, &mut module, &setpoint);
+    }
+    let _ = module.safe_state();
+    print(json, &output, print_reading);
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        true => Err(GoctlError::Aborted),
+        false => Ok(()),
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupted(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+///Let Ctrl-C end a hold or watch instead of killing goctl before the module is in its safe state
+fn catch_interrupt() {
+    let handler: extern "C" fn(libc::c_int) = interrupted;
+    //SAFETY: the handler only stores to an atomic, which is async signal safe
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+fn parse_hex(bytes: &[String]) -> Result<Vec<u8>, GoctlError> {
+    bytes
+        .iter()
+        .map(|byte| {
+            u8::from_str_radix(byte.trim_start_matches("0x"), 16)
+                .map_err(|_| GoctlError::InvalidHex(byte.clone()))
+        })
+        .collect()
+}
+
+///The length byte of the header, which the module echoes, limits the frame length
+const MAXFRAMELENGTH: usize = u8::MAX as usize;
+
+///Length of a raw frame: the requested one, or just enough for the 6 byte header, the payload and the checksum
+fn frame_len(len: Option<usize>, payload: usize) -> Result<usize, GoctlError> {
+    match len.unwrap_or(0).max(payload + 7) {
+        len if len > MAXFRAMELENGTH => Err(GoctlError::FrameTooLong(len)),
+        len => Ok(len),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn raw(
+    json: bool,
+    slot: u8,
+    escape: bool,
+    module_id: u8,
+    message_type: MessageType,
+    index: u8,
+    len: Option<usize>,
+    payload: &[String],
+) -> Result<(), GoctlError> {
+    let payload = parse_hex(payload)?;
+    //checked before the module is reset
+    frame_len(len, payload.len())?;
+    let mut module = slot_map()?
+        .open(slot_number(slot)?)
+        .map_err(GoctlError::Open)?
+        .module_reset()
+        .map_err(|_| GoctlError::Reset)?;
+    let bootmessage = match escape {
+        true => Some(hex(&module.escape_module_bootloader().map_err(|_| {
+            GoctlError::NoModule(SlotState::Faulty(SlotFault::NoBootMessage))
+        })?)),
+        false => None,
+    };
+    let len = frame_len(len, payload.len())?;
+    let mut tx = vec![0u8; len];
+    let mut rx = vec![0u8; tx.len()];
+    tx[6..6 + payload.len()].copy_from_slice(&payload);
+    let result = module.send_receive_spi(
+        ModuleCommunicationDirection::ToModule,
+        module_id,
+        message_type.into(),
+        index,
+        &mut tx,
+        &mut rx,
+        len,
+        0,
+    );
+    let output = RawOutput {
+        bootmessage,
+        sent: hex(&tx[..len]),
+        received: hex(&rx[..len]),
+        error: result.err().map(|err| format!("{err:?}")),
+    };
+    print(json, &output, |output| {
+        if let Some(bootmessage) = &output.bootmessage {
+            println!("boot message: {bootmessage}");
+        }
+        println!("sent:         {}", output.sent);
+        println!("received:     {}", output.received);
+        if let Some(error) = &output.error {
+            println!("error:        {error}");
+        }
+    });
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+    let result = match cli.command {
+        Command::Detect => detect(json),
+        Command::Slots => slots(json),
+        Command::Reset { slot } => reset(json, slot),
+        Command::Read {
+            slot,
+            config,
+            watch,
+        } => read(json, slot, config, watch),
+        Command::Set {
+            slot,
+            channel,
+            value,
+            config,
+            hold_ms,
+            yes,
+        } => set(
+            json,
+            slot,
+            channel,
+            value,
+            config,
+            Duration::from_millis(hold_ms),
+            yes,
+        ),
+        Command::Raw {
+            slot,
+            escape,
+            module_id,
+            message_type,
+            index,
+            len,
+            payload,
+        } => raw(
+            json,
+            slot,
+            escape,
+            module_id,
+            message_type,
+            index,
+            len,
+            &payload,
+        ),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("goctl: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_a_raw_frame_in_the_length_byte() {
+        assert_eq!(frame_len(None, 3).unwrap(), 10);
+        assert_eq!(frame_len(Some(44), 3).unwrap(), 44);
+        assert_eq!(frame_len(Some(255), 0).unwrap(), 255);
+        assert!(matches!(
+            frame_len(Some(256), 0),
+            Err(GoctlError::FrameTooLong(256))
+        ));
+        assert!(matches!(
+            frame_len(None, 249),
+            Err(GoctlError::FrameTooLong(256))
+        ));
+    }
+}